@@ -2,3 +2,32 @@ use anchor_lang::prelude::*;
 
 #[constant]
 pub const SEED: &str = "anchor";
+
+// Fixed-point scale used for the treasury reward-per-share index.
+#[constant]
+pub const REWARD_PRECISION: u128 = 1_000_000_000_000;
+
+// Length of a spending window for newly created spending accounts (30 days).
+#[constant]
+pub const DEFAULT_SPEND_PERIOD_SECONDS: i64 = 30 * 24 * 60 * 60;
+
+// Decimals of the debt denomination: spending limits and outstanding spend are
+// held in micro-USD (1_000_000 = $1.00).
+#[constant]
+pub const USD_DECIMALS: u8 = 6;
+
+// Fixed-point scale of the treasury's cumulative borrow index (1.0).
+#[constant]
+pub const BORROW_INDEX_PRECISION: u128 = 1_000_000_000_000;
+
+// Seconds in the year the annual borrow rate is spread over.
+#[constant]
+pub const SECONDS_PER_YEAR: i64 = 365 * 24 * 60 * 60;
+
+// Highest annual borrow rate (in basis points) the admin can set.
+#[constant]
+pub const MAX_BORROW_RATE_BPS: u64 = 10_000;
+
+// Highest merchant fee (in basis points) the admin can set, globally or per merchant.
+#[constant]
+pub const MAX_MERCHANT_FEE_BPS: u64 = 1_000;
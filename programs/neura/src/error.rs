@@ -6,4 +6,68 @@ pub enum AmountError {
     InsufficientFunds,
     #[msg("The requested spend amount exceeds the remaining limit.")]
     SpendingLimitExceeded,
+    #[msg("There are no rewards to claim.")]
+    NoRewardsToClaim,
+    #[msg("There is no outstanding spend to repay.")]
+    NothingToRepay,
+    #[msg("The collateral left would not back the debt at the initial margin.")]
+    BelowInitialMargin,
+    #[msg("The account still holds a balance, stake, rewards or debt.")]
+    AccountNotEmpty,
+}
+
+#[error_code(offset = 6100)]
+pub enum ProtocolError {
+    #[msg("The protocol configuration parameters are invalid.")]
+    InvalidConfig,
+    #[msg("The signer is not the protocol admin.")]
+    Unauthorized,
+    #[msg("Rewards cannot be funded while nothing is staked.")]
+    NoStakers,
+    #[msg("The oracle price is zero or negative.")]
+    InvalidPrice,
+    #[msg("Arithmetic overflow while converting a price.")]
+    MathOverflow,
+    #[msg("The oracle confidence interval is too wide to act on.")]
+    PriceTooUncertain,
+    #[msg("The collateral accounts do not match the position.")]
+    InvalidCollateralAccounts,
+    #[msg("The vault holds fewer lamports than its recorded balance.")]
+    VaultOutOfSync,
+    #[msg("Checking a position for debt requires its spending account, the treasury and a price update.")]
+    MarginAccountsRequired,
+    #[msg("The position is healthy and cannot be liquidated.")]
+    PositionHealthy,
+    #[msg("The liquidation would not seize any collateral.")]
+    NothingToSeize,
+}
+
+#[error_code(offset = 6200)]
+pub enum PaymentError {
+    #[msg("The merchant does not accept payment in this asset.")]
+    AssetNotAccepted,
+    #[msg("The mandate parameters are invalid.")]
+    InvalidMandate,
+    #[msg("The payment mandate has expired.")]
+    MandateExpired,
+    #[msg("The charge exceeds the limits of the payment mandate.")]
+    MandateLimitExceeded,
+    #[msg("The refund exceeds the amount left on the payment.")]
+    RefundExceedsPayment,
+    #[msg("The order ID is empty or too long.")]
+    InvalidOrderId,
+    #[msg("The payment memo is too long.")]
+    MemoTooLong,
+    #[msg("The merchant name is too long.")]
+    MerchantNameTooLong,
+    #[msg("The merchant has not been verified.")]
+    MerchantNotVerified,
+    #[msg("The merchant is suspended.")]
+    MerchantSuspended,
+    #[msg("Token payments are refunded in their mint through refund_token.")]
+    TokenRefundUnsupported,
+    #[msg("The merchant still has open receipts or mandates.")]
+    MerchantHasOpenAccounts,
+    #[msg("The receipt can only be closed once fully refunded or past its retention period.")]
+    ReceiptStillRetained,
 }
@@ -0,0 +1,170 @@
+use anchor_lang::prelude::*;
+
+#[event]
+pub struct DebtRepaid {
+    pub user: Pubkey,
+    // Lamports taken from the user and added to the treasury.
+    pub lamports: u64,
+    // Debt cleared, in micro-USD.
+    pub amount_repaid: u64,
+    // Debt still outstanding after the repayment, in micro-USD.
+    pub remaining_debt: u64,
+}
+
+#[event]
+pub struct Deposited {
+    pub user: Pubkey,
+    pub amount: u64,
+    // Vault balance after the deposit.
+    pub vault_balance: u64,
+}
+
+#[event]
+pub struct Withdrawn {
+    pub user: Pubkey,
+    pub amount: u64,
+    // Vault balance after the withdrawal.
+    pub vault_balance: u64,
+}
+
+#[event]
+pub struct TokenDeposited {
+    pub user: Pubkey,
+    pub mint: Pubkey,
+    // Amount credited, in base units of `mint`.
+    pub amount: u64,
+    // Token vault balance after the deposit.
+    pub token_vault_balance: u64,
+}
+
+#[event]
+pub struct TokenWithdrawn {
+    pub user: Pubkey,
+    pub mint: Pubkey,
+    // Amount withdrawn, in base units of `mint`.
+    pub amount: u64,
+    // Token vault balance after the withdrawal.
+    pub token_vault_balance: u64,
+}
+
+#[event]
+pub struct Staked {
+    pub user: Pubkey,
+    pub amount: u64,
+    // The user's stake after the change.
+    pub staked_amount: u64,
+    // Stake pooled in the treasury after the change.
+    pub total_staked: u64,
+}
+
+#[event]
+pub struct Unstaked {
+    pub user: Pubkey,
+    pub amount: u64,
+    // The user's stake after the change.
+    pub staked_amount: u64,
+    // Stake pooled in the treasury after the change.
+    pub total_staked: u64,
+}
+
+#[event]
+pub struct RewardsClaimed {
+    pub user: Pubkey,
+    // Reward lamports paid out of the treasury.
+    pub amount: u64,
+}
+
+#[event]
+pub struct SpendingLimitUpdated {
+    pub user: Pubkey,
+    // New limit, in micro-USD.
+    pub spending_limit: u64,
+}
+
+#[event]
+pub struct WindowLimitUpdated {
+    pub user: Pubkey,
+    // New per-window limit, in micro-USD.
+    pub window_limit: u64,
+}
+
+#[event]
+pub struct SpendAuthorized {
+    pub user: Pubkey,
+    // Amount authorised, in micro-USD.
+    pub amount: u64,
+    // Outstanding spend after the authorisation, in micro-USD.
+    pub amount_spent: u64,
+    // Spend in the current window after the authorisation, in micro-USD.
+    pub window_spent: u64,
+}
+
+#[event]
+pub struct PaymentProcessed {
+    pub user: Pubkey,
+    pub merchant_account: Pubkey,
+    // The SPL mint paid in, or `None` for lamports.
+    pub mint: Option<Pubkey>,
+    // Amount paid, in lamports or base units of `mint`.
+    pub amount: u64,
+    // Part of `amount` kept by the protocol as a merchant fee.
+    pub fee: u64,
+    // Debt recorded for the payment, in micro-USD.
+    pub amount_usd: u64,
+    pub order_id: Option<String>,
+}
+
+#[event]
+pub struct PaymentRefunded {
+    pub user: Pubkey,
+    pub merchant_account: Pubkey,
+    pub receipt: Pubkey,
+    // Lamports, or base units of the receipt's mint, returned to the user.
+    pub amount: u64,
+    // Debt released, in micro-USD.
+    pub amount_usd: u64,
+}
+
+#[event]
+pub struct Liquidated {
+    pub user: Pubkey,
+    pub liquidator: Pubkey,
+    // Debt cleared, in micro-USD.
+    pub debt_repaid: u64,
+    // SOL taken from the user's stake and vault, bonus included.
+    pub lamports_seized: u64,
+    // Part of `lamports_seized` paid to the liquidator.
+    pub liquidator_bonus: u64,
+    // Mint of the SPL collateral seized once the SOL ran out, if any.
+    pub mint: Option<Pubkey>,
+    // Base units of that mint paid to the liquidator, bonus included.
+    pub tokens_seized: u64,
+}
+
+#[event]
+pub struct MerchantRegistered {
+    pub merchant_account: Pubkey,
+    pub authority: Pubkey,
+    pub name: String,
+    pub accepted_mint: Option<Pubkey>,
+}
+
+#[event]
+pub struct VaultSynced {
+    pub user: Pubkey,
+    // Balance recorded before the sync.
+    pub previous_balance: u64,
+    // Lamports the vault holds above its rent-exempt minimum.
+    pub balance: u64,
+}
+
+#[event]
+pub struct PositionsClosed {
+    pub user: Pubkey,
+    // Vault balance returned with the vault's rent.
+    pub vault_balance: u64,
+    // Stake returned from the treasury.
+    pub staked_amount: u64,
+    // Rewards paid out with the stake.
+    pub rewards: u64,
+}
@@ -0,0 +1,302 @@
+use anchor_lang::prelude::*;
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+
+use crate::{
+    error::{AmountError, ProtocolError},
+    pricing::{AssetPrice, SOL_DECIMALS},
+    CollateralAsset, ProtocolConfig, TokenVault, Vault, YieldAccount,
+};
+
+// Risk-weighted value of a position's collateral, in micro-USD.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CollateralValue {
+    // Sum of value * collateral factor: how much debt the position may take on.
+    pub borrow_capacity: u128,
+    // Sum of value * liquidation threshold: debt above this can be liquidated.
+    pub liquidation_value: u128,
+}
+
+impl CollateralValue {
+    pub fn add(&mut self, value_usd: u128, collateral_factor_bps: u64, liquidation_threshold_bps: u64) -> Result<()> {
+        self.borrow_capacity = self.borrow_capacity
+            .checked_add(weight(value_usd, collateral_factor_bps as u128)?)
+            .ok_or(ProtocolError::MathOverflow)?;
+        self.liquidation_value = self.liquidation_value
+            .checked_add(weight(value_usd, liquidation_threshold_bps as u128)?)
+            .ok_or(ProtocolError::MathOverflow)?;
+        Ok(())
+    }
+
+    // SOL is weighted by the protocol-wide parameters. Its liquidation threshold is a
+    // collateral ratio in percent, so the equivalent weight is 100 / ratio.
+    pub fn add_sol(&mut self, value_usd: u128, config: &ProtocolConfig) -> Result<()> {
+        let liquidation_threshold_bps = 10_000 * 100 / config.liquidation_threshold;
+        self.add(value_usd, config.loan_to_value_bps, liquidation_threshold_bps)
+    }
+}
+
+// Snapshot of a position returned by `get_position_health`. USD amounts are in micro-USD.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PositionHealth {
+    // Collateral counted by `liquidate`: SOL in the vault and staked, plus the
+    // SOL-equivalent of SPL collateral.
+    pub collateral_value: u64,
+    // Outstanding spend including interest accrued up to now.
+    pub debt: u64,
+    // `collateral_value / debt` in percent, comparable to the config's
+    // `liquidation_threshold`. `u64::MAX` when there is no debt.
+    pub collateral_ratio: u64,
+    // Further spend the current collateral supports, as `update_spending_limit` would set it.
+    pub max_borrowable: u64,
+    // SOL price (micro-USD per SOL, at the collateral bound) below which the position
+    // can be liquidated. Zero when no SOL price would make it liquidatable.
+    pub liquidation_price: u64,
+}
+
+impl PositionHealth {
+    // `sol_lamports` is the SOL backing the position, in the vault and staked.
+    pub fn new(
+        debt: u64,
+        sol_lamports: u64,
+        tokens: CollateralValue,
+        sol_price: &AssetPrice,
+        config: &ProtocolConfig,
+    ) -> Result<Self> {
+        let liquidation_threshold = config.liquidation_threshold as u128;
+
+        let sol_in_usd = sol_price.collateral_value_usd(sol_lamports)?;
+        let collateral_value = sol_in_usd
+            .checked_add(weight(tokens.liquidation_value, liquidation_threshold * 100)?)
+            .ok_or(ProtocolError::MathOverflow)?;
+
+        let collateral_ratio = if debt == 0 {
+            u64::MAX
+        } else {
+            u64::try_from(collateral_value.checked_mul(100).ok_or(ProtocolError::MathOverflow)? / debt as u128)
+                .unwrap_or(u64::MAX)
+        };
+
+        let mut capacity = tokens;
+        capacity.add_sol(sol_in_usd, config)?;
+        let max_borrowable = capacity.borrow_capacity.saturating_sub(debt as u128);
+
+        // The ratio drops under the threshold once the SOL is worth less than the
+        // debt not already covered by weighted token collateral.
+        let uncovered_debt = (debt as u128).saturating_sub(tokens.liquidation_value);
+        let liquidation_price = if sol_lamports == 0 || uncovered_debt == 0 {
+            0
+        } else {
+            weight(uncovered_debt, liquidation_threshold * 100)?
+                .checked_mul(10u128.pow(SOL_DECIMALS as u32))
+                .ok_or(ProtocolError::MathOverflow)?
+                / sol_lamports as u128
+        };
+
+        // Values beyond u64 are reported as u64::MAX rather than failing the query.
+        Ok(Self {
+            collateral_value: u64::try_from(collateral_value).unwrap_or(u64::MAX),
+            debt,
+            collateral_ratio,
+            max_borrowable: u64::try_from(max_borrowable).unwrap_or(u64::MAX),
+            liquidation_price: u64::try_from(liquidation_price).unwrap_or(u64::MAX),
+        })
+    }
+}
+
+fn weight(value_usd: u128, bps: u128) -> Result<u128> {
+    Ok(value_usd.checked_mul(bps).ok_or(ProtocolError::MathOverflow)? / 10_000)
+}
+
+// SOL counted as collateral by every rule in the program: the vault balance plus
+// stake, both of which `liquidate` can seize. `debit` is SOL leaving the vault in
+// the current instruction that its balance does not show yet.
+pub fn sol_collateral(vault: &Vault, yield_account: Option<&YieldAccount>, debit: u64) -> Result<u64> {
+    let staked_amount = yield_account.map_or(0, |y| y.staked_amount);
+    let vault_lamports = vault.balance.checked_sub(debit).ok_or(AmountError::InsufficientFunds)?;
+    Ok(vault_lamports.checked_add(staked_amount).ok_or(ProtocolError::MathOverflow)?)
+}
+
+// The initial-margin rule every instruction that takes collateral out of a position
+// applies: the debt must still fit within the borrow capacity of what is left, the
+// same margin `update_spending_limit` grants new spend against. `sol_lamports` is the
+// SOL left counting as collateral, from `sol_collateral`, and `token_debit` a `(mint, amount)` leaving that
+// mint's token vault in this instruction, which the stored balance does not show yet.
+// Positions without debt always pass, without loading any prices.
+pub fn require_initial_margin(
+    debt: u64,
+    sol_lamports: u64,
+    remaining_accounts: &[AccountInfo],
+    vault: &Vault,
+    price_update: &PriceUpdateV2,
+    config: &ProtocolConfig,
+    token_debit: Option<(Pubkey, u64)>,
+) -> Result<()> {
+    if debt == 0 {
+        return Ok(());
+    }
+
+    let sol_price = AssetPrice::load_sol_usd(price_update, config)?;
+    let mut collateral = token_collateral_value_after(remaining_accounts, vault, config, token_debit)?;
+    collateral.add_sol(sol_price.collateral_value_usd(sol_lamports)?, config)?;
+
+    require!(debt as u128 <= collateral.borrow_capacity, AmountError::BelowInitialMargin);
+    Ok(())
+}
+
+// Values the SPL collateral of `vault`'s owner passed as remaining accounts, in
+// `[CollateralAsset, TokenVault, PriceUpdateV2]` triples, one per mint. Every token
+// vault the owner has opened must be included, so a caller cannot undervalue the
+// position by leaving some out.
+pub fn token_collateral_value(
+    remaining_accounts: &[AccountInfo],
+    vault: &Vault,
+    config: &ProtocolConfig,
+) -> Result<CollateralValue> {
+    token_collateral_value_after(remaining_accounts, vault, config, None)
+}
+
+// Price of `mint` out of the collateral triples in `remaining_accounts`, which
+// `token_collateral_value` has already checked.
+pub fn token_price(
+    remaining_accounts: &[AccountInfo],
+    mint: &Pubkey,
+    config: &ProtocolConfig,
+) -> Result<AssetPrice> {
+    for accounts in remaining_accounts.chunks_exact(3) {
+        let asset: CollateralAsset = load(&accounts[0])?;
+        if asset.mint == *mint {
+            let price_update: PriceUpdateV2 = load(&accounts[2])?;
+            return AssetPrice::load(&price_update, &asset.price_feed_id, asset.decimals, config);
+        }
+    }
+    err!(ProtocolError::InvalidCollateralAccounts)
+}
+
+// Like `token_collateral_value`, with `token_debit`'s amount taken off its mint's balance.
+fn token_collateral_value_after(
+    remaining_accounts: &[AccountInfo],
+    vault: &Vault,
+    config: &ProtocolConfig,
+    token_debit: Option<(Pubkey, u64)>,
+) -> Result<CollateralValue> {
+    require!(
+        remaining_accounts.len() == 3 * vault.token_vault_count as usize,
+        ProtocolError::InvalidCollateralAccounts
+    );
+
+    let mut total = CollateralValue::default();
+    let mut seen_mints: Vec<Pubkey> = Vec::new();
+
+    for accounts in remaining_accounts.chunks_exact(3) {
+        let asset: CollateralAsset = load(&accounts[0])?;
+        let token_vault: TokenVault = load(&accounts[1])?;
+        let price_update: PriceUpdateV2 = load(&accounts[2])?;
+
+        require_keys_eq!(token_vault.authority, vault.authority, ProtocolError::InvalidCollateralAccounts);
+        require_keys_eq!(token_vault.mint, asset.mint, ProtocolError::InvalidCollateralAccounts);
+        // Counting the same vault twice would inflate the position.
+        require!(!seen_mints.contains(&asset.mint), ProtocolError::InvalidCollateralAccounts);
+        seen_mints.push(asset.mint);
+
+        let balance = match token_debit {
+            Some((mint, amount)) if mint == asset.mint => token_vault.balance.saturating_sub(amount),
+            _ => token_vault.balance,
+        };
+
+        let price = AssetPrice::load(&price_update, &asset.price_feed_id, asset.decimals, config)?;
+        let value = price.collateral_value_usd(balance)?;
+        total.add(value, asset.collateral_factor_bps, asset.liquidation_threshold_bps)?;
+    }
+
+    Ok(total)
+}
+
+// Remaining accounts are not checked by Anchor, so verify the owner before
+// trusting the discriminator.
+fn load<T: AccountDeserialize + Owner>(info: &AccountInfo) -> Result<T> {
+    require_keys_eq!(*info.owner, T::owner(), ErrorCode::AccountOwnedByWrongProgram);
+    T::try_deserialize(&mut &info.try_borrow_data()?[..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOL: u64 = 1_000_000_000;
+
+    fn sol_at(dollars: i64) -> AssetPrice {
+        AssetPrice::from_oracle(dollars * 100_000_000, 0, -8, SOL_DECIMALS, &ProtocolConfig::test_default()).unwrap()
+    }
+
+    #[test]
+    fn reports_ratio_capacity_and_liquidation_price() {
+        // 12 SOL at $150, with $600 spent.
+        let health = PositionHealth::new(
+            600_000_000, 12 * SOL, CollateralValue::default(), &sol_at(150), &ProtocolConfig::test_default(),
+        ).unwrap();
+
+        assert_eq!(health.collateral_value, 1_800_000_000);
+        assert_eq!(health.collateral_ratio, 300);
+        // Half of the $1800 of SOL, less what is already spent.
+        assert_eq!(health.max_borrowable, 300_000_000);
+        // 12 SOL at $60 cover $600 at exactly 120%.
+        assert_eq!(health.liquidation_price, 60_000_000);
+
+        // At that price the ratio sits on the threshold, just below it liquidation starts.
+        let at_threshold = PositionHealth::new(
+            600_000_000, 10 * SOL, CollateralValue::default(), &sol_at(72), &ProtocolConfig::test_default(),
+        ).unwrap();
+        assert_eq!(at_threshold.collateral_ratio, 120);
+    }
+
+    #[test]
+    fn token_collateral_lowers_the_liquidation_price() {
+        let mut tokens = CollateralValue::default();
+        tokens.add(375_000_000, 7_500, 8_000).unwrap();
+
+        // $300 of the debt is covered by weighted tokens, so 10 SOL only needs to
+        // cover the other $300 at 120%.
+        let health = PositionHealth::new(
+            600_000_000, 10 * SOL, tokens, &sol_at(150), &ProtocolConfig::test_default(),
+        ).unwrap();
+        assert_eq!(health.collateral_value, 1_860_000_000);
+        assert_eq!(health.liquidation_price, 36_000_000);
+    }
+
+    #[test]
+    fn position_without_debt_cannot_be_liquidated() {
+        let health = PositionHealth::new(
+            0, 10 * SOL, CollateralValue::default(), &sol_at(150), &ProtocolConfig::test_default(),
+        ).unwrap();
+        assert_eq!(health.collateral_ratio, u64::MAX);
+        assert_eq!(health.liquidation_price, 0);
+        assert_eq!(health.max_borrowable, 750_000_000);
+    }
+
+    #[test]
+    fn sol_collateral_counts_vault_and_stake_less_the_debit() {
+        let vault = Vault { authority: Pubkey::default(), balance: 2 * SOL, token_vault_count: 0, has_spending_account: false, bump: 0 };
+        let yield_account = YieldAccount {
+            authority: Pubkey::default(), staked_amount: 3 * SOL, last_index: 0, pending_rewards: 0, bump: 0,
+        };
+
+        assert_eq!(sol_collateral(&vault, Some(&yield_account), SOL).unwrap(), 4 * SOL);
+        assert_eq!(sol_collateral(&vault, None, 0).unwrap(), 2 * SOL);
+        // Stake cannot make up for a debit larger than the vault balance.
+        assert!(sol_collateral(&vault, Some(&yield_account), 3 * SOL).is_err());
+    }
+
+    #[test]
+    fn saturates_values_that_do_not_fit_in_u64() {
+        // A token position worth more than u64::MAX micro-USD.
+        let mut tokens = CollateralValue::default();
+        tokens.add(u64::MAX as u128 * 4, 10_000, 10_000).unwrap();
+
+        let health = PositionHealth::new(
+            1, 0, tokens, &sol_at(150), &ProtocolConfig::test_default(),
+        ).unwrap();
+        assert_eq!(health.collateral_value, u64::MAX);
+        assert_eq!(health.max_borrowable, u64::MAX);
+        assert_eq!(health.collateral_ratio, u64::MAX);
+    }
+}
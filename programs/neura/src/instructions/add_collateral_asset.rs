@@ -0,0 +1,50 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::Mint;
+
+use crate::{error::ProtocolError, CollateralAsset, CollateralAssetParams, ProtocolConfig};
+
+#[derive(Accounts)]
+pub struct AddCollateralAsset<'info> {
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        has_one = admin @ ProtocolError::Unauthorized,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + CollateralAsset::INIT_SPACE,
+        seeds = [b"collateral", mint.key().as_ref()],
+        bump
+    )]
+    pub collateral_asset: Account<'info, CollateralAsset>,
+
+    pub system_program: Program<'info, System>,
+}
+
+impl <'info> AddCollateralAsset <'info> {
+
+    pub fn add_collateral_asset(&mut self, params: CollateralAssetParams, bumps: &AddCollateralAssetBumps) -> Result<()>{
+
+        params.validate()?;
+
+        self.collateral_asset.set_inner(CollateralAsset {
+            mint: self.mint.key(),
+            price_feed_id: params.price_feed_id,
+            decimals: self.mint.decimals,
+            collateral_factor_bps: params.collateral_factor_bps,
+            liquidation_threshold_bps: params.liquidation_threshold_bps,
+            bump: bumps.collateral_asset,
+        });
+
+        Ok(())
+    }
+}
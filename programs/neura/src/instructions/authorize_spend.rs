@@ -1,16 +1,39 @@
 use anchor_lang::prelude::*;
-use crate::state::SpendingAccount;
-use crate::error::AmountError;
-
-pub fn authorize_spend(ctx: Context<AuthorizeSpend>, amount: u64) -> Result<()> {
-    ctx.accounts.authorize(amount)
-}
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+use crate::events::SpendAuthorized;
+use crate::health::{require_initial_margin, sol_collateral};
+use crate::state::{ProtocolConfig, SpendingAccount, Treasury, Vault, YieldAccount};
 
 #[derive(Accounts)]
 pub struct AuthorizeSpend<'info> {
 
     pub authority: Signer<'info>,
 
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    // The collateral backing the spend, re-priced on every spend as the stored
+    // limit is only a snapshot.
+    #[account(
+        has_one = authority,
+        seeds = [b"vault", authority.key().as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    // Users who never staked can omit it.
+    #[account(
+        has_one = authority,
+        seeds = [b"yield", authority.key().as_ref()],
+        bump = yield_account.bump
+    )]
+    pub yield_account: Option<Account<'info, YieldAccount>>,
+
+    pub price_update: Account<'info, PriceUpdateV2>,
+
     #[account(
         mut,
         has_one = authority,
@@ -18,24 +41,42 @@ pub struct AuthorizeSpend<'info> {
         bump = spending_account.bump
     )]
     pub spending_account: Account<'info, SpendingAccount>,
+
+    // Holds the borrow index that interest on the spend is accrued against.
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = treasury.bump
+    )]
+    pub treasury: Account<'info, Treasury>,
 }
 
 impl<'info> AuthorizeSpend<'info> {
-    pub fn authorize(&mut self, amount: u64) -> Result<()> {
-        
-        let available_to_spend = self.spending_account.spending_limit
-            .checked_sub(self.spending_account.amount_spent)
-            .unwrap();
-
-        
-        require!(
-            amount <= available_to_spend,
-            AmountError::SpendingLimitExceeded
-        );
-
-        self.spending_account.amount_spent = self.spending_account.amount_spent
-            .checked_add(amount)
-            .unwrap();
+    // `remaining_accounts` carries any SPL collateral, see `token_collateral_value`.
+    pub fn authorize(&mut self, amount: u64, remaining_accounts: &[AccountInfo]) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let borrow_index = self.treasury.accrue_interest(now, self.config.borrow_rate_bps);
+        self.spending_account.accrue_interest(borrow_index);
+        self.spending_account.record_spend(amount, now)?;
+
+        // The limit was set at an earlier price, so the collateral must still back
+        // the debt at the current one.
+        require_initial_margin(
+            self.spending_account.amount_spent,
+            sol_collateral(&self.vault, self.yield_account.as_deref(), 0)?,
+            remaining_accounts,
+            &self.vault,
+            &self.price_update,
+            &self.config,
+            None,
+        )?;
+
+        emit!(SpendAuthorized {
+            user: self.authority.key(),
+            amount,
+            amount_spent: self.spending_account.amount_spent,
+            window_spent: self.spending_account.window_spent,
+        });
 
         Ok(())
     }
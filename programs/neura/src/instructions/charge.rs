@@ -0,0 +1,176 @@
+use anchor_lang::prelude::*;
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+
+use crate::error::PaymentError;
+use crate::events::PaymentProcessed;
+use crate::health::{require_initial_margin, sol_collateral};
+use crate::pricing::AssetPrice;
+use crate::state::{debit_vault, MerchantAccount, PaymentMandate, PaymentReceipt, PaymentSource, ProtocolConfig, SpendingAccount, Treasury, Vault, YieldAccount};
+
+#[derive(Accounts)]
+#[instruction(amount: u64, nonce: u64)]
+pub struct Charge<'info> {
+    // The merchant pulling the payment. It pays for the receipt, as the user does not sign.
+    #[account(mut)]
+    pub merchant: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"merchant", merchant.key().as_ref()],
+        bump = merchant_account.bump,
+        constraint = merchant_account.accepted_mint.is_none() @ PaymentError::AssetNotAccepted,
+        constraint = merchant_account.verified @ PaymentError::MerchantNotVerified,
+        constraint = !merchant_account.suspended @ PaymentError::MerchantSuspended
+    )]
+    pub merchant_account: Account<'info, MerchantAccount>,
+
+    /// CHECK: The wallet the lamports are paid to, validated by the address constraint.
+    #[account(
+        mut,
+        address = merchant_account.payout_wallet
+    )]
+    pub payout_wallet: AccountInfo<'info>,
+
+    /// CHECK: The user being charged. Only its key is used to derive their PDAs.
+    pub user: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    // The SOL/USD price used to convert the lamports charged into debt.
+    pub price_update: Account<'info, PriceUpdateV2>,
+
+    #[account(
+        mut,
+        has_one = merchant_account,
+        seeds = [b"mandate", user.key().as_ref(), merchant_account.key().as_ref()],
+        bump = mandate.bump
+    )]
+    pub mandate: Account<'info, PaymentMandate>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    // Staked funds also back the debt. Users who never staked can omit it.
+    #[account(
+        seeds = [b"yield", user.key().as_ref()],
+        bump = yield_account.bump
+    )]
+    pub yield_account: Option<Account<'info, YieldAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"spending", user.key().as_ref()],
+        bump = spending_account.bump
+    )]
+    pub spending_account: Account<'info, SpendingAccount>,
+
+    // Receives the protocol's merchant fee.
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = treasury.bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    // Proof of the charge, keyed like a payment without an order ID with a nonce
+    // the merchant picks, so the user can have it refunded.
+    #[account(
+        init,
+        payer = merchant,
+        space = 8 + PaymentReceipt::INIT_SPACE,
+        seeds = [
+            b"receipt",
+            merchant_account.key().as_ref(),
+            &PaymentReceipt::seed(None, &user.key(), nonce)
+        ],
+        bump
+    )]
+    pub receipt: Account<'info, PaymentReceipt>,
+
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> Charge<'info> {
+    // `remaining_accounts` carries the user's SPL collateral, see `token_collateral_value`.
+    pub fn charge(
+        &mut self,
+        amount: u64,
+        nonce: u64,
+        bumps: &ChargeBumps,
+        remaining_accounts: &[AccountInfo],
+    ) -> Result<()> {
+        // --- 1. Authorize the Spend ---
+        // The charge must fit both the mandate and the user's own spending limit.
+        let sol_price = AssetPrice::load_sol_usd(&self.price_update, &self.config)?;
+        let amount_in_usd = u64::try_from(sol_price.debt_value_usd(amount)?).unwrap();
+
+        let now = Clock::get()?.unix_timestamp;
+        let borrow_index = self.treasury.accrue_interest(now, self.config.borrow_rate_bps);
+        self.spending_account.accrue_interest(borrow_index);
+        self.mandate.record_charge(amount_in_usd, now)?;
+        self.spending_account.record_spend(amount_in_usd, now)?;
+
+        msg!("Charge of {} micro-USD authorized.", amount_in_usd);
+
+        // --- 2. Pay the Merchant ---
+        // The protocol fee is split off and the merchant receives the rest.
+        let fee = self.merchant_account.fee_for(amount, &self.config);
+        debit_vault(&mut self.vault, &self.payout_wallet, amount.checked_sub(fee).unwrap())?;
+        debit_vault(&mut self.vault, &self.treasury.to_account_info(), fee)?;
+        self.treasury.collect_fee(fee);
+
+        // The vault is collateral, so what is left must still back the debt,
+        // this charge included, at the initial margin.
+        require_initial_margin(
+            self.spending_account.amount_spent,
+            sol_collateral(&self.vault, self.yield_account.as_deref(), 0)?,
+            remaining_accounts,
+            &self.vault,
+            &self.price_update,
+            &self.config,
+            None,
+        )?;
+
+        // --- 3. Issue the Receipt ---
+        let merchant_account = self.merchant_account.key();
+        PaymentReceipt::issue(&mut self.receipt, &mut self.merchant_account, PaymentReceipt {
+            payer: self.user.key(),
+            merchant_account,
+            source: PaymentSource::Vault,
+            mint: None,
+            amount,
+            fee,
+            amount_usd: amount_in_usd,
+            refunded: 0,
+            refunded_usd: 0,
+            created_at: now,
+            order_id: None,
+            nonce,
+            memo: None,
+            rent_payer: self.merchant.key(),
+            bump: bumps.receipt,
+        });
+
+        msg!("Merchant {} charged {} lamports, {} of them as fee.", self.merchant_account.name, amount, fee);
+
+        emit!(PaymentProcessed {
+            user: self.user.key(),
+            merchant_account: self.merchant_account.key(),
+            mint: None,
+            amount,
+            fee,
+            amount_usd: amount_in_usd,
+            order_id: None,
+        });
+
+        Ok(())
+    }
+}
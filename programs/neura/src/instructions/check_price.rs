@@ -1,8 +1,16 @@
 use anchor_lang::prelude::*;
-use pyth_solana_receiver_sdk::price_update::{get_feed_id_from_hex, PriceUpdateV2};
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+
+use crate::ProtocolConfig;
  
 #[derive(Accounts)]
 pub struct CheckPrice<'info> {
+
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
     
     pub price_update: Account<'info, PriceUpdateV2>,
 }
@@ -13,14 +21,9 @@ impl <'info> CheckPrice <'info> {
 
     let price_update = &self.price_update;
 
-    // get_price_no_older_than will fail if the price update is more than 30 seconds old
-    let maximum_age: u64 = 60;
-
-    // get_price_no_older_than will fail if the price update is for a different price feed.
-    // This string is the id of the BTC/USD feed. See https://docs.pyth.network/price-feeds/price-feeds for all available IDs.
-    let feed_id: [u8; 32] = get_feed_id_from_hex("0xef0d8b614545449452e9f8d4623e34ade2ba2ac67362100e27457bf6fc8894c4")?;
-
-    let price = price_update.get_price_no_older_than(&Clock::get()?, maximum_age, &feed_id)?;
+    // get_price_no_older_than will fail if the price update is older than the configured maximum age,
+    // or if it is for a different price feed than the one stored in the protocol config.
+    let price = price_update.get_price_no_older_than(&Clock::get()?, self.config.maximum_age, &self.config.sol_usd_feed_id)?;
     
     // Sample output:
     // The price is (7160106530699 ± 5129162301) * 10^-8
@@ -28,4 +31,4 @@ impl <'info> CheckPrice <'info> {
  
     Ok(())
     }
-}
\ No newline at end of file
+}
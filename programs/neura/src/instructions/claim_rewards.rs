@@ -0,0 +1,59 @@
+use anchor_lang::prelude::*;
+use crate::state::{Treasury, YieldAccount};
+use crate::error::AmountError;
+use crate::events::RewardsClaimed;
+
+#[derive(Accounts)]
+pub struct ClaimRewards<'info> {
+    // The staker claiming their rewards. Rewards are paid to this wallet.
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = treasury.bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [b"yield", authority.key().as_ref()],
+        bump = yield_account.bump
+    )]
+    pub yield_account: Account<'info, YieldAccount>,
+}
+
+impl<'info> ClaimRewards<'info> {
+    pub fn claim_rewards(&mut self) -> Result<()> {
+        self.yield_account.settle_rewards(self.treasury.reward_per_share)?;
+
+        let amount = self.yield_account.pending_rewards;
+        require!(amount > 0, AmountError::NoRewardsToClaim);
+
+        // --- Manual Lamport Transfer ---
+        // Debit from the treasury.
+        let treasury_lamports = self.treasury.to_account_info().lamports();
+        **self.treasury.to_account_info().try_borrow_mut_lamports()? = treasury_lamports
+            .checked_sub(amount)
+            .ok_or(AmountError::InsufficientFunds)?;
+
+        // Credit to the staker.
+        let authority_lamports = self.authority.to_account_info().lamports();
+        **self.authority.to_account_info().try_borrow_mut_lamports()? = authority_lamports
+            .checked_add(amount)
+            .unwrap();
+
+        // --- Update State ---
+        self.yield_account.pending_rewards = 0;
+        self.treasury.unclaimed_rewards = self.treasury.unclaimed_rewards.checked_sub(amount).unwrap();
+
+        emit!(RewardsClaimed {
+            user: self.authority.key(),
+            amount,
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,130 @@
+use anchor_lang::prelude::*;
+use crate::error::{AmountError, PaymentError, ProtocolError};
+use crate::events::PositionsClosed;
+use crate::state::{MerchantAccount, ProtocolConfig, SpendingAccount, Treasury, Vault, YieldAccount};
+
+#[derive(Accounts)]
+pub struct CloseAllPositions<'info> {
+    // Everything is paid out to this wallet.
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    // Closing returns the vault balance along with the rent. Token vaults have
+    // to be closed first with `close_token_vault`. Mandates the user granted are
+    // not closed here: without a spending account they can no longer be charged,
+    // and `revoke_mandate` still returns their rent afterwards.
+    #[account(
+        mut,
+        close = authority,
+        has_one = authority,
+        seeds = [b"vault", authority.key().as_ref()],
+        bump = vault.bump,
+        constraint = vault.token_vault_count == 0 @ AmountError::AccountNotEmpty
+    )]
+    pub vault: Account<'info, Vault>,
+
+    // Users who never opened one can omit it. Once the vault records one, it has
+    // to be passed so collateral cannot be taken out from under outstanding spend.
+    #[account(
+        mut,
+        close = authority,
+        has_one = authority,
+        seeds = [b"spending", authority.key().as_ref()],
+        bump = spending_account.bump
+    )]
+    pub spending_account: Option<Account<'info, SpendingAccount>>,
+
+    // Users who never staked can omit it.
+    #[account(
+        mut,
+        close = authority,
+        has_one = authority,
+        seeds = [b"yield", authority.key().as_ref()],
+        bump = yield_account.bump
+    )]
+    pub yield_account: Option<Account<'info, YieldAccount>>,
+
+    // Only needed by users who also registered as a merchant, once its receipts
+    // and mandates are closed.
+    #[account(
+        mut,
+        close = authority,
+        has_one = authority,
+        constraint = merchant_account.open_receipts == 0 && merchant_account.open_mandates == 0
+            @ PaymentError::MerchantHasOpenAccounts,
+        seeds = [b"merchant", authority.key().as_ref()],
+        bump = merchant_account.bump
+    )]
+    pub merchant_account: Option<Account<'info, MerchantAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = treasury.bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+}
+
+impl<'info> CloseAllPositions<'info> {
+    pub fn close_all_positions(&mut self) -> Result<()> {
+        // --- 1. Check the Debt ---
+        // Spend has to be repaid before the collateral behind it is released.
+        match self.spending_account.as_mut() {
+            Some(spending_account) => {
+                let now = Clock::get()?.unix_timestamp;
+                let borrow_index = self.treasury.accrue_interest(now, self.config.borrow_rate_bps);
+                spending_account.accrue_interest(borrow_index);
+                require!(spending_account.amount_spent == 0, AmountError::AccountNotEmpty);
+            }
+            None => require!(!self.vault.has_spending_account, ProtocolError::MarginAccountsRequired),
+        }
+
+        // --- 2. Return Stake and Rewards ---
+        let (staked_amount, rewards) = match self.yield_account.as_mut() {
+            Some(yield_account) => {
+                yield_account.settle_rewards(self.treasury.reward_per_share)?;
+                (yield_account.staked_amount, yield_account.pending_rewards)
+            }
+            None => (0, 0),
+        };
+        let payout = staked_amount.checked_add(rewards).unwrap();
+
+        let treasury_lamports = self.treasury.to_account_info().lamports();
+        **self.treasury.to_account_info().try_borrow_mut_lamports()? = treasury_lamports
+            .checked_sub(payout)
+            .ok_or(AmountError::InsufficientFunds)?;
+
+        let authority_lamports = self.authority.to_account_info().lamports();
+        **self.authority.to_account_info().try_borrow_mut_lamports()? = authority_lamports
+            .checked_add(payout)
+            .unwrap();
+
+        self.treasury.total_staked = self.treasury.total_staked.checked_sub(staked_amount).unwrap();
+        self.treasury.unclaimed_rewards = self.treasury.unclaimed_rewards.checked_sub(rewards).unwrap();
+        if self.spending_account.is_some() {
+            self.treasury.open_spending_accounts = self.treasury.open_spending_accounts.checked_sub(1).unwrap();
+        }
+        if self.yield_account.is_some() {
+            self.treasury.open_yield_accounts = self.treasury.open_yield_accounts.checked_sub(1).unwrap();
+        }
+
+        // --- 3. Close the Accounts ---
+        // The `close` constraints hand every account's lamports to the authority on exit.
+        msg!("Closed all positions of {}, returning {} staked and {} reward lamports.", self.authority.key(), staked_amount, rewards);
+
+        emit!(PositionsClosed {
+            user: self.authority.key(),
+            vault_balance: self.vault.balance,
+            staked_amount,
+            rewards,
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,30 @@
+use anchor_lang::prelude::*;
+use crate::error::PaymentError;
+use crate::state::MerchantAccount;
+
+#[derive(Accounts)]
+pub struct CloseMerchant<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    // Closing the account returns its rent to the merchant. Its receipts and
+    // mandates must be closed first, or a merchant re-created at the same address
+    // would reuse their seeds.
+    #[account(
+        mut,
+        close = authority,
+        has_one = authority,
+        constraint = merchant_account.open_receipts == 0 && merchant_account.open_mandates == 0
+            @ PaymentError::MerchantHasOpenAccounts,
+        seeds = [b"merchant", authority.key().as_ref()],
+        bump = merchant_account.bump
+    )]
+    pub merchant_account: Account<'info, MerchantAccount>,
+}
+
+impl<'info> CloseMerchant<'info> {
+    pub fn close_merchant(&mut self) -> Result<()> {
+        msg!("Merchant {} closed.", self.merchant_account.name);
+        Ok(())
+    }
+}
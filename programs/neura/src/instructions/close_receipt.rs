@@ -0,0 +1,43 @@
+use anchor_lang::prelude::*;
+
+use crate::error::PaymentError;
+use crate::state::{MerchantAccount, PaymentReceipt};
+
+#[derive(Accounts)]
+pub struct CloseReceipt<'info> {
+    // Only the merchant. Closing the receipt ends any refunds on it and frees its
+    // order ID to be paid again.
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"merchant", authority.key().as_ref()],
+        bump = merchant_account.bump
+    )]
+    pub merchant_account: Account<'info, MerchantAccount>,
+
+    // The rent goes back to whoever funded the receipt.
+    #[account(
+        mut,
+        close = rent_payer,
+        has_one = merchant_account,
+        has_one = rent_payer
+    )]
+    pub receipt: Account<'info, PaymentReceipt>,
+
+    /// CHECK: The account that funded the receipt, validated against the receipt.
+    #[account(mut)]
+    pub rent_payer: AccountInfo<'info>,
+}
+
+impl<'info> CloseReceipt<'info> {
+    pub fn close_receipt(&mut self) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        require!(self.receipt.closable(now), PaymentError::ReceiptStillRetained);
+
+        self.merchant_account.open_receipts = self.merchant_account.open_receipts.checked_sub(1).unwrap();
+
+        msg!("Receipt {} closed.", self.receipt.key());
+        Ok(())
+    }
+}
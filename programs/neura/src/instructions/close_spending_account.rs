@@ -0,0 +1,45 @@
+use anchor_lang::prelude::*;
+use crate::error::AmountError;
+use crate::state::{SpendingAccount, Treasury, Vault};
+
+#[derive(Accounts)]
+pub struct CloseSpendingAccount<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    // Interest only grows a nonzero debt, so no accrual is needed to check it.
+    #[account(
+        mut,
+        close = authority,
+        has_one = authority,
+        seeds = [b"spending", authority.key().as_ref()],
+        bump = spending_account.bump,
+        constraint = spending_account.amount_spent == 0 @ AmountError::AccountNotEmpty
+    )]
+    pub spending_account: Account<'info, SpendingAccount>,
+
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [b"vault", authority.key().as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = treasury.bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+}
+
+impl<'info> CloseSpendingAccount<'info> {
+    pub fn close_spending_account(&mut self) -> Result<()> {
+        self.vault.has_spending_account = false;
+        self.treasury.open_spending_accounts = self.treasury.open_spending_accounts.checked_sub(1).unwrap();
+
+        msg!("Spending account of {} closed.", self.authority.key());
+        Ok(())
+    }
+}
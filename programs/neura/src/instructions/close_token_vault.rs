@@ -0,0 +1,90 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{
+    close_account, transfer_checked, CloseAccount, Mint, TokenAccount, TokenInterface, TransferChecked,
+};
+
+use crate::error::AmountError;
+use crate::state::{TokenVault, Vault};
+
+#[derive(Accounts)]
+pub struct CloseTokenVault<'info> {
+    // Receives the rent of both closed accounts.
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [b"vault", authority.key().as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    // Withdraw the tokens first: only an empty token vault can be closed.
+    #[account(
+        mut,
+        close = authority,
+        has_one = authority,
+        has_one = mint,
+        seeds = [b"token_vault", authority.key().as_ref(), mint.key().as_ref()],
+        bump = token_vault.bump,
+        constraint = token_vault.balance == 0 @ AmountError::AccountNotEmpty
+    )]
+    pub token_vault: Account<'info, TokenVault>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault,
+        associated_token::token_program = token_program
+    )]
+    pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    // Receives any tokens sent straight to the vault's token account, which were
+    // never credited and would otherwise stop it from being closed.
+    #[account(
+        mut,
+        token::mint = mint,
+        token::token_program = token_program
+    )]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> CloseTokenVault<'info> {
+    pub fn close_token_vault(&mut self) -> Result<()> {
+        // The vault PDA owns the token account, so it signs both calls.
+        let authority_key = self.authority.key();
+        let signer_seeds: &[&[&[u8]]] = &[&[b"vault", authority_key.as_ref(), &[self.vault.bump]]];
+
+        // --- 1. Sweep Uncredited Tokens ---
+        let stray = self.vault_token_account.amount;
+        if stray > 0 {
+            let accounts = TransferChecked {
+                from: self.vault_token_account.to_account_info(),
+                mint: self.mint.to_account_info(),
+                to: self.user_token_account.to_account_info(),
+                authority: self.vault.to_account_info(),
+            };
+            let ctx = CpiContext::new_with_signer(self.token_program.to_account_info(), accounts, signer_seeds);
+            transfer_checked(ctx, stray, self.mint.decimals)?;
+        }
+
+        // --- 2. Close the Token Account ---
+        let accounts = CloseAccount {
+            account: self.vault_token_account.to_account_info(),
+            destination: self.authority.to_account_info(),
+            authority: self.vault.to_account_info(),
+        };
+        let ctx = CpiContext::new_with_signer(self.token_program.to_account_info(), accounts, signer_seeds);
+        close_account(ctx)?;
+
+        self.vault.token_vault_count = self.vault.token_vault_count.checked_sub(1).unwrap();
+
+        msg!("Token vault of {} for mint {} closed.", self.authority.key(), self.mint.key());
+        Ok(())
+    }
+}
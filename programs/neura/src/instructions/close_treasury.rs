@@ -0,0 +1,38 @@
+use anchor_lang::prelude::*;
+use crate::error::{AmountError, ProtocolError};
+use crate::state::{ProtocolConfig, Treasury};
+
+#[derive(Accounts)]
+pub struct CloseTreasury<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        has_one = admin @ ProtocolError::Unauthorized,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    // Only once no user has a claim on it. Any other lamports left, such as
+    // repaid spend, go to the admin with the rent.
+    #[account(
+        mut,
+        close = admin,
+        seeds = [b"treasury"],
+        bump = treasury.bump,
+        constraint = treasury.total_staked == 0 @ AmountError::AccountNotEmpty,
+        constraint = treasury.unclaimed_rewards == 0 @ AmountError::AccountNotEmpty,
+        constraint = treasury.accrued_fees == 0 @ AmountError::AccountNotEmpty,
+        constraint = treasury.open_spending_accounts == 0 @ AmountError::AccountNotEmpty,
+        constraint = treasury.open_yield_accounts == 0 @ AmountError::AccountNotEmpty
+    )]
+    pub treasury: Account<'info, Treasury>,
+}
+
+impl<'info> CloseTreasury<'info> {
+    pub fn close_treasury(&mut self) -> Result<()> {
+        msg!("Treasury closed.");
+        Ok(())
+    }
+}
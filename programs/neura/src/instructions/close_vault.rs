@@ -0,0 +1,31 @@
+use anchor_lang::prelude::*;
+use crate::error::AmountError;
+use crate::state::Vault;
+
+#[derive(Accounts)]
+pub struct CloseVault<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    // Token vaults have to be closed first with `close_token_vault`, which also
+    // closes the token accounts owned by the vault PDA, and so does the spending
+    // account.
+    #[account(
+        mut,
+        close = authority,
+        has_one = authority,
+        seeds = [b"vault", authority.key().as_ref()],
+        bump = vault.bump,
+        constraint = vault.balance == 0 @ AmountError::AccountNotEmpty,
+        constraint = vault.token_vault_count == 0 @ AmountError::AccountNotEmpty,
+        constraint = !vault.has_spending_account @ AmountError::AccountNotEmpty
+    )]
+    pub vault: Account<'info, Vault>,
+}
+
+impl<'info> CloseVault<'info> {
+    pub fn close_vault(&mut self) -> Result<()> {
+        msg!("Vault of {} closed.", self.authority.key());
+        Ok(())
+    }
+}
@@ -0,0 +1,37 @@
+use anchor_lang::prelude::*;
+use crate::error::AmountError;
+use crate::state::{Treasury, YieldAccount};
+
+#[derive(Accounts)]
+pub struct CloseYieldAccount<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    // Without stake no further rewards accrue, so settled rewards are all there is to claim.
+    #[account(
+        mut,
+        close = authority,
+        has_one = authority,
+        seeds = [b"yield", authority.key().as_ref()],
+        bump = yield_account.bump,
+        constraint = yield_account.staked_amount == 0 @ AmountError::AccountNotEmpty,
+        constraint = yield_account.pending_rewards == 0 @ AmountError::AccountNotEmpty
+    )]
+    pub yield_account: Account<'info, YieldAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = treasury.bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+}
+
+impl<'info> CloseYieldAccount<'info> {
+    pub fn close_yield_account(&mut self) -> Result<()> {
+        self.treasury.open_yield_accounts = self.treasury.open_yield_accounts.checked_sub(1).unwrap();
+
+        msg!("Yield account of {} closed.", self.authority.key());
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+use anchor_lang::prelude::*;
+
+use crate::{MandateParams, MerchantAccount, PaymentMandate};
+
+#[derive(Accounts)]
+pub struct CreateMandate<'info> {
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    // The merchant being authorised to pull payments.
+    #[account(
+        mut,
+        seeds = [b"merchant", merchant_account.authority.as_ref()],
+        bump = merchant_account.bump
+    )]
+    pub merchant_account: Account<'info, MerchantAccount>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + PaymentMandate::INIT_SPACE,
+        seeds = [b"mandate", authority.key().as_ref(), merchant_account.key().as_ref()],
+        bump
+    )]
+    pub mandate: Account<'info, PaymentMandate>,
+
+    pub system_program: Program<'info, System>,
+}
+
+impl <'info> CreateMandate <'info> {
+
+    pub fn create_mandate(&mut self, params: MandateParams, bumps: &CreateMandateBumps) -> Result<()>{
+
+        let now = Clock::get()?.unix_timestamp;
+        params.validate(now)?;
+
+        self.mandate.set_inner(PaymentMandate {
+            authority: self.authority.key(),
+            merchant_account: self.merchant_account.key(),
+            max_per_charge: params.max_per_charge,
+            max_per_period: params.max_per_period,
+            period_seconds: params.period_seconds,
+            expires_at: params.expires_at,
+            period_start: now,
+            period_spent: 0,
+            bump: bumps.mandate,
+        });
+        self.merchant_account.open_mandates = self.merchant_account.open_mandates.checked_add(1).unwrap();
+
+        Ok(())
+    }
+}
@@ -1,6 +1,6 @@
-use anchor_lang::{accounts::program, prelude::*, system_program::{transfer, Transfer}};
+use anchor_lang::{prelude::*, system_program::{transfer, Transfer}};
 
-use crate::Vault;
+use crate::{events::Deposited, state::credit_vault, Vault};
 
 
 #[derive(Accounts)]
@@ -34,9 +34,13 @@ impl <'info> Deposit <'info> {
 
         transfer(ctx, amount)?;
 
-        self.vault.reload()?;
-        self.vault.balance = self.vault.balance.checked_add(amount).unwrap();
+        credit_vault(&mut self.vault, amount)?;
 
+        emit!(Deposited {
+            user: self.authority.key(),
+            amount,
+            vault_balance: self.vault.balance,
+        });
 
         Ok(())
     }
@@ -0,0 +1,83 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked};
+
+use crate::{events::TokenDeposited, TokenVault, Vault};
+
+#[derive(Accounts)]
+pub struct DepositToken<'info>{
+
+    pub authority: Signer<'info>,
+
+    #[account(
+        has_one = authority,
+        seeds = [b"vault", authority.key().as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        has_one = authority,
+        has_one = mint,
+        seeds = [b"token_vault", authority.key().as_ref(), mint.key().as_ref()],
+        bump = token_vault.bump
+    )]
+    pub token_vault: Account<'info, TokenVault>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault,
+        associated_token::token_program = token_program
+    )]
+    pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    // The user's own token account the deposit is taken from.
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = authority,
+        token::token_program = token_program
+    )]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl <'info> DepositToken <'info> {
+    
+    pub fn deposit_token(&mut self, amount: u64) -> Result<()>{
+
+        let balance_before = self.vault_token_account.amount;
+
+        let program = self.token_program.to_account_info();
+        let accounts = TransferChecked{
+            from: self.user_token_account.to_account_info(),
+            mint: self.mint.to_account_info(),
+            to: self.vault_token_account.to_account_info(),
+            authority: self.authority.to_account_info(),
+        };
+
+        let ctx = CpiContext::new(program, accounts);
+
+        transfer_checked(ctx, amount, self.mint.decimals)?;
+
+        // Token-2022 transfer fees are withheld from what arrives, so credit the
+        // change in the vault's token account rather than the requested amount.
+        self.vault_token_account.reload()?;
+        let received = self.vault_token_account.amount.checked_sub(balance_before).unwrap();
+
+        self.token_vault.balance = self.token_vault.balance.checked_add(received).unwrap();
+
+        emit!(TokenDeposited {
+            user: self.authority.key(),
+            mint: self.mint.key(),
+            amount: received,
+            token_vault_balance: self.token_vault.balance,
+        });
+
+        Ok(())
+    }
+}
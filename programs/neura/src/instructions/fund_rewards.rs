@@ -0,0 +1,48 @@
+use anchor_lang::{prelude::*, system_program::{transfer, Transfer}};
+
+use crate::{error::ProtocolError, ProtocolConfig, Treasury};
+
+#[derive(Accounts)]
+pub struct FundRewards<'info> {
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        has_one = admin @ ProtocolError::Unauthorized,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = treasury.bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    pub system_program: Program<'info, System>,
+}
+
+impl <'info> FundRewards <'info> {
+
+    pub fn fund_rewards(&mut self, amount: u64) -> Result<()>{
+
+        let program = self.system_program.to_account_info();
+        let accounts = Transfer{
+            from: self.admin.to_account_info(),
+            to: self.treasury.to_account_info(),
+        };
+
+        let ctx = CpiContext::new(program, accounts);
+
+        transfer(ctx, amount)?;
+
+        self.treasury.distribute_rewards(amount)?;
+
+        msg!("Funded {} reward lamports across {} staked lamports.", amount, self.treasury.total_staked);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+use anchor_lang::prelude::*;
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+
+use crate::{
+    health::{sol_collateral, token_collateral_value, PositionHealth},
+    pricing::AssetPrice,
+    ProtocolConfig, SpendingAccount, Treasury, Vault, YieldAccount,
+};
+
+// Read-only: nothing is written, so clients and keepers can simulate it for any user.
+#[derive(Accounts)]
+pub struct GetPositionHealth<'info> {
+
+    /// CHECK: The owner of the position. Only its key is used to derive the position PDAs.
+    pub user: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        seeds = [b"vault", user.key().as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    // Users who never staked can omit it.
+    #[account(
+        seeds = [b"yield", user.key().as_ref()],
+        bump = yield_account.bump
+    )]
+    pub yield_account: Option<Account<'info, YieldAccount>>,
+
+    #[account(
+        seeds = [b"spending", user.key().as_ref()],
+        bump = spending_account.bump
+    )]
+    pub spending_account: Account<'info, SpendingAccount>,
+
+    // Supplies the borrow index, so the reported debt includes interest up to now.
+    #[account(
+        seeds = [b"treasury"],
+        bump = treasury.bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    pub price_update: Account<'info, PriceUpdateV2>,
+}
+
+impl <'info> GetPositionHealth <'info> {
+
+    // `remaining_accounts` carries the user's SPL collateral, see `token_collateral_value`.
+    pub fn get_position_health(&self, remaining_accounts: &[AccountInfo]) -> Result<PositionHealth> {
+
+        // Interest is accrued on copies, the accounts themselves are left untouched.
+        let now = Clock::get()?.unix_timestamp;
+        let borrow_index = Treasury::clone(&self.treasury).accrue_interest(now, self.config.borrow_rate_bps);
+        let mut spending_account = SpendingAccount::clone(&self.spending_account);
+        spending_account.accrue_interest(borrow_index);
+
+        let sol_price = AssetPrice::load_sol_usd(&self.price_update, &self.config)?;
+        let tokens = token_collateral_value(remaining_accounts, &self.vault, &self.config)?;
+        let health = PositionHealth::new(
+            spending_account.amount_spent,
+            sol_collateral(&self.vault, self.yield_account.as_deref(), 0)?,
+            tokens,
+            &sol_price,
+            &self.config,
+        )?;
+
+        msg!(
+            "Collateral ratio {}% with {} micro-USD of debt. Liquidatable below {} micro-USD per SOL.",
+            health.collateral_ratio, health.debt, health.liquidation_price
+        );
+
+        Ok(health)
+    }
+}
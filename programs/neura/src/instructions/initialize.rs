@@ -26,6 +26,8 @@ impl <'info> Initialize<'info> {
         self.vault.set_inner(Vault { 
             authority: self.user.key(), 
             balance: 0,
+            token_vault_count: 0,
+            has_spending_account: false,
             bump: bump.vault,
         });
 
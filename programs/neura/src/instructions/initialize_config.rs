@@ -0,0 +1,57 @@
+use anchor_lang::prelude::*;
+
+use crate::{error::ProtocolError, program::CapstoneProject, ConfigParams, ProtocolConfig};
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+
+    // Only the program's upgrade authority can claim the admin role.
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        constraint = program.programdata_address()? == Some(program_data.key()) @ ProtocolError::Unauthorized
+    )]
+    pub program: Program<'info, CapstoneProject>,
+
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(admin.key()) @ ProtocolError::Unauthorized
+    )]
+    pub program_data: Account<'info, ProgramData>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + ProtocolConfig::INIT_SPACE,
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    pub system_program: Program<'info, System>,
+}
+
+impl <'info> InitializeConfig <'info> {
+
+    pub fn initialize_config(&mut self, params: ConfigParams, bumps: &InitializeConfigBumps) -> Result<()>{
+
+        params.validate()?;
+
+        self.config.set_inner(ProtocolConfig {
+            admin: self.admin.key(),
+            sol_usd_feed_id: params.sol_usd_feed_id,
+            maximum_age: params.maximum_age,
+            liquidation_threshold: params.liquidation_threshold,
+            target_collateral_ratio: params.target_collateral_ratio,
+            loan_to_value_bps: params.loan_to_value_bps,
+            liquidation_bonus_bps: params.liquidation_bonus_bps,
+            confidence_multiplier_bps: params.confidence_multiplier_bps,
+            max_confidence_ratio_bps: params.max_confidence_ratio_bps,
+            merchant_fee_bps: params.merchant_fee_bps,
+            borrow_rate_bps: params.borrow_rate_bps,
+            bump: bumps.config,
+        });
+
+        Ok(())
+    }
+}
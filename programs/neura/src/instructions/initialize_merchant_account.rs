@@ -0,0 +1,56 @@
+use anchor_lang::prelude::*;
+use crate::events::MerchantRegistered;
+use crate::state::MerchantAccount;
+
+#[derive(Accounts)]
+// The name argument from the instruction function is also passed here.
+#[instruction(name: String)]
+pub struct InitializeMerchantAccount<'info> {
+    // The merchant who is creating the account.
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    // The new merchant account PDA.
+    // Its space is defined by the constant in the MerchantAccount state.
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + MerchantAccount::INIT_SPACE,
+        seeds = [b"merchant", authority.key().as_ref()],
+        bump
+    )]
+    pub merchant_account: Account<'info, MerchantAccount>,
+
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> InitializeMerchantAccount<'info> {
+    pub fn init_merchant_account(&mut self, name: String, accepted_mint: Option<Pubkey>, bumps: &InitializeMerchantAccountBumps) -> Result<()> {
+        MerchantAccount::validate_name(&name)?;
+
+        // New merchants start unverified and cannot take payments until the admin verifies them.
+        self.merchant_account.set_inner(MerchantAccount {
+            authority: self.authority.key(),
+            name,
+            accepted_mint,
+            payment_count: 0,
+            open_receipts: 0,
+            open_mandates: 0,
+            payout_wallet: self.authority.key(),
+            category: 0,
+            verified: false,
+            suspended: false,
+            fee_bps: None,
+            bump: bumps.merchant_account,
+        });
+
+        emit!(MerchantRegistered {
+            merchant_account: self.merchant_account.key(),
+            authority: self.authority.key(),
+            name: self.merchant_account.name.clone(),
+            accepted_mint: self.merchant_account.accepted_mint,
+        });
+
+        Ok(())
+    }
+}
@@ -1,6 +1,6 @@
 use anchor_lang::prelude::*;
 
-use crate::SpendingAccount;
+use crate::{DebtDenomination, SpendingAccount, Treasury, Vault, DEFAULT_SPEND_PERIOD_SECONDS};
 
 #[derive(Accounts)]
 pub struct InitializeSpendingAccount<'info>{
@@ -17,6 +17,24 @@ pub struct InitializeSpendingAccount<'info>{
     )]
     pub spending_account: Account<'info, SpendingAccount>,
 
+    // The collateral the spend is backed by. It records the spending account so
+    // withdrawals cannot leave it out.
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [b"vault", authority.key().as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    // Counts the account, which keeps the treasury open while it exists.
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = treasury.bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+
     pub system_program: Program<'info, System>,
 }
 
@@ -26,10 +44,20 @@ impl <'info> InitializeSpendingAccount <'info> {
 
         self.spending_account.set_inner(SpendingAccount { 
             authority: self.authority.key(), 
+            denomination: DebtDenomination::UsdMicro,
             spending_limit: 0, 
             amount_spent: 0,
+            period_seconds: DEFAULT_SPEND_PERIOD_SECONDS,
+            // No cap on a single window until the owner sets one.
+            window_limit: u64::MAX,
+            window_start: Clock::get()?.unix_timestamp,
+            window_spent: 0,
+            // Synced with the treasury on the first spend.
+            borrow_index: 0,
             bump: bumps.spending_account 
         });
+        self.vault.has_spending_account = true;
+        self.treasury.open_spending_accounts = self.treasury.open_spending_accounts.checked_add(1).unwrap();
 
         Ok(())
     }
@@ -0,0 +1,71 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{Mint, TokenAccount, TokenInterface},
+};
+
+use crate::{CollateralAsset, TokenVault, Vault};
+
+#[derive(Accounts)]
+pub struct InitializeTokenVault<'info> {
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [b"vault", authority.key().as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    // Token vaults hold collateral, so only registered collateral mints get one.
+    #[account(
+        seeds = [b"collateral", mint.key().as_ref()],
+        bump = collateral_asset.bump
+    )]
+    pub collateral_asset: Account<'info, CollateralAsset>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + TokenVault::INIT_SPACE,
+        seeds = [b"token_vault", authority.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub token_vault: Account<'info, TokenVault>,
+
+    // The vault PDA's associated token account for `mint`, which holds the tokens.
+    #[account(
+        init,
+        payer = authority,
+        associated_token::mint = mint,
+        associated_token::authority = vault,
+        associated_token::token_program = token_program
+    )]
+    pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+impl <'info> InitializeTokenVault <'info> {
+
+    pub fn init_token_vault(&mut self, bumps: &InitializeTokenVaultBumps) -> Result<()>{
+
+        self.token_vault.set_inner(TokenVault {
+            authority: self.authority.key(),
+            mint: self.mint.key(),
+            balance: 0,
+            bump: bumps.token_vault,
+        });
+        self.vault.token_vault_count = self.vault.token_vault_count.checked_add(1).unwrap();
+
+        Ok(())
+    }
+}
@@ -1,6 +1,6 @@
 use anchor_lang::prelude::*;
 
-use crate::Treasury;
+use crate::{error::ProtocolError, ProtocolConfig, Treasury, BORROW_INDEX_PRECISION};
 
 #[derive(Accounts)]
 pub struct InitializeTreasury<'info> {
@@ -8,11 +8,19 @@ pub struct InitializeTreasury<'info> {
     #[account(mut)]
     pub authority: Signer<'info>,
 
+    // Only the protocol admin may create the shared treasury.
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        constraint = config.admin == authority.key() @ ProtocolError::Unauthorized
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
     #[account(
         init,
         payer = authority,
         space = 8 + Treasury::INIT_SPACE,
-        seeds = [b"treasury", authority.key().as_ref()],
+        seeds = [b"treasury"],
         bump
     )]
     pub treasury: Account<'info, Treasury>,
@@ -27,8 +35,17 @@ impl <'info> InitializeTreasury <'info> {
 
         self.treasury.set_inner(Treasury { 
             authority: self.authority.key(),
+            total_staked: 0,
+            reward_per_share: 0,
+            unclaimed_rewards: 0,
+            accrued_fees: 0,
+            total_fees: 0,
+            borrow_index: BORROW_INDEX_PRECISION,
+            last_accrual: Clock::get()?.unix_timestamp,
+            open_spending_accounts: 0,
+            open_yield_accounts: 0,
             bump: bumps.treasury 
         });
         Ok(())
     }
-}
\ No newline at end of file
+}
@@ -17,6 +17,14 @@ pub struct InitializeYieldAccount<'info> {
     )]
     pub yield_account: Account<'info, YieldAccount>,
 
+    // Counts the account, which keeps the treasury open while it exists.
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = treasury.bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+
     pub system_program : Program<'info, System>,
 
 }
@@ -28,8 +36,11 @@ impl <'info> InitializeYieldAccount <'info> {
         self.yield_account.set_inner(YieldAccount { 
             authority: self.authority.key(), 
             staked_amount: 0, 
+            last_index: 0,
+            pending_rewards: 0,
             bump: bumps.yield_account 
         });
+        self.treasury.open_yield_accounts = self.treasury.open_yield_accounts.checked_add(1).unwrap();
         Ok(())
     }
 }
\ No newline at end of file
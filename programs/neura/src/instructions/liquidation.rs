@@ -1,121 +1,439 @@
 use anchor_lang::prelude::*;
-use pyth_solana_receiver_sdk::price_update::{get_feed_id_from_hex, PriceUpdateV2};
+use anchor_spl::{
+    associated_token::get_associated_token_address_with_program_id,
+    token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked},
+};
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
 
-use crate::{SpendingAccount, Treasury, Vault, YieldAccount};
+use crate::{
+    error::ProtocolError,
+    events::Liquidated,
+    health::{sol_collateral, token_collateral_value, token_price},
+    pricing::AssetPrice,
+    state::debit_vault,
+    ProtocolConfig, SpendingAccount, TokenVault, Treasury, Vault, YieldAccount,
+};
 
-const LIQUIDATION_THRESHOLD: u128 = 120; // If ratio is below 120%, liquidation can occur.
-const TARGET_COLLATERAL_RATIO: u128 = 150;
+// How much of an unhealthy position to unwind. USD amounts are in micro-USD.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LiquidationPlan {
+    // Debt cleared by the SOL seized.
+    pub debt_repaid: u64,
+    pub lamports_seized: u64,
+    // Part of `lamports_seized` paid out to the liquidator.
+    pub liquidator_bonus: u64,
+    // Debt the plan could not clear out of SOL, left for SPL collateral.
+    pub debt_uncovered: u64,
+}
+
+// SPL collateral taken to clear debt the SOL could not.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TokenSeizure {
+    pub debt_repaid: u64,
+    // Base units paid to the liquidator, bonus included.
+    pub tokens_seized: u64,
+}
 
 #[derive(Accounts)]
 pub struct Liquidation <'info> {
 
+    // Any keeper can liquidate an unhealthy position and earns the bonus.
     #[account(mut)]
-    pub authority: Signer<'info>,
+    pub liquidator: Signer<'info>,
+
+    /// CHECK: The owner of the position being liquidated. Only its key is used to derive the position PDAs.
+    pub user: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    // Its SOL backs the spend and is seized once the stake runs out. It also records
+    // how many token vaults the user has, all of which must be passed in.
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
 
     #[account(
         mut,
-        seeds = [b"spending", authority.key().as_ref()],
-        bump
+        seeds = [b"spending", user.key().as_ref()],
+        bump = spending_account.bump
     )]
     pub spending_account: Account<'info, SpendingAccount>,
 
+    // Users who never staked can omit it.
     #[account(
         mut,
-        has_one = authority,
-        seeds = [b"yield", authority.key().as_ref()],
+        seeds = [b"yield", user.key().as_ref()],
         bump = yield_account.bump
     )]
-    pub yield_account: Account<'info, YieldAccount>,
+    pub yield_account: Option<Account<'info, YieldAccount>>,
 
     pub price_update: Account<'info, PriceUpdateV2>,
 
     #[account(
         mut,
-        has_one = authority,
-        seeds = [b"vault", authority.key().as_ref()],
-        bump = vault.bump
+        seeds = [b"treasury"],
+        bump = treasury.bump
     )]
-    pub vault: Account<'info, Vault>,
+    pub treasury: Account<'info, Treasury>,
 
+    // The token vault to seize SPL collateral from once the SOL runs out, with its
+    // mint, the vault's token account holding it and the liquidator's token account
+    // the tokens are paid into. The liquidator pays the debt they clear in lamports.
+    // Only needed when the SOL does not cover the liquidation.
     #[account(
         mut,
-        has_one = authority,
-        seeds = [b"treasury", authority.key().as_ref()],
-        bump = treasury.bump
+        seeds = [b"token_vault", user.key().as_ref(), token_vault.mint.as_ref()],
+        bump = token_vault.bump
     )]
-    pub treasury: Account<'info, Treasury>,
+    pub token_vault: Option<Account<'info, TokenVault>>,
 
-    
+    pub mint: Option<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut)]
+    pub vault_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub liquidator_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+
+    pub system_program: Program<'info, System>,
 }
 
 impl <'info> Liquidation <'info> {
     
-    pub fn liquidation(&mut self) -> Result<()>{
+    // `remaining_accounts` carries the user's SPL collateral, see `token_collateral_value`.
+    // SOL is seized first, then the token vault passed in, if any.
+    pub fn liquidate(&mut self, remaining_accounts: &[AccountInfo]) -> Result<()>{
+
+        let now = Clock::get()?.unix_timestamp;
+        let borrow_index = self.treasury.accrue_interest(now, self.config.borrow_rate_bps);
+        self.spending_account.accrue_interest(borrow_index);
 
-        let amount_spent = self.spending_account.amount_spent;
-        let staked_amount = self.yield_account.staked_amount;
-        let price_update = &self.price_update;
+        let sol_price = AssetPrice::load_sol_usd(&self.price_update, &self.config)?;
+        let token_collateral = token_collateral_value(remaining_accounts, &self.vault, &self.config)?;
+        let staked_amount = self.yield_account.as_ref().map_or(0, |y| y.staked_amount);
+        let sol_lamports = sol_collateral(&self.vault, self.yield_account.as_deref(), 0)?;
 
-        let maximum_age: u64 = 60;
-        let feed_id: [u8; 32] = get_feed_id_from_hex("0xef0d8b614545449452e9f8d4623e34ade2ba2ac67362100e27457bf6fc8894c4")?;
+        // --- 1. Check if Liquidation is Necessary ---
+        let plan = plan_liquidation(
+            self.spending_account.amount_spent,
+            sol_lamports,
+            token_collateral.liquidation_value,
+            &sol_price,
+            &self.config,
+        )?.ok_or(ProtocolError::PositionHealthy)?;
+
+        // --- 2. Seize SOL ---
+        msg!("Liquidating {} lamports to repay {} micro-USD of debt.", plan.lamports_seized, plan.debt_repaid);
+
+        // Stake is seized first, as it already sits in the treasury. The rest comes out
+        // of the vault into the treasury. The repaid share stays there to cover the
+        // debt and the bonus share is paid out to the liquidator.
+        let stake_seized = plan.lamports_seized.min(staked_amount);
+        let vault_seized = plan.lamports_seized - stake_seized;
+        if let Some(yield_account) = self.yield_account.as_mut() {
+            yield_account.settle_rewards(self.treasury.reward_per_share)?;
+            yield_account.staked_amount = yield_account.staked_amount.checked_sub(stake_seized).unwrap();
+        }
+        self.treasury.total_staked = self.treasury.total_staked.checked_sub(stake_seized).unwrap();
+        debit_vault(&mut self.vault, &self.treasury.to_account_info(), vault_seized)?;
 
-        let current_price = price_update.get_price_no_older_than(&Clock::get()?, maximum_age, &feed_id)?;
+        let treasury_lamports = self.treasury.to_account_info().lamports();
+        **self.treasury.to_account_info().try_borrow_mut_lamports()? = treasury_lamports
+            .checked_sub(plan.liquidator_bonus)
+            .unwrap();
 
-        let staked_price_in_usd_cents = ((staked_amount as u128).checked_mul(current_price.price as u128).unwrap() as u128).checked_div((10u128).pow(15)).unwrap();
-        let amount_spent_in_cents = amount_spent as u128;
+        let liquidator_lamports = self.liquidator.to_account_info().lamports();
+        **self.liquidator.to_account_info().try_borrow_mut_lamports()? = liquidator_lamports
+            .checked_add(plan.liquidator_bonus)
+            .unwrap();
 
-        let collateral_ratio = if amount_spent_in_cents > 0 {
-            staked_price_in_usd_cents.checked_mul(100).unwrap().checked_div(amount_spent_in_cents).unwrap()
-        } else{
-            0
+        // --- 3. Seize SPL Collateral ---
+        let seizure = match self.token_vault.is_some() && plan.debt_uncovered > 0 {
+            true => Some(self.seize_tokens(plan.debt_uncovered, &sol_price, remaining_accounts)?),
+            false => None,
         };
+        let token_debt_repaid = seizure.map_or(0, |s| s.debt_repaid);
+        let tokens_seized = seizure.map_or(0, |s| s.tokens_seized);
+        require!(plan.lamports_seized > 0 || tokens_seized > 0, ProtocolError::NothingToSeize);
 
-        let pyth_price = current_price.price as u128;
-        let pyth_expo = current_price.exponent.abs() as u32;
-        let staked_lamports = self.yield_account.staked_amount as u128;
-
-        // --- 2. Check if Liquidation is Necessary ---
-        if collateral_ratio < LIQUIDATION_THRESHOLD {
-            msg!("Ratio is below threshold. Starting liquidation...");
-
-            // --- 3. Calculate How Much to Liquidate ---
-            // We want to find the new, smaller debt amount that makes the ratio 150%.
-            // Formula: NewDebt = (Collateral * 100) / TargetRatio
-            let target_debt_in_cents = staked_price_in_usd_cents
-                .checked_mul(100).unwrap()
-                .checked_div(TARGET_COLLATERAL_RATIO).unwrap();
-
-            // The amount to liquidate is the difference between the old debt and the new target debt.
-            let debt_to_repay_in_cents = amount_spent_in_cents
-                .checked_sub(target_debt_in_cents).unwrap();
-
-            // Convert the cents to repay back into lamports of SOL to sell.
-            // Formula: Lamports = (Cents * 10^9 * 10^expo) / (Price * 100)
-            let lamports_to_liquidate = debt_to_repay_in_cents
-                .checked_mul((10u128).pow(9 + pyth_expo)).unwrap()
-                .checked_div(pyth_price).unwrap()
-                .checked_div(100).unwrap();
-
-            // --- 4. Perform the Liquidation (Transfer and State Update) ---
-            msg!("Liquidating {} lamports to repay ${:.2} of debt.", lamports_to_liquidate, debt_to_repay_in_cents as f64 / 100.0);
-
-            // Transfer lamports from Treasury to user's Vault. This simulates the "sale".
-            // Debit from the treasury.
-            **self.treasury.to_account_info().try_borrow_mut_lamports()? -= lamports_to_liquidate as u64;
-            // Credit to the vault.
-            **self.vault.to_account_info().try_borrow_mut_lamports()? += lamports_to_liquidate as u64;
-
-            // Update the state accounts
-            self.yield_account.staked_amount -= lamports_to_liquidate as u64;
-            self.spending_account.amount_spent -= debt_to_repay_in_cents as u64;
-
-            msg!("Liquidation successful. New ratio will be >= 150%.");
-
-        } else {
-            msg!("Position is healthy. No liquidation needed.");
-        }
+        let debt_repaid = plan.debt_repaid.checked_add(token_debt_repaid).unwrap();
+        self.spending_account.amount_spent = self.spending_account.amount_spent.checked_sub(debt_repaid).unwrap();
+
+        msg!("Liquidation successful. Liquidator {} earned a {} lamport bonus.", self.liquidator.key(), plan.liquidator_bonus);
+
+        emit!(Liquidated {
+            user: self.user.key(),
+            liquidator: self.liquidator.key(),
+            debt_repaid,
+            lamports_seized: plan.lamports_seized,
+            liquidator_bonus: plan.liquidator_bonus,
+            mint: self.token_vault.as_ref().filter(|_| tokens_seized > 0).map(|t| t.mint),
+            tokens_seized,
+        });
 
         Ok(())
     
     }
+
+    // Clears up to `debt_usd` of debt out of the token vault. The liquidator pays
+    // the debt cleared into the treasury in lamports and receives the tokens,
+    // bonus included.
+    fn seize_tokens(&mut self, debt_usd: u64, sol_price: &AssetPrice, remaining_accounts: &[AccountInfo]) -> Result<TokenSeizure> {
+        let (Some(token_vault), Some(mint), Some(vault_token_account), Some(liquidator_token_account), Some(token_program)) = (
+            self.token_vault.as_mut(),
+            self.mint.as_ref(),
+            self.vault_token_account.as_ref(),
+            self.liquidator_token_account.as_ref(),
+            self.token_program.as_ref(),
+        ) else {
+            return err!(ProtocolError::InvalidCollateralAccounts);
+        };
+        require_keys_eq!(mint.key(), token_vault.mint, ProtocolError::InvalidCollateralAccounts);
+        require_keys_eq!(
+            vault_token_account.key(),
+            get_associated_token_address_with_program_id(&self.vault.key(), &mint.key(), &token_program.key()),
+            ProtocolError::InvalidCollateralAccounts
+        );
+
+        let price = token_price(remaining_accounts, &mint.key(), &self.config)?;
+        let seizure = plan_token_seizure(debt_usd, token_vault.balance, &price, &self.config)?;
+
+        // The liquidator buys the debt back at the lower SOL price, so the treasury is never short.
+        let lamports = u64::try_from(sol_price.collateral_value_amount(seizure.debt_repaid as u128)?)
+            .map_err(|_| ProtocolError::MathOverflow)?;
+        let accounts = anchor_lang::system_program::Transfer {
+            from: self.liquidator.to_account_info(),
+            to: self.treasury.to_account_info(),
+        };
+        anchor_lang::system_program::transfer(CpiContext::new(self.system_program.to_account_info(), accounts), lamports)?;
+
+        // The vault PDA owns the token account, so it signs the transfer.
+        let user_key = self.user.key();
+        let signer_seeds: &[&[&[u8]]] = &[&[b"vault", user_key.as_ref(), &[self.vault.bump]]];
+        let accounts = TransferChecked {
+            from: vault_token_account.to_account_info(),
+            mint: mint.to_account_info(),
+            to: liquidator_token_account.to_account_info(),
+            authority: self.vault.to_account_info(),
+        };
+        let ctx = CpiContext::new_with_signer(token_program.to_account_info(), accounts, signer_seeds);
+        transfer_checked(ctx, seizure.tokens_seized, mint.decimals)?;
+
+        token_vault.balance = token_vault.balance.checked_sub(seizure.tokens_seized).unwrap();
+
+        msg!("Seized {} tokens of mint {} to repay {} micro-USD of debt.", seizure.tokens_seized, mint.key(), seizure.debt_repaid);
+        Ok(seizure)
+    }
+}
+
+// Works out how much debt to repay and SOL to seize so the position lands on the
+// target collateral ratio. `sol_lamports` is the SOL that can be seized: the vault
+// balance plus stake. Returns `None` when the position is healthy.
+//
+// `token_liquidation_value` is the risk-weighted value of the SPL collateral. It is
+// counted as the amount of SOL collateral with the same weight, so the ratio below
+// falls under the threshold exactly when the weighted collateral falls under the debt.
+pub fn plan_liquidation(
+    amount_spent: u64,
+    sol_lamports: u64,
+    token_liquidation_value: u128,
+    sol_price: &AssetPrice,
+    config: &ProtocolConfig,
+) -> Result<Option<LiquidationPlan>> {
+
+    let liquidation_threshold = config.liquidation_threshold as u128; // If ratio is below this, liquidation can occur.
+    let target_collateral_ratio = config.target_collateral_ratio as u128;
+    let bonus_bps = config.liquidation_bonus_bps as u128;
+
+    let collateral_in_usd = sol_price.collateral_value_usd(sol_lamports)?
+        .checked_add(token_liquidation_value.checked_mul(liquidation_threshold).unwrap() / 100)
+        .unwrap();
+    let debt_in_usd = amount_spent as u128;
+
+    // A position without debt can never be liquidated.
+    if debt_in_usd == 0 {
+        return Ok(None);
+    }
+
+    let collateral_ratio = collateral_in_usd.checked_mul(100).unwrap().checked_div(debt_in_usd).unwrap();
+    if collateral_ratio >= liquidation_threshold {
+        return Ok(None);
+    }
+
+    // Every unit of debt repaid seizes (1 + bonus) units of collateral, so to land
+    // exactly on the target ratio T we solve (C - r * (1 + b)) / (D - r) = T for r:
+    // Formula: r = (T * D - C) / (T - 1 - b)
+    let target_bps = target_collateral_ratio.checked_mul(100).unwrap();
+    let mut debt_to_repay = target_bps
+        .checked_mul(debt_in_usd).unwrap()
+        .saturating_sub(collateral_in_usd.checked_mul(10_000).unwrap())
+        .checked_div(target_bps.checked_sub(10_000 + bonus_bps).unwrap()).unwrap()
+        .min(debt_in_usd);
+
+    let mut lamports_to_repay = sol_price.debt_value_amount(debt_to_repay)?;
+    let mut lamports_to_seize = lamports_to_repay
+        .checked_mul(10_000 + bonus_bps).unwrap()
+        .checked_div(10_000).unwrap();
+
+    // A deeply underwater position cannot cover the full bonus: seize everything
+    // and only credit the share of it that is not paid out as bonus. The rest of
+    // the debt is left for SPL collateral.
+    let debt_planned = debt_to_repay;
+    if lamports_to_seize > sol_lamports as u128 {
+        lamports_to_seize = sol_lamports as u128;
+        lamports_to_repay = lamports_to_seize
+            .checked_mul(10_000).unwrap()
+            .checked_div(10_000 + bonus_bps).unwrap();
+        debt_to_repay = sol_price
+            .debt_value_usd(lamports_to_repay as u64)?
+            .min(debt_in_usd);
+    }
+
+    Ok(Some(LiquidationPlan {
+        debt_repaid: debt_to_repay as u64,
+        lamports_seized: lamports_to_seize as u64,
+        liquidator_bonus: lamports_to_seize.checked_sub(lamports_to_repay).unwrap() as u64,
+        debt_uncovered: debt_planned.checked_sub(debt_to_repay).unwrap() as u64,
+    }))
+}
+
+// Works out how many tokens to seize out of `balance` to clear `debt_usd` of debt
+// plus the liquidator bonus, at the token's debt price. Token collateral is counted
+// at its liquidation weight, so unlike seizing SOL this lands near, not exactly on,
+// the target ratio.
+pub fn plan_token_seizure(
+    debt_usd: u64,
+    balance: u64,
+    price: &AssetPrice,
+    config: &ProtocolConfig,
+) -> Result<TokenSeizure> {
+    let bonus_bps = config.liquidation_bonus_bps as u128;
+
+    let mut debt_to_repay = debt_usd as u128;
+    let mut tokens_to_seize = price.debt_value_amount(debt_to_repay)?
+        .checked_mul(10_000 + bonus_bps).unwrap()
+        .checked_div(10_000).unwrap();
+
+    // As with SOL, a token vault that cannot cover the bonus is seized in full.
+    if tokens_to_seize > balance as u128 {
+        tokens_to_seize = balance as u128;
+        let tokens_to_repay = tokens_to_seize
+            .checked_mul(10_000).unwrap()
+            .checked_div(10_000 + bonus_bps).unwrap();
+        debt_to_repay = price.debt_value_usd(tokens_to_repay as u64)?.min(debt_to_repay);
+    }
+
+    Ok(TokenSeizure {
+        debt_repaid: debt_to_repay as u64,
+        tokens_seized: tokens_to_seize as u64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{health::CollateralValue, pricing::SOL_DECIMALS};
+
+    const SOL: u64 = 1_000_000_000;
+
+    fn sol_at(dollars: i64) -> AssetPrice {
+        AssetPrice::from_oracle(dollars * 100_000_000, 0, -8, SOL_DECIMALS, &ProtocolConfig::test_default()).unwrap()
+    }
+
+    // 10 SOL of collateral, then a 4 SOL payment made while SOL trades at $150.
+    fn position_after_payment() -> (u64, u64) {
+        let collateral = 10 * SOL;
+        let debt = u64::try_from(sol_at(150).debt_value_usd(4 * SOL).unwrap()).unwrap();
+        assert_eq!(debt, 600_000_000);
+        (debt, collateral)
+    }
+
+    #[test]
+    fn healthy_position_is_not_liquidated() {
+        let (debt, collateral) = position_after_payment();
+
+        // $800 of collateral against $600 of debt is 133%.
+        assert_eq!(plan_liquidation(debt, collateral, 0, &sol_at(80), &ProtocolConfig::test_default()).unwrap(), None);
+        assert_eq!(plan_liquidation(0, collateral, 0, &sol_at(1), &ProtocolConfig::test_default()).unwrap(), None);
+    }
+
+    #[test]
+    fn price_drop_liquidates_back_to_target_ratio() {
+        let (debt, collateral) = position_after_payment();
+
+        // $700 of collateral against $600 of debt is 116%.
+        let plan = plan_liquidation(debt, collateral, 0, &sol_at(70), &ProtocolConfig::test_default()).unwrap().unwrap();
+
+        // r = (1.5 * 600 - 700) / (1.5 - 1 - 0.05) = $444.444444
+        assert_eq!(plan.debt_repaid, 444_444_444);
+        assert_eq!(plan.lamports_seized, 6_666_666_659);
+        assert_eq!(plan.liquidator_bonus, 317_460_317);
+
+        let collateral_left = sol_at(70).collateral_value_usd(collateral - plan.lamports_seized).unwrap();
+        let debt_left = (debt - plan.debt_repaid) as u128;
+        // Within a basis point of 150%, the shortfall coming from rounding.
+        let ratio_bps = collateral_left * 10_000 / debt_left;
+        assert!((14_999..=15_000).contains(&ratio_bps), "ratio was {ratio_bps} bps");
+    }
+
+    #[test]
+    fn token_collateral_counts_towards_health() {
+        let (debt, collateral) = position_after_payment();
+
+        // $125 of a stablecoin with an 80% liquidation threshold backs $100 of debt,
+        // worth $120 of SOL at a 120% threshold: $820 against $600 of debt is 136%.
+        let mut tokens = CollateralValue::default();
+        tokens.add(125_000_000, 7_500, 8_000).unwrap();
+        assert_eq!(tokens.liquidation_value, 100_000_000);
+
+        assert_eq!(plan_liquidation(debt, collateral, tokens.liquidation_value, &sol_at(70), &ProtocolConfig::test_default()).unwrap(), None);
+    }
+
+    #[test]
+    fn underwater_position_seizes_all_collateral() {
+        let (debt, collateral) = position_after_payment();
+
+        // $500 of collateral cannot cover $600 of debt plus the bonus.
+        let plan = plan_liquidation(debt, collateral, 0, &sol_at(50), &ProtocolConfig::test_default()).unwrap().unwrap();
+
+        assert_eq!(plan.lamports_seized, collateral);
+        assert_eq!(plan.liquidator_bonus, collateral - 9_523_809_523);
+        assert_eq!(plan.debt_repaid, 476_190_476);
+        // The rest is left for the SPL collateral.
+        assert_eq!(plan.debt_uncovered, debt - 476_190_476);
+    }
+
+    #[test]
+    fn token_only_position_leaves_the_debt_to_token_seizure() {
+        let debt = 100_000_000;
+
+        // Tokens with a $80 liquidation value are worth $96 of SOL at a 120% threshold,
+        // against $100 of debt, and there is no SOL to seize.
+        let plan = plan_liquidation(debt, 0, 80_000_000, &sol_at(150), &ProtocolConfig::test_default()).unwrap().unwrap();
+
+        assert_eq!(plan.lamports_seized, 0);
+        assert_eq!(plan.debt_repaid, 0);
+        assert!(plan.debt_uncovered > 0);
+    }
+
+    #[test]
+    fn token_seizure_pays_the_bonus_in_tokens() {
+        let usdc = AssetPrice::from_oracle(100_000_000, 0, -8, 6, &ProtocolConfig::test_default()).unwrap();
+
+        let seizure = plan_token_seizure(100_000_000, 500_000_000, &usdc, &ProtocolConfig::test_default()).unwrap();
+        assert_eq!(seizure, TokenSeizure { debt_repaid: 100_000_000, tokens_seized: 105_000_000 });
+
+        // A vault too small for the bonus is seized in full.
+        let seizure = plan_token_seizure(100_000_000, 50_000_000, &usdc, &ProtocolConfig::test_default()).unwrap();
+        assert_eq!(seizure, TokenSeizure { debt_repaid: 47_619_047, tokens_seized: 50_000_000 });
+    }
 }
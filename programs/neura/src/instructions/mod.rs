@@ -5,14 +5,46 @@ pub mod initialize_spending_account;
 pub mod update_spending_limit;
 pub mod authorize_spend;
 pub mod reset_spend_tracker;
+pub mod set_window_limit;
 pub mod initialize_treasury;
 pub mod initialize_yield_account;
 pub mod stake;
 pub mod unstake;
 pub mod check_price;
 pub mod liquidation;
-pub mod merchant_account;
+pub mod initialize_merchant_account;
 pub mod process_payment;
+pub mod process_token_payment;
+pub mod initialize_config;
+pub mod update_config;
+pub mod fund_rewards;
+pub mod claim_rewards;
+pub mod repay;
+pub mod initialize_token_vault;
+pub mod deposit_token;
+pub mod withdraw_token;
+pub mod add_collateral_asset;
+pub mod update_collateral_asset;
+pub mod create_mandate;
+pub mod revoke_mandate;
+pub mod charge;
+pub mod refund;
+pub mod refund_token;
+pub mod close_receipt;
+pub mod update_merchant;
+pub mod close_merchant;
+pub mod set_merchant_status;
+pub mod set_merchant_fee;
+pub mod withdraw_fees;
+pub mod withdraw_token_fees;
+pub mod get_position_health;
+pub mod sync_vault;
+pub mod close_vault;
+pub mod close_token_vault;
+pub mod close_spending_account;
+pub mod close_yield_account;
+pub mod close_treasury;
+pub mod close_all_positions;
 
 pub use initialize::*;
 pub use deposit::*;
@@ -21,11 +53,43 @@ pub use initialize_spending_account::*;
 pub use update_spending_limit::*;
 pub use authorize_spend::*;
 pub use reset_spend_tracker::*;
+pub use set_window_limit::*;
 pub use initialize_treasury::*;
 pub use initialize_yield_account::*;
 pub use stake::*;
 pub use unstake::*;
 pub use check_price::*;
 pub use liquidation::*;
-pub use merchant_account::*;
+pub use initialize_merchant_account::*;
 pub use process_payment::*;
+pub use process_token_payment::*;
+pub use initialize_config::*;
+pub use update_config::*;
+pub use fund_rewards::*;
+pub use claim_rewards::*;
+pub use repay::*;
+pub use initialize_token_vault::*;
+pub use deposit_token::*;
+pub use withdraw_token::*;
+pub use add_collateral_asset::*;
+pub use update_collateral_asset::*;
+pub use create_mandate::*;
+pub use revoke_mandate::*;
+pub use charge::*;
+pub use refund::*;
+pub use refund_token::*;
+pub use close_receipt::*;
+pub use update_merchant::*;
+pub use close_merchant::*;
+pub use set_merchant_status::*;
+pub use set_merchant_fee::*;
+pub use withdraw_fees::*;
+pub use withdraw_token_fees::*;
+pub use get_position_health::*;
+pub use sync_vault::*;
+pub use close_vault::*;
+pub use close_token_vault::*;
+pub use close_spending_account::*;
+pub use close_yield_account::*;
+pub use close_treasury::*;
+pub use close_all_positions::*;
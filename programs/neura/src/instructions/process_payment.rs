@@ -1,50 +1,44 @@
 use anchor_lang::prelude::*;
-use crate::state::{SpendingAccount, MerchantAccount};
-use crate::error::AmountError;
-
-pub fn process_payment(ctx: Context<ProcessPayment>, amount: u64) -> Result<()> {
-    // --- 1. Authorize the Spend ---
-    // This logic is identical to our `authorize_spend` instruction.
-    let available_to_spend = ctx.accounts.spending_account.spending_limit
-        .checked_sub(ctx.accounts.spending_account.amount_spent)
-        .unwrap();
-
-    require!(
-        amount <= available_to_spend,
-        AmountError::SpendingLimitExceeded
-    );
-
-    // Update the user's amount spent.
-    ctx.accounts.spending_account.amount_spent = ctx.accounts.spending_account.amount_spent
-        .checked_add(amount)
-        .unwrap();
-    
-    msg!("Spend of {} authorized.", amount);
-
-    // --- 2. Pay the Merchant ---
-    // We now perform a CPI to the System Program to transfer SOL from the user
-    // to the merchant's authority wallet.
-    let cpi_accounts = anchor_lang::system_program::Transfer {
-        from: ctx.accounts.authority.to_account_info(),
-        to: ctx.accounts.merchant_authority.to_account_info(),
-    };
-    let cpi_context = CpiContext::new(
-        ctx.accounts.system_program.to_account_info(),
-        cpi_accounts,
-    );
-    anchor_lang::system_program::transfer(cpi_context, amount)?;
-
-    msg!("Transferred {} lamports to merchant {}.", amount, ctx.accounts.merchant_account.name);
-
-    Ok(())
-}
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+use crate::error::PaymentError;
+use crate::events::PaymentProcessed;
+use crate::health::{require_initial_margin, sol_collateral};
+use crate::pricing::AssetPrice;
+use crate::state::{debit_vault, ProtocolConfig, SpendingAccount, MerchantAccount, PaymentReceipt, PaymentSource, Treasury, Vault, YieldAccount};
 
 #[derive(Accounts)]
+#[instruction(amount: u64, source: PaymentSource, order_id: Option<String>, nonce: u64)]
 pub struct ProcessPayment<'info> {
     // The user making the payment. They must sign and will pay the lamports.
     #[account(mut)]
     pub authority: Signer<'info>,
 
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    // The SOL/USD price used to convert the lamports paid into debt.
+    pub price_update: Account<'info, PriceUpdateV2>,
+
+    // The collateral backing the debt. It pays the merchant when paying from the vault.
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [b"vault", authority.key().as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    // Staked funds also back the debt. Users who never staked can omit it.
+    #[account(
+        has_one = authority,
+        seeds = [b"yield", authority.key().as_ref()],
+        bump = yield_account.bump
+    )]
+    pub yield_account: Option<Account<'info, YieldAccount>>,
+
     // The user's spending account, to verify they have enough limit.
     #[account(
         mut,
@@ -52,22 +46,159 @@ pub struct ProcessPayment<'info> {
     )]
     pub spending_account: Account<'info, SpendingAccount>,
 
-    // The merchant's account, to identify the recipient.
+    // The merchant's account, to identify the recipient. It must be paid in SOL
+    // and be allowed to take payments.
     #[account(
-        seeds = [b"merchant", merchant_authority.key().as_ref()],
-        bump = merchant_account.bump
+        mut,
+        seeds = [b"merchant", merchant_account.authority.as_ref()],
+        bump = merchant_account.bump,
+        constraint = merchant_account.accepted_mint.is_none() @ PaymentError::AssetNotAccepted,
+        constraint = merchant_account.verified @ PaymentError::MerchantNotVerified,
+        constraint = !merchant_account.suspended @ PaymentError::MerchantSuspended
     )]
     pub merchant_account: Account<'info, MerchantAccount>,
 
-    // The merchant's actual wallet that will receive the payment.
+    // The merchant's wallet that will receive the payment.
     // The `address` constraint is a powerful security check that ensures this
-    // wallet public key matches the `authority` stored in the merchant_account.
+    // wallet public key matches the `payout_wallet` stored in the merchant_account.
     #[account(
         mut,
-        address = merchant_account.authority
+        address = merchant_account.payout_wallet
     )]
     /// CHECK: This is the merchant's wallet, validated by the address constraint.
-    pub merchant_authority: AccountInfo<'info>,
+    pub payout_wallet: AccountInfo<'info>,
+
+    // Receives the protocol's merchant fee.
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = treasury.bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    // Proof of this payment, which the merchant can refund against. Paying an
+    // order ID that already has a receipt fails here.
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + PaymentReceipt::INIT_SPACE,
+        seeds = [
+            b"receipt",
+            merchant_account.key().as_ref(),
+            &PaymentReceipt::seed(order_id.as_deref(), &authority.key(), nonce)
+        ],
+        bump
+    )]
+    pub receipt: Account<'info, PaymentReceipt>,
 
     pub system_program: Program<'info, System>,
 }
+
+impl<'info> ProcessPayment<'info> {
+    // `remaining_accounts` carries the user's SPL collateral, see `token_collateral_value`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn process_payment(
+        &mut self,
+        amount: u64,
+        source: PaymentSource,
+        order_id: Option<String>,
+        nonce: u64,
+        memo: Option<String>,
+        bumps: &ProcessPaymentBumps,
+        remaining_accounts: &[AccountInfo],
+    ) -> Result<()> {
+        PaymentReceipt::validate_reference(&order_id, &memo)?;
+
+        // --- 1. Authorize the Spend ---
+        // The payment is in lamports but the limit is in micro-USD, so convert
+        // at the upper price bound before checking it.
+        let sol_price = AssetPrice::load_sol_usd(&self.price_update, &self.config)?;
+        let amount_in_usd = u64::try_from(sol_price.debt_value_usd(amount)?).unwrap();
+
+        // This logic is identical to our `authorize_spend` instruction.
+        let now = Clock::get()?.unix_timestamp;
+        let borrow_index = self.treasury.accrue_interest(now, self.config.borrow_rate_bps);
+        self.spending_account.accrue_interest(borrow_index);
+        self.spending_account.record_spend(amount_in_usd, now)?;
+
+        msg!("Spend of {} micro-USD authorized.", amount_in_usd);
+
+        // --- 2. Pay the Merchant ---
+        // The protocol fee is split off and the merchant receives the rest.
+        let fee = self.merchant_account.fee_for(amount, &self.config);
+        let merchant_amount = amount.checked_sub(fee).unwrap();
+
+        match source {
+            PaymentSource::Wallet => {
+                // We now perform CPIs to the System Program to transfer SOL from the user
+                // to the merchant's payout wallet and the treasury.
+                for (to, lamports) in [
+                    (self.payout_wallet.to_account_info(), merchant_amount),
+                    (self.treasury.to_account_info(), fee),
+                ] {
+                    let cpi_accounts = anchor_lang::system_program::Transfer {
+                        from: self.authority.to_account_info(),
+                        to,
+                    };
+                    let cpi_context = CpiContext::new(
+                        self.system_program.to_account_info(),
+                        cpi_accounts,
+                    );
+                    anchor_lang::system_program::transfer(cpi_context, lamports)?;
+                }
+            }
+            PaymentSource::Vault => {
+                debit_vault(&mut self.vault, &self.payout_wallet, merchant_amount)?;
+                debit_vault(&mut self.vault, &self.treasury.to_account_info(), fee)?;
+            }
+        }
+
+        // The limit was set at an earlier price, and a vault payment also takes
+        // collateral out, so what is left must still back the debt, this payment
+        // included, at the current price.
+        require_initial_margin(
+            self.spending_account.amount_spent,
+            sol_collateral(&self.vault, self.yield_account.as_deref(), 0)?,
+            remaining_accounts,
+            &self.vault,
+            &self.price_update,
+            &self.config,
+            None,
+        )?;
+        self.treasury.collect_fee(fee);
+
+        // --- 3. Issue the Receipt ---
+        let merchant_account = self.merchant_account.key();
+        PaymentReceipt::issue(&mut self.receipt, &mut self.merchant_account, PaymentReceipt {
+            payer: self.authority.key(),
+            merchant_account,
+            source,
+            mint: None,
+            amount,
+            fee,
+            amount_usd: amount_in_usd,
+            refunded: 0,
+            refunded_usd: 0,
+            created_at: now,
+            nonce: if order_id.is_some() { 0 } else { nonce },
+            order_id,
+            memo,
+            rent_payer: self.authority.key(),
+            bump: bumps.receipt,
+        });
+
+        msg!("Transferred {} lamports to merchant {} and {} to the treasury.", merchant_amount, self.merchant_account.name, fee);
+
+        emit!(PaymentProcessed {
+            user: self.authority.key(),
+            merchant_account: self.merchant_account.key(),
+            mint: None,
+            amount,
+            fee,
+            amount_usd: amount_in_usd,
+            order_id: self.receipt.order_id.clone(),
+        });
+
+        Ok(())
+    }
+}
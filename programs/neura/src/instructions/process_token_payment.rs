@@ -0,0 +1,251 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked};
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+
+use crate::error::{AmountError, PaymentError};
+use crate::events::PaymentProcessed;
+use crate::health::{require_initial_margin, sol_collateral};
+use crate::pricing::AssetPrice;
+use crate::state::{CollateralAsset, MerchantAccount, PaymentReceipt, PaymentSource, ProtocolConfig, SpendingAccount, TokenVault, Treasury, Vault, YieldAccount};
+
+#[derive(Accounts)]
+#[instruction(amount: u64, order_id: Option<String>, nonce: u64)]
+pub struct ProcessTokenPayment<'info> {
+    // The user making the payment. The tokens come out of their token vault, and
+    // they pay for the receipt.
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    // The user's vault PDA, which owns the token account being debited.
+    #[account(
+        has_one = authority,
+        seeds = [b"vault", authority.key().as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    // Staked funds also back the debt. Users who never staked can omit it.
+    #[account(
+        has_one = authority,
+        seeds = [b"yield", authority.key().as_ref()],
+        bump = yield_account.bump
+    )]
+    pub yield_account: Option<Account<'info, YieldAccount>>,
+
+    // The mint the merchant accepts.
+    #[account(
+        constraint = merchant_account.accepted_mint == Some(mint.key()) @ PaymentError::AssetNotAccepted
+    )]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    // The registered asset supplies the price feed used to convert the payment into debt.
+    #[account(
+        seeds = [b"collateral", mint.key().as_ref()],
+        bump = collateral_asset.bump
+    )]
+    pub collateral_asset: Account<'info, CollateralAsset>,
+
+    pub price_update: Account<'info, PriceUpdateV2>,
+
+    // Values the SOL collateral in the initial-margin check.
+    pub sol_price_update: Account<'info, PriceUpdateV2>,
+
+    #[account(
+        mut,
+        has_one = authority,
+        has_one = mint,
+        seeds = [b"token_vault", authority.key().as_ref(), mint.key().as_ref()],
+        bump = token_vault.bump
+    )]
+    pub token_vault: Account<'info, TokenVault>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault,
+        associated_token::token_program = token_program
+    )]
+    pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    // The user's spending account, to verify they have enough limit.
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [b"spending", authority.key().as_ref()],
+        bump = spending_account.bump
+    )]
+    pub spending_account: Account<'info, SpendingAccount>,
+
+    // Holds the borrow index that interest on the spend is accrued against.
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = treasury.bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    #[account(
+        mut,
+        seeds = [b"merchant", merchant_account.authority.as_ref()],
+        bump = merchant_account.bump,
+        constraint = merchant_account.verified @ PaymentError::MerchantNotVerified,
+        constraint = !merchant_account.suspended @ PaymentError::MerchantSuspended
+    )]
+    pub merchant_account: Account<'info, MerchantAccount>,
+
+    // Any token account of the accepted mint owned by the merchant's payout wallet.
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = merchant_account.payout_wallet,
+        token::token_program = token_program
+    )]
+    pub merchant_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    // The treasury's token account for the mint, which receives the protocol's
+    // merchant fee. Anyone can create it through the associated token program.
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = treasury,
+        associated_token::token_program = token_program
+    )]
+    pub treasury_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    // Proof of this payment, keyed like the receipts of `process_payment`. Paying
+    // an order ID that already has a receipt fails here.
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + PaymentReceipt::INIT_SPACE,
+        seeds = [
+            b"receipt",
+            merchant_account.key().as_ref(),
+            &PaymentReceipt::seed(order_id.as_deref(), &authority.key(), nonce)
+        ],
+        bump
+    )]
+    pub receipt: Account<'info, PaymentReceipt>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> ProcessTokenPayment<'info> {
+    // `remaining_accounts` carries the user's SPL collateral, see `token_collateral_value`.
+    // This token vault is one of them.
+    pub fn process_token_payment(
+        &mut self,
+        amount: u64,
+        order_id: Option<String>,
+        nonce: u64,
+        memo: Option<String>,
+        bumps: &ProcessTokenPaymentBumps,
+        remaining_accounts: &[AccountInfo],
+    ) -> Result<()> {
+        PaymentReceipt::validate_reference(&order_id, &memo)?;
+
+        require!(
+            self.token_vault.balance >= amount,
+            AmountError::InsufficientFunds
+        );
+
+        // --- 1. Authorize the Spend ---
+        // The limit is in micro-USD, so value the tokens at the upper price bound.
+        let price = AssetPrice::load(
+            &self.price_update,
+            &self.collateral_asset.price_feed_id,
+            self.collateral_asset.decimals,
+            &self.config,
+        )?;
+        let amount_in_usd = u64::try_from(price.debt_value_usd(amount)?).unwrap();
+
+        let now = Clock::get()?.unix_timestamp;
+        let borrow_index = self.treasury.accrue_interest(now, self.config.borrow_rate_bps);
+        self.spending_account.accrue_interest(borrow_index);
+        self.spending_account.record_spend(amount_in_usd, now)?;
+
+        msg!("Spend of {} micro-USD authorized.", amount_in_usd);
+
+        // The token vault is collateral, so what is left must still back the debt,
+        // this payment included, at the initial margin. The balance read from the
+        // remaining accounts does not include the payment yet.
+        require_initial_margin(
+            self.spending_account.amount_spent,
+            sol_collateral(&self.vault, self.yield_account.as_deref(), 0)?,
+            remaining_accounts,
+            &self.vault,
+            &self.sol_price_update,
+            &self.config,
+            Some((self.mint.key(), amount)),
+        )?;
+
+        // --- 2. Pay the Merchant ---
+        // The protocol fee is split off in tokens and the merchant receives the rest.
+        let fee = self.merchant_account.fee_for(amount, &self.config);
+        let merchant_amount = amount.checked_sub(fee).unwrap();
+
+        // The vault PDA owns the token account, so it signs the transfers.
+        let authority_key = self.authority.key();
+        let signer_seeds: &[&[&[u8]]] = &[&[b"vault", authority_key.as_ref(), &[self.vault.bump]]];
+
+        for (to, tokens) in [
+            (self.merchant_token_account.to_account_info(), merchant_amount),
+            (self.treasury_token_account.to_account_info(), fee),
+        ] {
+            let program = self.token_program.to_account_info();
+            let accounts = TransferChecked{
+                from: self.vault_token_account.to_account_info(),
+                mint: self.mint.to_account_info(),
+                to,
+                authority: self.vault.to_account_info(),
+            };
+
+            let ctx = CpiContext::new_with_signer(program, accounts, signer_seeds);
+
+            transfer_checked(ctx, tokens, self.mint.decimals)?;
+        }
+
+        self.token_vault.balance = self.token_vault.balance.checked_sub(amount).unwrap();
+
+        // --- 3. Issue the Receipt ---
+        let merchant_account = self.merchant_account.key();
+        PaymentReceipt::issue(&mut self.receipt, &mut self.merchant_account, PaymentReceipt {
+            payer: self.authority.key(),
+            merchant_account,
+            source: PaymentSource::Vault,
+            mint: Some(self.mint.key()),
+            amount,
+            fee,
+            amount_usd: amount_in_usd,
+            refunded: 0,
+            refunded_usd: 0,
+            created_at: now,
+            nonce: if order_id.is_some() { 0 } else { nonce },
+            order_id,
+            memo,
+            rent_payer: self.authority.key(),
+            bump: bumps.receipt,
+        });
+
+        msg!("Transferred {} tokens to merchant {} and {} to the treasury.", merchant_amount, self.merchant_account.name, fee);
+
+        emit!(PaymentProcessed {
+            user: self.authority.key(),
+            merchant_account: self.merchant_account.key(),
+            mint: Some(self.mint.key()),
+            amount,
+            fee,
+            amount_usd: amount_in_usd,
+            order_id: self.receipt.order_id.clone(),
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+use anchor_lang::prelude::*;
+
+use crate::error::PaymentError;
+use crate::events::PaymentRefunded;
+use crate::state::{credit_vault, MerchantAccount, PaymentReceipt, PaymentSource, ProtocolConfig, SpendingAccount, Treasury, Vault};
+
+#[derive(Accounts)]
+pub struct Refund<'info> {
+    // The merchant giving the money back. The refund is paid from this wallet.
+    #[account(mut)]
+    pub merchant: Signer<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        seeds = [b"merchant", merchant.key().as_ref()],
+        bump = merchant_account.bump
+    )]
+    pub merchant_account: Account<'info, MerchantAccount>,
+
+    #[account(
+        mut,
+        has_one = merchant_account,
+        has_one = payer,
+        constraint = receipt.mint.is_none() @ PaymentError::TokenRefundUnsupported
+    )]
+    pub receipt: Account<'info, PaymentReceipt>,
+
+    /// CHECK: The user who made the payment, validated against the receipt.
+    #[account(mut)]
+    pub payer: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"spending", payer.key().as_ref()],
+        bump = spending_account.bump
+    )]
+    pub spending_account: Account<'info, SpendingAccount>,
+
+    // Holds the borrow index that interest on the spend is accrued against.
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = treasury.bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    // Only needed when the payment came out of the vault, which the refund goes back
+    // into. Once the vault has been closed, the refund goes to the payer instead.
+    #[account(
+        mut,
+        seeds = [b"vault", payer.key().as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Option<Account<'info, Vault>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> Refund<'info> {
+    pub fn refund(&mut self, amount: u64) -> Result<()> {
+        // Only what the merchant received can be refunded; the protocol keeps its fee.
+        require!(
+            self.receipt.refunded.checked_add(amount).unwrap() <= self.receipt.refundable(),
+            PaymentError::RefundExceedsPayment
+        );
+
+        // --- 1. Return the Funds ---
+        match (&self.receipt.source, self.vault.as_mut()) {
+            (PaymentSource::Vault, Some(vault)) => {
+                transfer_lamports(&self.merchant, &self.system_program, vault.to_account_info(), amount)?;
+                credit_vault(vault, amount)?;
+            }
+            _ => {
+                transfer_lamports(&self.merchant, &self.system_program, self.payer.to_account_info(), amount)?;
+            }
+        }
+
+        // --- 2. Release the Debt ---
+        let now = Clock::get()?.unix_timestamp;
+        let borrow_index = self.treasury.accrue_interest(now, self.config.borrow_rate_bps);
+        self.spending_account.accrue_interest(borrow_index);
+        let refund_usd = self.receipt.refund_usd(amount);
+        self.spending_account.release_spend(refund_usd, self.receipt.created_at, now);
+
+        self.receipt.refunded = self.receipt.refunded.checked_add(amount).unwrap();
+        self.receipt.refunded_usd = self.receipt.refunded_usd.checked_add(refund_usd).unwrap();
+
+        msg!("Refunded {} lamports ({} micro-USD) to {}.", amount, refund_usd, self.payer.key());
+
+        emit!(PaymentRefunded {
+            user: self.payer.key(),
+            merchant_account: self.merchant_account.key(),
+            receipt: self.receipt.key(),
+            amount,
+            amount_usd: refund_usd,
+        });
+
+        Ok(())
+    }
+}
+
+fn transfer_lamports<'info>(
+    from: &Signer<'info>,
+    system_program: &Program<'info, System>,
+    to: AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    let cpi_accounts = anchor_lang::system_program::Transfer {
+        from: from.to_account_info(),
+        to,
+    };
+    let cpi_context = CpiContext::new(
+        system_program.to_account_info(),
+        cpi_accounts,
+    );
+    anchor_lang::system_program::transfer(cpi_context, amount)
+}
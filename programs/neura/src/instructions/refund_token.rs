@@ -0,0 +1,140 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked};
+
+use crate::error::PaymentError;
+use crate::events::PaymentRefunded;
+use crate::state::{MerchantAccount, PaymentReceipt, ProtocolConfig, SpendingAccount, TokenVault, Treasury};
+
+#[derive(Accounts)]
+pub struct RefundToken<'info> {
+    // The merchant giving the tokens back. The refund is paid from its token account.
+    pub merchant: Signer<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        seeds = [b"merchant", merchant.key().as_ref()],
+        bump = merchant_account.bump
+    )]
+    pub merchant_account: Account<'info, MerchantAccount>,
+
+    #[account(
+        mut,
+        has_one = merchant_account,
+        has_one = payer,
+        constraint = receipt.mint == Some(mint.key()) @ PaymentError::AssetNotAccepted
+    )]
+    pub receipt: Account<'info, PaymentReceipt>,
+
+    /// CHECK: The user who made the payment, validated against the receipt.
+    pub payer: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"spending", payer.key().as_ref()],
+        bump = spending_account.bump
+    )]
+    pub spending_account: Account<'info, SpendingAccount>,
+
+    // Holds the borrow index that interest on the spend is accrued against.
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = treasury.bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    // Token payments come out of the payer's token vault, so the refund goes back
+    // into it. It must still be open.
+    #[account(
+        mut,
+        has_one = mint,
+        seeds = [b"token_vault", payer.key().as_ref(), mint.key().as_ref()],
+        bump = token_vault.bump
+    )]
+    pub token_vault: Account<'info, TokenVault>,
+
+    /// CHECK: The payer's vault PDA, which owns the token account refunded into.
+    #[account(
+        seeds = [b"vault", payer.key().as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault,
+        associated_token::token_program = token_program
+    )]
+    pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    // Any token account of the mint the merchant owns.
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = merchant,
+        token::token_program = token_program
+    )]
+    pub merchant_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> RefundToken<'info> {
+    pub fn refund_token(&mut self, amount: u64) -> Result<()> {
+        // Only what the merchant received can be refunded; the protocol keeps its fee.
+        require!(
+            self.receipt.refunded.checked_add(amount).unwrap() <= self.receipt.refundable(),
+            PaymentError::RefundExceedsPayment
+        );
+
+        // --- 1. Return the Tokens ---
+        let balance_before = self.vault_token_account.amount;
+
+        let program = self.token_program.to_account_info();
+        let accounts = TransferChecked{
+            from: self.merchant_token_account.to_account_info(),
+            mint: self.mint.to_account_info(),
+            to: self.vault_token_account.to_account_info(),
+            authority: self.merchant.to_account_info(),
+        };
+
+        let ctx = CpiContext::new(program, accounts);
+
+        transfer_checked(ctx, amount, self.mint.decimals)?;
+
+        // Like `deposit_token`, credit what arrived after any Token-2022 transfer fee.
+        self.vault_token_account.reload()?;
+        let received = self.vault_token_account.amount.checked_sub(balance_before).unwrap();
+        self.token_vault.balance = self.token_vault.balance.checked_add(received).unwrap();
+
+        // --- 2. Release the Debt ---
+        let now = Clock::get()?.unix_timestamp;
+        let borrow_index = self.treasury.accrue_interest(now, self.config.borrow_rate_bps);
+        self.spending_account.accrue_interest(borrow_index);
+        let refund_usd = self.receipt.refund_usd(amount);
+        self.spending_account.release_spend(refund_usd, self.receipt.created_at, now);
+
+        self.receipt.refunded = self.receipt.refunded.checked_add(amount).unwrap();
+        self.receipt.refunded_usd = self.receipt.refunded_usd.checked_add(refund_usd).unwrap();
+
+        msg!("Refunded {} tokens ({} micro-USD) to {}.", amount, refund_usd, self.payer.key());
+
+        emit!(PaymentRefunded {
+            user: self.payer.key(),
+            merchant_account: self.merchant_account.key(),
+            receipt: self.receipt.key(),
+            amount,
+            amount_usd: refund_usd,
+        });
+
+        Ok(())
+    }
+}
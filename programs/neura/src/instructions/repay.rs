@@ -0,0 +1,85 @@
+use anchor_lang::{prelude::*, system_program::{transfer, Transfer}};
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+
+use crate::{error::AmountError, events::DebtRepaid, pricing::AssetPrice, ProtocolConfig, SpendingAccount, Treasury};
+
+#[derive(Accounts)]
+pub struct Repay<'info> {
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [b"spending", authority.key().as_ref()],
+        bump = spending_account.bump
+    )]
+    pub spending_account: Account<'info, SpendingAccount>,
+
+    pub price_update: Account<'info, PriceUpdateV2>,
+
+    // Repayments flow back into the shared pool that funded the spend.
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = treasury.bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    pub system_program: Program<'info, System>,
+}
+
+impl <'info> Repay <'info> {
+
+    // Repays up to `amount` lamports worth of outstanding spend. Any part of
+    // `amount` beyond what is owed is left in the user's wallet.
+    pub fn repay(&mut self, amount: u64) -> Result<()>{
+
+        let now = Clock::get()?.unix_timestamp;
+        let borrow_index = self.treasury.accrue_interest(now, self.config.borrow_rate_bps);
+        self.spending_account.accrue_interest(borrow_index);
+
+        let debt = self.spending_account.amount_spent;
+        require!(debt > 0, AmountError::NothingToRepay);
+
+        // Repaid lamports are valued at the lower bound, like any other collateral.
+        let sol_price = AssetPrice::load_sol_usd(&self.price_update, &self.config)?;
+        let value_in_usd = sol_price.collateral_value_usd(amount)?;
+
+        let (lamports, amount_repaid) = if value_in_usd >= debt as u128 {
+            // Full repayment: only take the lamports needed to clear the debt.
+            let lamports_needed = sol_price.collateral_value_amount(debt as u128)?;
+            (lamports_needed.min(amount as u128) as u64, debt)
+        } else {
+            (amount, value_in_usd as u64)
+        };
+
+        let program = self.system_program.to_account_info();
+        let accounts = Transfer{
+            from: self.authority.to_account_info(),
+            to: self.treasury.to_account_info(),
+        };
+
+        let ctx = CpiContext::new(program, accounts);
+
+        transfer(ctx, lamports)?;
+
+        self.spending_account.amount_spent = debt.checked_sub(amount_repaid).unwrap();
+
+        emit!(DebtRepaid {
+            user: self.authority.key(),
+            lamports,
+            amount_repaid,
+            remaining_debt: self.spending_account.amount_spent,
+        });
+
+        Ok(())
+    }
+}
@@ -1,15 +1,27 @@
 use anchor_lang::prelude::*;
-use crate::state::SpendingAccount;
+use crate::error::ProtocolError;
+use crate::state::{ProtocolConfig, SpendingAccount};
 
+// Admin override that clears a user's current spend window early. Windows
+// otherwise roll over on their own in `authorize_spend` and `process_payment`.
 #[derive(Accounts)]
 pub struct ResetSpendTracker<'info> {
 
-    pub authority: Signer<'info>,
+    pub admin: Signer<'info>,
+
+    #[account(
+        has_one = admin @ ProtocolError::Unauthorized,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    /// CHECK: The owner of the spending account. Only its key is used to derive the PDA.
+    pub user: AccountInfo<'info>,
 
     #[account(
         mut,
-        has_one = authority,
-        seeds = [b"spending", authority.key().as_ref()],
+        seeds = [b"spending", user.key().as_ref()],
         bump = spending_account.bump
     )]
     pub spending_account: Account<'info, SpendingAccount>,
@@ -18,7 +30,8 @@ pub struct ResetSpendTracker<'info> {
 impl <'info> ResetSpendTracker<'info> {
     
     pub fn reset_spend_tracker(&mut self) -> Result<()> {
-        self.spending_account.amount_spent = 0;
+        self.spending_account.window_start = Clock::get()?.unix_timestamp;
+        self.spending_account.window_spent = 0;
         Ok(())
     }
-}
\ No newline at end of file
+}
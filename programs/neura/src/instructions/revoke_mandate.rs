@@ -0,0 +1,40 @@
+use anchor_lang::prelude::*;
+
+use crate::{MerchantAccount, PaymentMandate};
+
+#[derive(Accounts)]
+pub struct RevokeMandate<'info> {
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"merchant", merchant_account.authority.as_ref()],
+        bump = merchant_account.bump
+    )]
+    pub merchant_account: Account<'info, MerchantAccount>,
+
+    // Closing the mandate stops any further charges and refunds its rent.
+    #[account(
+        mut,
+        close = authority,
+        has_one = authority,
+        has_one = merchant_account,
+        seeds = [b"mandate", authority.key().as_ref(), merchant_account.key().as_ref()],
+        bump = mandate.bump
+    )]
+    pub mandate: Account<'info, PaymentMandate>,
+}
+
+impl <'info> RevokeMandate <'info> {
+
+    pub fn revoke_mandate(&mut self) -> Result<()>{
+
+        self.merchant_account.open_mandates = self.merchant_account.open_mandates.checked_sub(1).unwrap();
+
+        msg!("Mandate for merchant {} revoked.", self.mandate.merchant_account);
+
+        Ok(())
+    }
+}
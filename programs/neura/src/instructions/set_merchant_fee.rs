@@ -0,0 +1,35 @@
+use anchor_lang::prelude::*;
+use crate::error::ProtocolError;
+use crate::state::{MerchantAccount, ProtocolConfig};
+use crate::MAX_MERCHANT_FEE_BPS;
+
+#[derive(Accounts)]
+pub struct SetMerchantFee<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        has_one = admin @ ProtocolError::Unauthorized,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"merchant", merchant_account.authority.as_ref()],
+        bump = merchant_account.bump
+    )]
+    pub merchant_account: Account<'info, MerchantAccount>,
+}
+
+impl<'info> SetMerchantFee<'info> {
+    // `None` puts the merchant back on the global `merchant_fee_bps`.
+    pub fn set_merchant_fee(&mut self, fee_bps: Option<u64>) -> Result<()> {
+        if let Some(fee_bps) = fee_bps {
+            require!(fee_bps <= MAX_MERCHANT_FEE_BPS, ProtocolError::InvalidConfig);
+        }
+        self.merchant_account.fee_bps = fee_bps;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,34 @@
+use anchor_lang::prelude::*;
+use crate::error::ProtocolError;
+use crate::state::{MerchantAccount, ProtocolConfig};
+
+#[derive(Accounts)]
+pub struct SetMerchantStatus<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        has_one = admin @ ProtocolError::Unauthorized,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"merchant", merchant_account.authority.as_ref()],
+        bump = merchant_account.bump
+    )]
+    pub merchant_account: Account<'info, MerchantAccount>,
+}
+
+impl<'info> SetMerchantStatus<'info> {
+    // Merchants can only take payments while verified and not suspended.
+    pub fn set_merchant_status(&mut self, verified: bool, suspended: bool) -> Result<()> {
+        self.merchant_account.verified = verified;
+        self.merchant_account.suspended = suspended;
+
+        msg!("Merchant {}: verified = {}, suspended = {}.", self.merchant_account.name, verified, suspended);
+
+        Ok(())
+    }
+}
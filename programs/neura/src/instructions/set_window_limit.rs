@@ -0,0 +1,34 @@
+use anchor_lang::prelude::*;
+
+use crate::{events::WindowLimitUpdated, SpendingAccount};
+
+// Lets the owner cap how much can be spent within one spend window.
+#[derive(Accounts)]
+pub struct SetWindowLimit<'info> {
+
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [b"spending", authority.key().as_ref()],
+        bump = spending_account.bump
+    )]
+    pub spending_account: Account<'info, SpendingAccount>,
+}
+
+impl <'info> SetWindowLimit <'info> {
+
+    // `window_limit` is in micro-USD. Spend already made in the current window
+    // still counts against the new limit.
+    pub fn set_window_limit(&mut self, window_limit: u64) -> Result<()> {
+        self.spending_account.window_limit = window_limit;
+
+        emit!(WindowLimitUpdated {
+            user: self.authority.key(),
+            window_limit,
+        });
+
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 use anchor_lang::prelude::*;
-use crate::state::{Vault, Treasury, YieldAccount};
+use crate::state::{debit_vault, Vault, Treasury, YieldAccount};
 use crate::error::AmountError;
+use crate::events::Staked;
 
 #[derive(Accounts)]
 pub struct Stake<'info> {
@@ -17,11 +18,10 @@ pub struct Stake<'info> {
     )]
     pub vault: Account<'info, Vault>,
 
-    // The central treasury to deposit funds into.
+    // The shared protocol treasury that pools every user's stake.
     #[account(
         mut,
-        has_one = authority,
-        seeds = [b"treasury", authority.key().as_ref()],
+        seeds = [b"treasury"],
         bump = treasury.bump
     )]
     pub treasury: Account<'info, Treasury>,
@@ -45,25 +45,24 @@ impl<'info> Stake<'info> {
         require!(self.vault.balance >= amount, AmountError::InsufficientFunds);
 
         // --- Lamport Transfer ---
-        // We manually move the lamports from the user's vault PDA to the treasury PDA.
-        // This is the same pattern we used in the `withdraw` instruction.
-
-        // 1. Debit from the vault.
-        let vault_lamports = self.vault.to_account_info().lamports();
-        **self.vault.to_account_info().try_borrow_mut_lamports()? = vault_lamports
-            .checked_sub(amount)
-            .ok_or(AmountError::InsufficientFunds)?;
-
-        // 2. Credit to the treasury.
-        let treasury_lamports = self.treasury.to_account_info().lamports();
-        **self.treasury.to_account_info().try_borrow_mut_lamports()? = treasury_lamports
-            .checked_add(amount)
-            .unwrap();
+        // Move the lamports from the user's vault PDA to the treasury PDA. This also
+        // updates the vault's balance.
+        debit_vault(&mut self.vault, &self.treasury.to_account_info(), amount)?;
 
         // --- Update State ---
+        // Settle rewards on the old stake before it changes size.
+        self.yield_account.settle_rewards(self.treasury.reward_per_share)?;
+
         // Now we update our own account records to reflect the transfer.
-        self.vault.balance = self.vault.balance.checked_sub(amount).unwrap();
         self.yield_account.staked_amount = self.yield_account.staked_amount.checked_add(amount).unwrap();
+        self.treasury.total_staked = self.treasury.total_staked.checked_add(amount).unwrap();
+
+        emit!(Staked {
+            user: self.authority.key(),
+            amount,
+            staked_amount: self.yield_account.staked_amount,
+            total_staked: self.treasury.total_staked,
+        });
 
         Ok(())
     }
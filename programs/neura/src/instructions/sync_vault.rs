@@ -0,0 +1,46 @@
+use anchor_lang::prelude::*;
+use crate::error::ProtocolError;
+use crate::events::VaultSynced;
+use crate::state::{vault_lamports_available, ProtocolConfig, Vault};
+
+#[derive(Accounts)]
+pub struct SyncVault<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        has_one = admin @ ProtocolError::Unauthorized,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    /// CHECK: The owner of the vault. Only its key is used to derive the vault PDA.
+    pub user: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+}
+
+impl<'info> SyncVault<'info> {
+    // Resets the balance to what the vault actually holds, in either direction.
+    // Vault instructions already pick up lamports sent in directly, but refuse to
+    // run on a vault holding less than its balance until it is synced.
+    pub fn sync_vault(&mut self) -> Result<()> {
+        let previous_balance = self.vault.balance;
+        self.vault.balance = vault_lamports_available(&self.vault)?;
+
+        msg!("Vault of {} synced from {} to {} lamports.", self.user.key(), previous_balance, self.vault.balance);
+
+        emit!(VaultSynced {
+            user: self.user.key(),
+            previous_balance,
+            balance: self.vault.balance,
+        });
+
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 use anchor_lang::prelude::*;
-use crate::state::{Vault, Treasury, YieldAccount};
+use crate::state::{credit_vault, Vault, Treasury, YieldAccount};
 use crate::error::AmountError;
+use crate::events::Unstaked;
 
 #[derive(Accounts)]
 pub struct Unstake<'info> {
@@ -17,8 +18,7 @@ pub struct Unstake<'info> {
 
     #[account(
         mut,
-        has_one = authority,
-        seeds = [b"treasury", authority.key().as_ref()],
+        seeds = [b"treasury"],
         bump = treasury.bump
     )]
     pub treasury: Account<'info, Treasury>,
@@ -33,6 +33,9 @@ pub struct Unstake<'info> {
 }
 
 impl<'info> Unstake<'info> {
+    // Unstaked funds return to the vault, which counts as collateral just like the
+    // stake (see `sol_collateral`), so unstaking never changes a position's health
+    // and needs no margin check.
     pub fn unstake_funds(&mut self, amount: u64) -> Result<()> {
         // Security Check
         require!(self.yield_account.staked_amount >= amount, AmountError::InsufficientFunds);
@@ -51,8 +54,19 @@ impl<'info> Unstake<'info> {
             .unwrap();
 
         // --- Update State ---
-        self.vault.balance = self.vault.balance.checked_add(amount).unwrap();
+        // Settle rewards on the old stake before it changes size.
+        self.yield_account.settle_rewards(self.treasury.reward_per_share)?;
+
+        credit_vault(&mut self.vault, amount)?;
         self.yield_account.staked_amount = self.yield_account.staked_amount.checked_sub(amount).unwrap();
+        self.treasury.total_staked = self.treasury.total_staked.checked_sub(amount).unwrap();
+
+        emit!(Unstaked {
+            user: self.authority.key(),
+            amount,
+            staked_amount: self.yield_account.staked_amount,
+            total_staked: self.treasury.total_staked,
+        });
 
         Ok(())
     }
@@ -0,0 +1,34 @@
+use anchor_lang::prelude::*;
+
+use crate::{error::ProtocolError, CollateralAsset, CollateralAssetParams, ProtocolConfig};
+
+#[derive(Accounts)]
+pub struct UpdateCollateralAsset<'info> {
+
+    pub admin: Signer<'info>,
+
+    #[account(
+        has_one = admin @ ProtocolError::Unauthorized,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"collateral", collateral_asset.mint.as_ref()],
+        bump = collateral_asset.bump
+    )]
+    pub collateral_asset: Account<'info, CollateralAsset>,
+}
+
+impl <'info> UpdateCollateralAsset <'info> {
+
+    pub fn update_collateral_asset(&mut self, params: CollateralAssetParams) -> Result<()>{
+
+        params.validate()?;
+        self.collateral_asset.apply(params);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,41 @@
+use anchor_lang::prelude::*;
+
+use crate::{ConfigParams, ProtocolConfig, Treasury};
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    // Interest up to now is accrued at the old rate before a new one applies, so
+    // the config can only be updated once the treasury exists.
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = treasury.bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+}
+
+impl <'info> UpdateConfig <'info> {
+
+    pub fn update_config(&mut self, params: ConfigParams) -> Result<()>{
+
+        params.validate()?;
+
+        let now = Clock::get()?.unix_timestamp;
+        self.treasury.accrue_interest(now, self.config.borrow_rate_bps);
+
+        self.config.apply(params);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,22 @@
+use anchor_lang::prelude::*;
+use crate::state::{MerchantAccount, MerchantProfileParams};
+
+#[derive(Accounts)]
+pub struct UpdateMerchant<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [b"merchant", authority.key().as_ref()],
+        bump = merchant_account.bump
+    )]
+    pub merchant_account: Account<'info, MerchantAccount>,
+}
+
+impl<'info> UpdateMerchant<'info> {
+    pub fn update_merchant(&mut self, params: MerchantProfileParams) -> Result<()> {
+        self.merchant_account.apply(params)?;
+        Ok(())
+    }
+}
@@ -1,12 +1,24 @@
 use anchor_lang::prelude::*;
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
 
-use crate::{SpendingAccount, Vault};
+use crate::{
+    events::SpendingLimitUpdated,
+    health::{sol_collateral, token_collateral_value},
+    pricing::AssetPrice,
+    ProtocolConfig, SpendingAccount, Vault, YieldAccount,
+};
 
 #[derive(Accounts)]
 pub struct UpdateSpendingLimit<'info> {
 
     pub authority: Signer<'info>,
 
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
     #[account(
         has_one = authority,
         seeds = [b"vault", authority.key().as_ref()],
@@ -14,6 +26,16 @@ pub struct UpdateSpendingLimit<'info> {
     )]
     pub vault: Account<'info, Vault>,
 
+    // Staked funds also count as collateral. Users who never staked can omit it.
+    #[account(
+        has_one = authority,
+        seeds = [b"yield", authority.key().as_ref()],
+        bump = yield_account.bump
+    )]
+    pub yield_account: Option<Account<'info, YieldAccount>>,
+
+    pub price_update: Account<'info, PriceUpdateV2>,
+
     #[account(
         mut,
         has_one = authority,
@@ -26,11 +48,29 @@ pub struct UpdateSpendingLimit<'info> {
 
 impl <'info> UpdateSpendingLimit <'info> {
 
-    pub fn update_spending_limit(&mut self) -> Result<()>{
+    // `remaining_accounts` carries any SPL collateral, see `token_collateral_value`.
+    pub fn update_spending_limit(&mut self, remaining_accounts: &[AccountInfo]) -> Result<()>{
+
+        let collateral_lamports = sol_collateral(&self.vault, self.yield_account.as_deref(), 0)?;
+
+        // The limit is in the spending account's debt denomination (micro-USD).
+        let sol_price = AssetPrice::load_sol_usd(&self.price_update, &self.config)?;
+        let collateral_in_usd = sol_price.collateral_value_usd(collateral_lamports)?;
+
+        let mut collateral = token_collateral_value(remaining_accounts, &self.vault, &self.config)?;
+        collateral.add_sol(collateral_in_usd, &self.config)?;
 
-        let new_limit = self.vault.balance / 2;
+        // Collateral worth more than u64::MAX micro-USD caps the limit rather than aborting.
+        let new_limit = u64::try_from(collateral.borrow_capacity).unwrap_or(u64::MAX);
         self.spending_account.spending_limit = new_limit;
 
+        msg!("Spending limit set to {} micro-USD.", new_limit);
+
+        emit!(SpendingLimitUpdated {
+            user: self.authority.key(),
+            spending_limit: self.spending_account.spending_limit,
+        });
+
         Ok(())
     }
-}
\ No newline at end of file
+}
@@ -1,11 +1,24 @@
 use anchor_lang::prelude::*;
-use crate::{error::AmountError, state::Vault};
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+
+use crate::{
+    error::{AmountError, ProtocolError},
+    events::Withdrawn,
+    health::{require_initial_margin, sol_collateral},
+    state::{debit_vault, ProtocolConfig, SpendingAccount, Treasury, Vault, YieldAccount},
+};
 
 #[derive(Accounts)]
 pub struct Withdraw<'info> {
     #[account(mut)]
     pub authority: Signer<'info>,
 
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
     #[account(
         mut,
         has_one = authority,
@@ -14,61 +27,83 @@ pub struct Withdraw<'info> {
     )]
     pub vault: Account<'info, Vault>,
 
+    // Staked funds also back the debt. Users who never staked can omit it.
+    #[account(
+        has_one = authority,
+        seeds = [b"yield", authority.key().as_ref()],
+        bump = yield_account.bump
+    )]
+    pub yield_account: Option<Account<'info, YieldAccount>>,
+
+    // The collateral left behind must still cover the spend recorded here. Users
+    // without a spending account can omit it, along with the treasury and price
+    // update, which are only needed to check outstanding spend.
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [b"spending", authority.key().as_ref()],
+        bump = spending_account.bump
+    )]
+    pub spending_account: Option<Account<'info, SpendingAccount>>,
+
+    // Holds the borrow index that interest on the spend is accrued against.
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = treasury.bump
+    )]
+    pub treasury: Option<Account<'info, Treasury>>,
+
+    pub price_update: Option<Account<'info, PriceUpdateV2>>,
+
     pub system_program: Program<'info, System>,
 }
 
 impl<'info> Withdraw<'info> {
-    pub fn withdraw_sol(&mut self, amount: u64) -> Result<()> {
+    // `remaining_accounts` carries any SPL collateral, see `token_collateral_value`.
+    pub fn withdraw_sol(&mut self, amount: u64, remaining_accounts: &[AccountInfo]) -> Result<()> {
         // Check if we have sufficient balance in our tracker
         require!(
             self.vault.balance >= amount,
             AmountError::InsufficientFunds
         );
 
-        // Get rent exemption minimum for the vault account
-        let rent = Rent::get()?;
-        let vault_data_len = 8 + std::mem::size_of::<Vault>();
-        let min_rent_exempt_balance = rent.minimum_balance(vault_data_len);
-        
-        let current_lamports = self.vault.to_account_info().lamports();
-        
-        // Ensure we don't withdraw below rent exemption
+        // The collateral left must still back any debt at the initial margin.
+        // Interest only grows a nonzero debt, so a position without any is free
+        // to withdraw.
         require!(
-            current_lamports.checked_sub(amount).unwrap_or(0) >= min_rent_exempt_balance,
-            AmountError::InsufficientFunds
+            self.spending_account.is_some() || !self.vault.has_spending_account,
+            ProtocolError::MarginAccountsRequired
         );
+        if let Some(spending_account) = self.spending_account.as_mut().filter(|s| s.amount_spent > 0) {
+            let treasury = self.treasury.as_mut().ok_or(ProtocolError::MarginAccountsRequired)?;
+            let price_update = self.price_update.as_ref().ok_or(ProtocolError::MarginAccountsRequired)?;
+
+            let now = Clock::get()?.unix_timestamp;
+            let borrow_index = treasury.accrue_interest(now, self.config.borrow_rate_bps);
+            spending_account.accrue_interest(borrow_index);
+
+            require_initial_margin(
+                spending_account.amount_spent,
+                sol_collateral(&self.vault, self.yield_account.as_deref(), amount)?,
+                remaining_accounts,
+                &self.vault,
+                price_update,
+                &self.config,
+                None,
+            )?;
+        }
 
-        // Manually transfer lamports from vault to user
-        // We need to use the vault's authority to sign this transfer
-        let vault_info = self.vault.to_account_info();
-        let authority_info = self.authority.to_account_info();
-        
-        // Debit from vault
-        **vault_info.try_borrow_mut_lamports()? = vault_info
-            .lamports()
-            .checked_sub(amount)
-            .ok_or(AmountError::InsufficientFunds)?;
-        
-        // Credit to authority
-        **authority_info.try_borrow_mut_lamports()? = authority_info
-            .lamports()
-            .checked_add(amount)
-            .unwrap();
-
-        // Update our vault's internal balance tracker
-        self.vault.balance = self.vault
-            .balance
-            .checked_sub(amount)
-            .ok_or(AmountError::InsufficientFunds)?;
+        // The balance never includes the vault's rent-exempt minimum, so paying it
+        // out in full still leaves the vault rent exempt.
+        debit_vault(&mut self.vault, &self.authority.to_account_info(), amount)?;
+
+        emit!(Withdrawn {
+            user: self.authority.key(),
+            amount,
+            vault_balance: self.vault.balance,
+        });
 
         Ok(())
     }
 }
-
-// You'll also need to update your Vault struct to store the bump:
-// #[account]
-// pub struct Vault {
-//     pub authority: Pubkey,
-//     pub balance: u64,
-//     pub bump: u8,  // Add this field
-// }
\ No newline at end of file
@@ -0,0 +1,52 @@
+use anchor_lang::prelude::*;
+
+use crate::{error::{AmountError, ProtocolError}, ProtocolConfig, Treasury};
+
+#[derive(Accounts)]
+pub struct WithdrawFees<'info> {
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        has_one = admin @ ProtocolError::Unauthorized,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = treasury.bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+}
+
+impl <'info> WithdrawFees <'info> {
+
+    pub fn withdraw_fees(&mut self, amount: u64) -> Result<()>{
+
+        // Only collected fees can leave; stakes and rewards stay in the treasury.
+        require!(
+            amount <= self.treasury.accrued_fees,
+            AmountError::InsufficientFunds
+        );
+
+        let treasury_lamports = self.treasury.to_account_info().lamports();
+        **self.treasury.to_account_info().try_borrow_mut_lamports()? = treasury_lamports
+            .checked_sub(amount)
+            .ok_or(AmountError::InsufficientFunds)?;
+
+        let admin_lamports = self.admin.to_account_info().lamports();
+        **self.admin.to_account_info().try_borrow_mut_lamports()? = admin_lamports
+            .checked_add(amount)
+            .unwrap();
+
+        self.treasury.accrued_fees = self.treasury.accrued_fees.checked_sub(amount).unwrap();
+
+        msg!("Withdrew {} fee lamports, {} left.", amount, self.treasury.accrued_fees);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked};
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+
+use crate::{
+    error::AmountError, events::TokenWithdrawn, health::{require_initial_margin, sol_collateral}, ProtocolConfig,
+    SpendingAccount, TokenVault, Treasury, Vault, YieldAccount,
+};
+
+#[derive(Accounts)]
+pub struct WithdrawToken<'info>{
+
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        has_one = authority,
+        seeds = [b"vault", authority.key().as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    // Staked funds also back the debt. Users who never staked can omit it.
+    #[account(
+        has_one = authority,
+        seeds = [b"yield", authority.key().as_ref()],
+        bump = yield_account.bump
+    )]
+    pub yield_account: Option<Account<'info, YieldAccount>>,
+
+    // The collateral left behind must still cover the spend recorded here.
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [b"spending", authority.key().as_ref()],
+        bump = spending_account.bump
+    )]
+    pub spending_account: Account<'info, SpendingAccount>,
+
+    // Holds the borrow index that interest on the spend is accrued against.
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = treasury.bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    pub price_update: Account<'info, PriceUpdateV2>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        has_one = authority,
+        has_one = mint,
+        seeds = [b"token_vault", authority.key().as_ref(), mint.key().as_ref()],
+        bump = token_vault.bump
+    )]
+    pub token_vault: Account<'info, TokenVault>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault,
+        associated_token::token_program = token_program
+    )]
+    pub vault_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    // The user's own token account the withdrawal is paid into.
+    #[account(
+        mut,
+        token::mint = mint,
+        token::token_program = token_program
+    )]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl <'info> WithdrawToken <'info> {
+    
+    // `remaining_accounts` carries the user's SPL collateral, see `token_collateral_value`.
+    // This token vault is one of them.
+    pub fn withdraw_token(&mut self, amount: u64, remaining_accounts: &[AccountInfo]) -> Result<()>{
+
+        require!(
+            self.token_vault.balance >= amount,
+            AmountError::InsufficientFunds
+        );
+
+        // The collateral left must still back the debt at the initial margin. The
+        // balance read from the remaining accounts does not include the withdrawal
+        // yet, so it is taken off there.
+        let now = Clock::get()?.unix_timestamp;
+        let borrow_index = self.treasury.accrue_interest(now, self.config.borrow_rate_bps);
+        self.spending_account.accrue_interest(borrow_index);
+
+        require_initial_margin(
+            self.spending_account.amount_spent,
+            sol_collateral(&self.vault, self.yield_account.as_deref(), 0)?,
+            remaining_accounts,
+            &self.vault,
+            &self.price_update,
+            &self.config,
+            Some((self.mint.key(), amount)),
+        )?;
+
+        // The vault PDA owns the token account, so it signs the transfer.
+        let authority_key = self.authority.key();
+        let signer_seeds: &[&[&[u8]]] = &[&[b"vault", authority_key.as_ref(), &[self.vault.bump]]];
+
+        let program = self.token_program.to_account_info();
+        let accounts = TransferChecked{
+            from: self.vault_token_account.to_account_info(),
+            mint: self.mint.to_account_info(),
+            to: self.user_token_account.to_account_info(),
+            authority: self.vault.to_account_info(),
+        };
+
+        let ctx = CpiContext::new_with_signer(program, accounts, signer_seeds);
+
+        transfer_checked(ctx, amount, self.mint.decimals)?;
+
+        self.token_vault.balance = self.token_vault.balance.checked_sub(amount).unwrap();
+
+        emit!(TokenWithdrawn {
+            user: self.authority.key(),
+            mint: self.mint.key(),
+            amount,
+            token_vault_balance: self.token_vault.balance,
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,70 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked};
+
+use crate::{error::ProtocolError, ProtocolConfig, Treasury};
+
+// Merchant fees on token payments are held in the treasury's token account for
+// each mint. That account holds nothing else, so its whole balance can be withdrawn.
+#[derive(Accounts)]
+pub struct WithdrawTokenFees<'info> {
+
+    pub admin: Signer<'info>,
+
+    #[account(
+        has_one = admin @ ProtocolError::Unauthorized,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        seeds = [b"treasury"],
+        bump = treasury.bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = treasury,
+        associated_token::token_program = token_program
+    )]
+    pub treasury_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    // Any token account of the mint the admin wants the fees paid into.
+    #[account(
+        mut,
+        token::mint = mint,
+        token::token_program = token_program
+    )]
+    pub destination: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl <'info> WithdrawTokenFees <'info> {
+
+    pub fn withdraw_token_fees(&mut self, amount: u64) -> Result<()>{
+
+        // The treasury PDA owns the token account, so it signs the transfer.
+        let signer_seeds: &[&[&[u8]]] = &[&[b"treasury", &[self.treasury.bump]]];
+
+        let program = self.token_program.to_account_info();
+        let accounts = TransferChecked{
+            from: self.treasury_token_account.to_account_info(),
+            mint: self.mint.to_account_info(),
+            to: self.destination.to_account_info(),
+            authority: self.treasury.to_account_info(),
+        };
+
+        let ctx = CpiContext::new_with_signer(program, accounts, signer_seeds);
+
+        transfer_checked(ctx, amount, self.mint.decimals)?;
+
+        msg!("Withdrew {} fee tokens of {}.", amount, self.mint.key());
+
+        Ok(())
+    }
+}
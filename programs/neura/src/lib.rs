@@ -1,90 +1,275 @@
 pub mod constants;
 pub mod error;
+pub mod events;
+pub mod health;
 pub mod instructions;
+pub mod pricing;
 pub mod state;
 
 use anchor_lang::prelude::*;
 
 pub use constants::*;
+pub use health::PositionHealth;
 pub use instructions::*;
 pub use state::*;
 
 declare_id!("8aWorH9nmQ9uQ51Z8Pm33aHdzfnoav7AStPth6yHyKtA");
 
-#[program]
-pub mod capstone_project {
+// The IDL instructions `#[program]` generates call the deprecated
+// `AccountInfo::realloc` next to the module it expands, so the allow wraps the
+// program module alone.
+#[allow(deprecated)]
+mod entrypoint {
     use super::*;
 
-    pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
-        ctx.accounts.init_vault(&ctx.bumps)?;
-        Ok(())
-    }
+    #[program]
+    pub mod capstone_project {
+        use super::*;
 
-    pub fn deposit(ctx: Context<Deposit>, amount: u64) -> Result<()>{
-        ctx.accounts.deposit_tokens(amount)?;
-        Ok(())
-    }
+        pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
+            ctx.accounts.init_vault(&ctx.bumps)?;
+            Ok(())
+        }
 
-    pub fn withdraw(ctx: Context<Withdraw>, amount: u64) -> Result<()>{
-        ctx.accounts.withdraw_sol(amount)?;
-        Ok(())
-    }
+        pub fn deposit(ctx: Context<Deposit>, amount: u64) -> Result<()>{
+            ctx.accounts.deposit_tokens(amount)?;
+            Ok(())
+        }
 
-    pub fn initialize_spending_account(ctx: Context<InitializeSpendingAccount>) -> Result<()>{
-        ctx.accounts.init_spending_account(&ctx.bumps)?;
-        Ok(())
-    }
+        pub fn withdraw(ctx: Context<Withdraw>, amount: u64) -> Result<()>{
+            ctx.accounts.withdraw_sol(amount, ctx.remaining_accounts)?;
+            Ok(())
+        }
 
-    pub fn update_spending_limit(ctx: Context<UpdateSpendingLimit>) -> Result<()> {
-        ctx.accounts.update_spending_limit()?;
-        Ok(())
-    }
+        pub fn initialize_spending_account(ctx: Context<InitializeSpendingAccount>) -> Result<()>{
+            ctx.accounts.init_spending_account(&ctx.bumps)?;
+            Ok(())
+        }
 
-    pub fn authorize_spend(ctx:Context<AuthorizeSpend>, amount: u64) -> Result<()>{
-        ctx.accounts.authorize(amount)?;
-        Ok(())
-    }
+        pub fn update_spending_limit(ctx: Context<UpdateSpendingLimit>) -> Result<()> {
+            ctx.accounts.update_spending_limit(ctx.remaining_accounts)?;
+            Ok(())
+        }
 
-    pub fn reset_spend_tracker(ctx:Context<ResetSpendTracker>) -> Result<()> {
-        ctx.accounts.reset_spend_tracker()?;
-        Ok(())
-    }
+        pub fn authorize_spend(ctx:Context<AuthorizeSpend>, amount: u64) -> Result<()>{
+            ctx.accounts.authorize(amount, ctx.remaining_accounts)?;
+            Ok(())
+        }
 
-    pub fn initialize_treasury(ctx:Context<InitializeTreasury>) -> Result<()>{
-        ctx.accounts.initialize_treasury(&ctx.bumps)?;
-        Ok(())
-    }
+        pub fn reset_spend_tracker(ctx:Context<ResetSpendTracker>) -> Result<()> {
+            ctx.accounts.reset_spend_tracker()?;
+            Ok(())
+        }
 
-    pub fn initialize_yield_account(ctx:Context<InitializeYieldAccount>) -> Result<()>{
-        ctx.accounts.initialize_yield_account(&ctx.bumps)?;
-        Ok(())
-    }
+        pub fn set_window_limit(ctx: Context<SetWindowLimit>, window_limit: u64) -> Result<()> {
+            ctx.accounts.set_window_limit(window_limit)?;
+            Ok(())
+        }
 
-    pub fn stake(ctx: Context<Stake>, amount: u64) -> Result<()>{
-        ctx.accounts.stake_funds(amount)?;
-        Ok(())
-    }
+        pub fn initialize_treasury(ctx:Context<InitializeTreasury>) -> Result<()>{
+            ctx.accounts.initialize_treasury(&ctx.bumps)?;
+            Ok(())
+        }
 
-    pub fn unstake(ctx: Context<Unstake>, amount: u64) -> Result<()> {
-        ctx.accounts.unstake_funds(amount)?;
-        Ok(())
-    }
+        pub fn initialize_yield_account(ctx:Context<InitializeYieldAccount>) -> Result<()>{
+            ctx.accounts.initialize_yield_account(&ctx.bumps)?;
+            Ok(())
+        }
 
-    pub fn liquidation(ctx: Context<Liquidation>) -> Result<()> {
-        ctx.accounts.liquidation()?;
-        Ok(())
-    }
+        pub fn stake(ctx: Context<Stake>, amount: u64) -> Result<()>{
+            ctx.accounts.stake_funds(amount)?;
+            Ok(())
+        }
 
-    pub fn initialize_merchant_account(ctx: Context<InitializeMerchantAccount>, name: String) -> Result<()> {
-        ctx.accounts.merchant_account.authority = ctx.accounts.authority.key();
-        ctx.accounts.merchant_account.name = name;
-        ctx.accounts.merchant_account.bump = ctx.bumps.merchant_account;
-        Ok(())       
-    }
+        pub fn unstake(ctx: Context<Unstake>, amount: u64) -> Result<()> {
+            ctx.accounts.unstake_funds(amount)?;
+            Ok(())
+        }
 
-    pub fn process_payment(ctx: Context<ProcessPayment>, amount: u64) -> Result<()> {
-        instructions::process_payment::process_payment(ctx, amount)
-    }
+        pub fn liquidate(ctx: Context<Liquidation>) -> Result<()> {
+            ctx.accounts.liquidate(ctx.remaining_accounts)?;
+            Ok(())
+        }
+
+        pub fn initialize_merchant_account(ctx: Context<InitializeMerchantAccount>, name: String, accepted_mint: Option<Pubkey>) -> Result<()> {
+            ctx.accounts.init_merchant_account(name, accepted_mint, &ctx.bumps)?;
+            Ok(())
+        }
+
+        pub fn process_payment(
+            ctx: Context<ProcessPayment>,
+            amount: u64,
+            source: PaymentSource,
+            order_id: Option<String>,
+            nonce: u64,
+            memo: Option<String>,
+        ) -> Result<()> {
+            ctx.accounts.process_payment(amount, source, order_id, nonce, memo, &ctx.bumps, ctx.remaining_accounts)?;
+            Ok(())
+        }
+
+        pub fn initialize_config(ctx: Context<InitializeConfig>, params: ConfigParams) -> Result<()> {
+            ctx.accounts.initialize_config(params, &ctx.bumps)?;
+            Ok(())
+        }
+
+        pub fn update_config(ctx: Context<UpdateConfig>, params: ConfigParams) -> Result<()> {
+            ctx.accounts.update_config(params)?;
+            Ok(())
+        }
+
+        pub fn fund_rewards(ctx: Context<FundRewards>, amount: u64) -> Result<()> {
+            ctx.accounts.fund_rewards(amount)?;
+            Ok(())
+        }
+
+        pub fn claim_rewards(ctx: Context<ClaimRewards>) -> Result<()> {
+            ctx.accounts.claim_rewards()?;
+            Ok(())
+        }
+
+        pub fn repay(ctx: Context<Repay>, amount: u64) -> Result<()> {
+            ctx.accounts.repay(amount)?;
+            Ok(())
+        }
+
+        pub fn initialize_token_vault(ctx: Context<InitializeTokenVault>) -> Result<()> {
+            ctx.accounts.init_token_vault(&ctx.bumps)?;
+            Ok(())
+        }
+
+        pub fn deposit_token(ctx: Context<DepositToken>, amount: u64) -> Result<()> {
+            ctx.accounts.deposit_token(amount)?;
+            Ok(())
+        }
+
+        pub fn withdraw_token(ctx: Context<WithdrawToken>, amount: u64) -> Result<()> {
+            ctx.accounts.withdraw_token(amount, ctx.remaining_accounts)?;
+            Ok(())
+        }
+
+        pub fn process_token_payment(
+            ctx: Context<ProcessTokenPayment>,
+            amount: u64,
+            order_id: Option<String>,
+            nonce: u64,
+            memo: Option<String>,
+        ) -> Result<()> {
+            ctx.accounts.process_token_payment(amount, order_id, nonce, memo, &ctx.bumps, ctx.remaining_accounts)?;
+            Ok(())
+        }
 
+        pub fn add_collateral_asset(ctx: Context<AddCollateralAsset>, params: CollateralAssetParams) -> Result<()> {
+            ctx.accounts.add_collateral_asset(params, &ctx.bumps)?;
+            Ok(())
+        }
 
+        pub fn update_collateral_asset(ctx: Context<UpdateCollateralAsset>, params: CollateralAssetParams) -> Result<()> {
+            ctx.accounts.update_collateral_asset(params)?;
+            Ok(())
+        }
+
+        pub fn create_mandate(ctx: Context<CreateMandate>, params: MandateParams) -> Result<()> {
+            ctx.accounts.create_mandate(params, &ctx.bumps)?;
+            Ok(())
+        }
+
+        pub fn revoke_mandate(ctx: Context<RevokeMandate>) -> Result<()> {
+            ctx.accounts.revoke_mandate()?;
+            Ok(())
+        }
+
+        pub fn charge(ctx: Context<Charge>, amount: u64, nonce: u64) -> Result<()> {
+            ctx.accounts.charge(amount, nonce, &ctx.bumps, ctx.remaining_accounts)?;
+            Ok(())
+        }
+
+        pub fn refund(ctx: Context<Refund>, amount: u64) -> Result<()> {
+            ctx.accounts.refund(amount)?;
+            Ok(())
+        }
+
+        pub fn refund_token(ctx: Context<RefundToken>, amount: u64) -> Result<()> {
+            ctx.accounts.refund_token(amount)?;
+            Ok(())
+        }
+
+        pub fn close_receipt(ctx: Context<CloseReceipt>) -> Result<()> {
+            ctx.accounts.close_receipt()?;
+            Ok(())
+        }
+
+        pub fn update_merchant(ctx: Context<UpdateMerchant>, params: MerchantProfileParams) -> Result<()> {
+            ctx.accounts.update_merchant(params)?;
+            Ok(())
+        }
+
+        pub fn close_merchant(ctx: Context<CloseMerchant>) -> Result<()> {
+            ctx.accounts.close_merchant()?;
+            Ok(())
+        }
+
+        pub fn set_merchant_status(ctx: Context<SetMerchantStatus>, verified: bool, suspended: bool) -> Result<()> {
+            ctx.accounts.set_merchant_status(verified, suspended)?;
+            Ok(())
+        }
+
+        pub fn set_merchant_fee(ctx: Context<SetMerchantFee>, fee_bps: Option<u64>) -> Result<()> {
+            ctx.accounts.set_merchant_fee(fee_bps)?;
+            Ok(())
+        }
+
+        pub fn withdraw_fees(ctx: Context<WithdrawFees>, amount: u64) -> Result<()> {
+            ctx.accounts.withdraw_fees(amount)?;
+            Ok(())
+        }
+
+        pub fn withdraw_token_fees(ctx: Context<WithdrawTokenFees>, amount: u64) -> Result<()> {
+            ctx.accounts.withdraw_token_fees(amount)?;
+            Ok(())
+        }
+
+        pub fn get_position_health(ctx: Context<GetPositionHealth>) -> Result<PositionHealth> {
+            ctx.accounts.get_position_health(ctx.remaining_accounts)
+        }
+
+        pub fn sync_vault(ctx: Context<SyncVault>) -> Result<()> {
+            ctx.accounts.sync_vault()?;
+            Ok(())
+        }
+
+        pub fn close_vault(ctx: Context<CloseVault>) -> Result<()> {
+            ctx.accounts.close_vault()?;
+            Ok(())
+        }
+
+        pub fn close_token_vault(ctx: Context<CloseTokenVault>) -> Result<()> {
+            ctx.accounts.close_token_vault()?;
+            Ok(())
+        }
+
+        pub fn close_spending_account(ctx: Context<CloseSpendingAccount>) -> Result<()> {
+            ctx.accounts.close_spending_account()?;
+            Ok(())
+        }
+
+        pub fn close_yield_account(ctx: Context<CloseYieldAccount>) -> Result<()> {
+            ctx.accounts.close_yield_account()?;
+            Ok(())
+        }
+
+        pub fn close_treasury(ctx: Context<CloseTreasury>) -> Result<()> {
+            ctx.accounts.close_treasury()?;
+            Ok(())
+        }
+
+        pub fn close_all_positions(ctx: Context<CloseAllPositions>) -> Result<()> {
+            ctx.accounts.close_all_positions()?;
+            Ok(())
+        }
+
+
+    }
 }
+
+pub use entrypoint::*;
@@ -0,0 +1,184 @@
+use anchor_lang::prelude::*;
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+
+use crate::{error::ProtocolError, ProtocolConfig, USD_DECIMALS};
+
+// Lamports per SOL, as a power of ten.
+pub const SOL_DECIMALS: u8 = 9;
+
+// A validated USD quote for an asset, widened by the oracle confidence interval.
+// One whole token is worth between `collateral_price` and `debt_price` times
+// `10^exponent` dollars; amounts are in base units with `decimals` decimals.
+//
+// Every USD amount going in or out is in the debt denomination: micro-USD
+// (10^-USD_DECIMALS dollars).
+#[derive(Clone, Copy, Debug)]
+pub struct AssetPrice {
+    // price - k * conf: used whenever the asset is counted in the user's favour (collateral).
+    pub collateral_price: u128,
+    // price + k * conf: used whenever the asset is counted against the user (debt).
+    pub debt_price: u128,
+    pub exponent: i32,
+    pub decimals: u8,
+}
+
+impl AssetPrice {
+    // Reads the configured SOL/USD feed.
+    pub fn load_sol_usd(price_update: &PriceUpdateV2, config: &ProtocolConfig) -> Result<Self> {
+        Self::load(price_update, &config.sol_usd_feed_id, SOL_DECIMALS, config)
+    }
+
+    // Reads `feed_id`, rejecting stale, foreign, non-positive or overly uncertain prices.
+    pub fn load(
+        price_update: &PriceUpdateV2,
+        feed_id: &[u8; 32],
+        decimals: u8,
+        config: &ProtocolConfig,
+    ) -> Result<Self> {
+        let price = price_update.get_price_no_older_than(
+            &Clock::get()?,
+            config.maximum_age,
+            feed_id,
+        )?;
+
+        Self::from_oracle(price.price, price.conf, price.exponent, decimals, config)
+    }
+
+    pub fn from_oracle(price: i64, conf: u64, exponent: i32, decimals: u8, config: &ProtocolConfig) -> Result<Self> {
+        require!(price > 0, ProtocolError::InvalidPrice);
+
+        let mid = price as u128;
+        let conf = conf as u128;
+
+        // Refuse to act on a wide (or manipulated) update.
+        let conf_ratio_bps = conf
+            .checked_mul(10_000)
+            .ok_or(ProtocolError::MathOverflow)?
+            .checked_div(mid)
+            .ok_or(ProtocolError::MathOverflow)?;
+        require!(
+            conf_ratio_bps <= config.max_confidence_ratio_bps as u128,
+            ProtocolError::PriceTooUncertain
+        );
+
+        let spread = conf
+            .checked_mul(config.confidence_multiplier_bps as u128)
+            .ok_or(ProtocolError::MathOverflow)?
+            .checked_div(10_000)
+            .ok_or(ProtocolError::MathOverflow)?;
+
+        let collateral_price = mid.saturating_sub(spread);
+        require!(collateral_price > 0, ProtocolError::PriceTooUncertain);
+
+        Ok(Self {
+            collateral_price,
+            debt_price: mid.checked_add(spread).ok_or(ProtocolError::MathOverflow)?,
+            exponent,
+            decimals,
+        })
+    }
+
+    // Value of `amount` base units of collateral, at the lower bound.
+    pub fn collateral_value_usd(&self, amount: u64) -> Result<u128> {
+        amount_to_usd(amount, self.collateral_price, self.exponent, self.decimals)
+    }
+
+    // Base units needed to be worth `usd` of collateral, at the lower bound.
+    // Rounds up so the amount is never worth less than `usd`.
+    pub fn collateral_value_amount(&self, usd: u128) -> Result<u128> {
+        let value = scale(usd, self.decimals as i32 - USD_DECIMALS as i32 - self.exponent)?;
+        Ok(value
+            .checked_add(self.collateral_price - 1)
+            .ok_or(ProtocolError::MathOverflow)?
+            / self.collateral_price)
+    }
+
+    // Value of `amount` base units counted as debt, at the upper bound.
+    pub fn debt_value_usd(&self, amount: u64) -> Result<u128> {
+        amount_to_usd(amount, self.debt_price, self.exponent, self.decimals)
+    }
+
+    // Base units matching an amount of USD debt, at the upper bound.
+    pub fn debt_value_amount(&self, usd: u128) -> Result<u128> {
+        usd_to_amount(usd, self.debt_price, self.exponent, self.decimals)
+    }
+}
+
+// Formula: USD = Amount * Price * 10^(expo + USD_DECIMALS - decimals)
+fn amount_to_usd(amount: u64, price: u128, exponent: i32, decimals: u8) -> Result<u128> {
+    let value = (amount as u128)
+        .checked_mul(price)
+        .ok_or(ProtocolError::MathOverflow)?;
+    scale(value, exponent + USD_DECIMALS as i32 - decimals as i32)
+}
+
+// Formula: Amount = USD * 10^(decimals - USD_DECIMALS - expo) / Price
+fn usd_to_amount(usd: u128, price: u128, exponent: i32, decimals: u8) -> Result<u128> {
+    let value = scale(usd, decimals as i32 - USD_DECIMALS as i32 - exponent)?;
+    Ok(value.checked_div(price).ok_or(ProtocolError::MathOverflow)?)
+}
+
+// Multiplies by 10^decimals, dividing instead when `decimals` is negative.
+fn scale(value: u128, decimals: i32) -> Result<u128> {
+    let factor = 10u128
+        .checked_pow(decimals.unsigned_abs())
+        .ok_or(ProtocolError::MathOverflow)?;
+
+    let scaled = if decimals >= 0 {
+        value.checked_mul(factor)
+    } else {
+        value.checked_div(factor)
+    };
+    Ok(scaled.ok_or(ProtocolError::MathOverflow)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_lamports_using_the_price_exponent() {
+        // $150.00 quoted with two different exponents.
+        let negative = AssetPrice::from_oracle(15_000_000_000, 0, -8, SOL_DECIMALS, &ProtocolConfig::test_default()).unwrap();
+        let positive = AssetPrice::from_oracle(15, 0, 1, SOL_DECIMALS, &ProtocolConfig::test_default()).unwrap();
+
+        for price in [negative, positive] {
+            assert_eq!(price.collateral_value_usd(1_000_000_000).unwrap(), 150_000_000);
+            assert_eq!(price.debt_value_amount(150_000_000).unwrap(), 1_000_000_000);
+        }
+    }
+
+    #[test]
+    fn widens_prices_by_the_confidence_interval() {
+        // $100.00 ± $0.50, widened by 2 intervals to $99.00 / $101.00.
+        let price = AssetPrice::from_oracle(10_000_000_000, 50_000_000, -8, SOL_DECIMALS, &ProtocolConfig::test_default()).unwrap();
+
+        assert_eq!(price.collateral_value_usd(1_000_000_000).unwrap(), 99_000_000);
+        assert_eq!(price.debt_value_usd(1_000_000_000).unwrap(), 101_000_000);
+    }
+
+    #[test]
+    fn rounds_collateral_lamports_up() {
+        let price = AssetPrice::from_oracle(300_000_000, 0, -8, SOL_DECIMALS, &ProtocolConfig::test_default()).unwrap();
+
+        // $1.00 at $3.00/SOL is 333_333_333.3 lamports.
+        assert_eq!(price.collateral_value_amount(1_000_000).unwrap(), 333_333_334);
+    }
+
+    #[test]
+    fn values_tokens_using_their_own_decimals() {
+        // A $1.00 stablecoin with 6 decimals.
+        let usdc = AssetPrice::from_oracle(100_000_000, 0, -8, 6, &ProtocolConfig::test_default()).unwrap();
+
+        assert_eq!(usdc.collateral_value_usd(25_000_000).unwrap(), 25_000_000);
+        assert_eq!(usdc.debt_value_amount(25_000_000).unwrap(), 25_000_000);
+    }
+
+    #[test]
+    fn rejects_non_positive_and_uncertain_prices() {
+        assert!(AssetPrice::from_oracle(0, 0, -8, SOL_DECIMALS, &ProtocolConfig::test_default()).is_err());
+        assert!(AssetPrice::from_oracle(-1, 0, -8, SOL_DECIMALS, &ProtocolConfig::test_default()).is_err());
+        // A 3% confidence interval is above the 2% maximum.
+        assert!(AssetPrice::from_oracle(10_000_000_000, 300_000_000, -8, SOL_DECIMALS, &ProtocolConfig::test_default()).is_err());
+    }
+}
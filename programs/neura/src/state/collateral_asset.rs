@@ -0,0 +1,48 @@
+use anchor_lang::prelude::*;
+
+use crate::error::ProtocolError;
+
+// Risk parameters for an SPL mint accepted as collateral next to SOL.
+// Users post it through their `TokenVault` for the same mint.
+#[account]
+#[derive(InitSpace)]
+pub struct CollateralAsset {
+    pub mint: Pubkey,
+    // The Pyth feed pricing one whole token in USD.
+    pub price_feed_id: [u8; 32],
+    pub decimals: u8,
+    // Share of the asset's value (in basis points) that counts towards the spending limit.
+    pub collateral_factor_bps: u64,
+    // Share of the asset's value (in basis points) that backs debt before the
+    // position can be liquidated.
+    pub liquidation_threshold_bps: u64,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct CollateralAssetParams {
+    pub price_feed_id: [u8; 32],
+    pub collateral_factor_bps: u64,
+    pub liquidation_threshold_bps: u64,
+}
+
+impl CollateralAssetParams {
+    pub fn validate(&self) -> Result<()> {
+        // Spending the full limit must leave the position above the liquidation threshold.
+        require!(self.collateral_factor_bps > 0, ProtocolError::InvalidConfig);
+        require!(
+            self.collateral_factor_bps < self.liquidation_threshold_bps,
+            ProtocolError::InvalidConfig
+        );
+        require!(self.liquidation_threshold_bps <= 10_000, ProtocolError::InvalidConfig);
+        Ok(())
+    }
+}
+
+impl CollateralAsset {
+    pub fn apply(&mut self, params: CollateralAssetParams) {
+        self.price_feed_id = params.price_feed_id;
+        self.collateral_factor_bps = params.collateral_factor_bps;
+        self.liquidation_threshold_bps = params.liquidation_threshold_bps;
+    }
+}
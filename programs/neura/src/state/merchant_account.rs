@@ -1,14 +1,71 @@
 use anchor_lang::prelude::*;
 
+use crate::{error::PaymentError, ProtocolConfig};
+
+pub const MAX_MERCHANT_NAME_LEN: usize = 200;
+
 #[account]
 #[derive(InitSpace)]
 pub struct MerchantAccount {
     // The public key of the merchant who owns this account.
     pub authority: Pubkey,
     // The display name of the merchant.
-    #[max_len(200)]
+    #[max_len(MAX_MERCHANT_NAME_LEN)]
     pub name: String,
+    // The SPL mint the merchant is paid in, or `None` to be paid in SOL.
+    pub accepted_mint: Option<Pubkey>,
+    // Number of payments received.
+    pub payment_count: u64,
+    // Receipts and mandates that still point at this account. It can only be
+    // closed once both are zero, so a re-created merchant never inherits them.
+    pub open_receipts: u64,
+    pub open_mandates: u64,
+    // The wallet payments are sent to. Defaults to `authority`.
+    pub payout_wallet: Pubkey,
+    // ISO 18245 merchant category code, 0 if not set.
+    pub category: u16,
+    // Set by the admin once the merchant has been onboarded.
+    pub verified: bool,
+    // Set by the admin to stop the merchant from taking payments.
+    pub suspended: bool,
+    // Fee rate (in basis points) set by the admin for this merchant, overriding
+    // `ProtocolConfig::merchant_fee_bps`.
+    pub fee_bps: Option<u64>,
     // The PDA bump seed.
     pub bump: u8,
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct MerchantProfileParams {
+    pub name: Option<String>,
+    pub payout_wallet: Option<Pubkey>,
+    pub category: Option<u16>,
+}
+
+impl MerchantAccount {
+    pub fn validate_name(name: &str) -> Result<()> {
+        require!(name.len() <= MAX_MERCHANT_NAME_LEN, PaymentError::MerchantNameTooLong);
+        Ok(())
+    }
+
+    // Part of a payment of `amount` kept by the protocol.
+    pub fn fee_for(&self, amount: u64, config: &ProtocolConfig) -> u64 {
+        let fee_bps = self.fee_bps.unwrap_or(config.merchant_fee_bps);
+        ((amount as u128) * (fee_bps as u128) / 10_000) as u64
+    }
+
+    // Applies the fields that are set, leaving the others unchanged.
+    pub fn apply(&mut self, params: MerchantProfileParams) -> Result<()> {
+        if let Some(name) = params.name {
+            Self::validate_name(&name)?;
+            self.name = name;
+        }
+        if let Some(payout_wallet) = params.payout_wallet {
+            self.payout_wallet = payout_wallet;
+        }
+        if let Some(category) = params.category {
+            self.category = category;
+        }
+        Ok(())
+    }
+}
@@ -3,9 +3,19 @@ pub mod spending_account;
 pub mod yield_account;
 pub mod treasury;
 pub mod merchant_account;
+pub mod protocol_config;
+pub mod token_vault;
+pub mod collateral_asset;
+pub mod payment_mandate;
+pub mod payment_receipt;
 
 pub use vault::*;
 pub use spending_account::*;
 pub use yield_account::*;
 pub use treasury::*;
-pub use merchant_account::*;
\ No newline at end of file
+pub use merchant_account::*;
+pub use protocol_config::*;
+pub use token_vault::*;
+pub use collateral_asset::*;
+pub use payment_mandate::*;
+pub use payment_receipt::*;
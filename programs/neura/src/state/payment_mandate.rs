@@ -0,0 +1,65 @@
+use anchor_lang::prelude::*;
+
+use crate::error::PaymentError;
+
+// A user's standing authorisation for one merchant to pull payments from their
+// vault. Caps are in the spending account's debt denomination (micro-USD).
+#[account]
+#[derive(InitSpace)]
+pub struct PaymentMandate {
+    pub authority: Pubkey,
+    // The `MerchantAccount` allowed to charge against this mandate.
+    pub merchant_account: Pubkey,
+    pub max_per_charge: u64,
+    pub max_per_period: u64,
+    // Length of a charging period, in seconds.
+    pub period_seconds: i64,
+    // Unix timestamp after which the merchant can no longer charge.
+    pub expires_at: i64,
+    // Unix timestamp the current charging period started at.
+    pub period_start: i64,
+    // Amount charged since `period_start`.
+    pub period_spent: u64,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct MandateParams {
+    pub max_per_charge: u64,
+    pub max_per_period: u64,
+    pub period_seconds: i64,
+    pub expires_at: i64,
+}
+
+impl MandateParams {
+    pub fn validate(&self, now: i64) -> Result<()> {
+        require!(self.max_per_charge > 0, PaymentError::InvalidMandate);
+        require!(self.max_per_charge <= self.max_per_period, PaymentError::InvalidMandate);
+        require!(self.period_seconds > 0, PaymentError::InvalidMandate);
+        require!(self.expires_at > now, PaymentError::InvalidMandate);
+        Ok(())
+    }
+}
+
+impl PaymentMandate {
+    // Checks a charge against the mandate's expiry and caps, then records it.
+    // Periods roll over the same way as `SpendingAccount` windows.
+    pub fn record_charge(&mut self, amount: u64, now: i64) -> Result<()> {
+        require!(now < self.expires_at, PaymentError::MandateExpired);
+
+        let elapsed = now.saturating_sub(self.period_start);
+        if elapsed >= self.period_seconds {
+            self.period_start += elapsed - elapsed % self.period_seconds;
+            self.period_spent = 0;
+        }
+
+        let period_spent = self.period_spent.checked_add(amount).unwrap();
+        require!(
+            amount <= self.max_per_charge && period_spent <= self.max_per_period,
+            PaymentError::MandateLimitExceeded
+        );
+        self.period_spent = period_spent;
+
+        Ok(())
+    }
+}
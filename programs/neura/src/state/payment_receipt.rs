@@ -0,0 +1,195 @@
+use anchor_lang::{prelude::*, solana_program::hash::hashv};
+
+use crate::{error::PaymentError, MerchantAccount};
+
+pub const MAX_ORDER_ID_LEN: usize = 64;
+pub const MAX_MEMO_LEN: usize = 128;
+// How long a receipt that was not fully refunded is kept before the merchant can
+// close it. Closing frees its order ID to be paid again.
+pub const RECEIPT_RETENTION_SECONDS: i64 = 180 * 24 * 60 * 60;
+
+// Where the lamports paid to the merchant come from.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Debug)]
+pub enum PaymentSource {
+    // The user's own wallet, through the System Program.
+    Wallet,
+    // The user's `Vault` PDA, reducing `vault.balance`.
+    Vault,
+}
+
+// Record of a single `process_payment`, `process_token_payment` or `charge`, used to refund
+// and reconcile it later. Keyed by merchant and order ID, so the same order cannot
+// be paid twice, or by merchant, payer and nonce for payments without an order ID.
+#[account]
+#[derive(InitSpace)]
+pub struct PaymentReceipt {
+    // The user who paid.
+    pub payer: Pubkey,
+    pub merchant_account: Pubkey,
+    pub source: PaymentSource,
+    // The SPL mint paid in, or `None` for lamports.
+    pub mint: Option<Pubkey>,
+    // Amount paid by the user, fee included, in lamports or base units of `mint`.
+    pub amount: u64,
+    // Part of `amount` kept by the protocol as a merchant fee.
+    pub fee: u64,
+    // Debt recorded for the payment, in micro-USD.
+    pub amount_usd: u64,
+    // Lamports or base units refunded so far, at most `refundable()`.
+    pub refunded: u64,
+    // Debt released by refunds so far, in micro-USD.
+    pub refunded_usd: u64,
+    pub created_at: i64,
+    // The merchant's reference for the order, if any.
+    #[max_len(MAX_ORDER_ID_LEN)]
+    pub order_id: Option<String>,
+    // Picked by the payer to key a receipt without an order ID, 0 otherwise.
+    pub nonce: u64,
+    // Who funded the receipt: the payer, or the merchant for a mandate charge.
+    // Closing the receipt returns its rent to them.
+    pub rent_payer: Pubkey,
+    #[max_len(MAX_MEMO_LEN)]
+    pub memo: Option<String>,
+    pub bump: u8,
+}
+
+impl PaymentReceipt {
+    // The last receipt seed: a hash of the order ID, or for payments without one
+    // of the payer and a nonce the payer picks, so concurrent payments to the same
+    // merchant never contend for a receipt. The prefixes keep the two apart.
+    pub fn seed(order_id: Option<&str>, payer: &Pubkey, nonce: u64) -> [u8; 32] {
+        match order_id {
+            Some(order_id) => hashv(&[b"order", order_id.as_bytes()]).to_bytes(),
+            None => hashv(&[b"payer", payer.as_ref(), &nonce.to_le_bytes()]).to_bytes(),
+        }
+    }
+
+    // Writes the receipt and counts it on the merchant, where it stays open until
+    // `close_receipt`.
+    pub fn issue(
+        receipt: &mut Account<PaymentReceipt>,
+        merchant_account: &mut Account<MerchantAccount>,
+        details: PaymentReceipt,
+    ) {
+        receipt.set_inner(details);
+        merchant_account.payment_count = merchant_account.payment_count.checked_add(1).unwrap();
+        merchant_account.open_receipts = merchant_account.open_receipts.checked_add(1).unwrap();
+
+        if let Some(order_id) = &receipt.order_id {
+            msg!("Order {} paid.", order_id);
+        }
+    }
+
+    pub fn validate_reference(order_id: &Option<String>, memo: &Option<String>) -> Result<()> {
+        if let Some(order_id) = order_id {
+            require!(
+                !order_id.is_empty() && order_id.len() <= MAX_ORDER_ID_LEN,
+                PaymentError::InvalidOrderId
+            );
+        }
+        if let Some(memo) = memo {
+            require!(memo.len() <= MAX_MEMO_LEN, PaymentError::MemoTooLong);
+        }
+        Ok(())
+    }
+
+    // Lamports or tokens the merchant can give back: what it received. The protocol keeps
+    // the fee, which the merchant never had.
+    pub fn refundable(&self) -> u64 {
+        self.amount.checked_sub(self.fee).unwrap()
+    }
+
+    // Whether the merchant can close the receipt at `now`: once nothing is left to
+    // refund, or once the retention period is over.
+    pub fn closable(&self, now: i64) -> bool {
+        self.refunded == self.refundable()
+            || now >= self.created_at.saturating_add(RECEIPT_RETENTION_SECONDS)
+    }
+
+    // Debt to release when refunding `amount` more lamports: the pro-rata share of
+    // the refundable amount, with the final refund releasing whatever is left.
+    pub fn refund_usd(&self, amount: u64) -> u64 {
+        let refunded = self.refunded.checked_add(amount).unwrap();
+        if refunded == self.refundable() {
+            return self.amount_usd - self.refunded_usd;
+        }
+        let share = (self.amount_usd as u128)
+            .checked_mul(amount as u128).unwrap()
+            / self.refundable() as u128;
+        share as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receipt(amount: u64, fee: u64, amount_usd: u64) -> PaymentReceipt {
+        PaymentReceipt {
+            payer: Pubkey::default(),
+            merchant_account: Pubkey::default(),
+            source: PaymentSource::Wallet,
+            mint: None,
+            amount,
+            fee,
+            amount_usd,
+            refunded: 0,
+            refunded_usd: 0,
+            created_at: 0,
+            order_id: None,
+            nonce: 0,
+            rent_payer: Pubkey::default(),
+            memo: None,
+            bump: 0,
+        }
+    }
+
+    #[test]
+    fn partial_refunds_release_all_of_the_debt() {
+        let mut receipt = receipt(300, 0, 1_000);
+
+        for expected in [333, 333, 334] {
+            let refund_usd = receipt.refund_usd(100);
+            assert_eq!(refund_usd, expected);
+            receipt.refunded += 100;
+            receipt.refunded_usd += refund_usd;
+        }
+        assert_eq!(receipt.refunded_usd, receipt.amount_usd);
+    }
+
+    #[test]
+    fn refunding_the_amount_after_fee_releases_all_of_the_debt() {
+        // A 10% fee leaves 270 lamports with the merchant.
+        let mut receipt = receipt(300, 30, 1_000);
+        assert_eq!(receipt.refundable(), 270);
+
+        assert_eq!(receipt.refund_usd(135), 500);
+        receipt.refunded += 135;
+        receipt.refunded_usd += 500;
+        assert_eq!(receipt.refund_usd(135), 500);
+    }
+
+    #[test]
+    fn receipts_without_an_order_id_are_keyed_by_payer_and_nonce() {
+        let payer = Pubkey::new_unique();
+        let seed = PaymentReceipt::seed(None, &payer, 1);
+
+        assert_ne!(seed, PaymentReceipt::seed(None, &payer, 2));
+        assert_ne!(seed, PaymentReceipt::seed(None, &Pubkey::new_unique(), 1));
+        // An order ID keys the receipt on its own, whoever pays it.
+        assert_eq!(
+            PaymentReceipt::seed(Some("order-1"), &payer, 1),
+            PaymentReceipt::seed(Some("order-1"), &Pubkey::new_unique(), 7)
+        );
+    }
+
+    #[test]
+    fn receipts_close_once_refunded_or_past_retention() {
+        let mut receipt = receipt(300, 30, 1_000);
+        assert!(!receipt.closable(RECEIPT_RETENTION_SECONDS - 1));
+        assert!(receipt.closable(RECEIPT_RETENTION_SECONDS));
+
+        receipt.refunded = 270;
+        assert!(receipt.closable(0));
+    }
+}
@@ -0,0 +1,122 @@
+use anchor_lang::prelude::*;
+
+use crate::{error::ProtocolError, MAX_BORROW_RATE_BPS, MAX_MERCHANT_FEE_BPS};
+
+#[account]
+#[derive(InitSpace)]
+pub struct ProtocolConfig {
+    // The admin allowed to retune the protocol parameters.
+    pub admin: Pubkey,
+    // The Pyth SOL/USD feed used to value lamport collateral.
+    pub sol_usd_feed_id: [u8; 32],
+    // Maximum age, in seconds, of a price update before it is rejected.
+    pub maximum_age: u64,
+    // Collateral ratio (in percent) below which a position can be liquidated.
+    pub liquidation_threshold: u64,
+    // Collateral ratio (in percent) a liquidation restores the position to.
+    pub target_collateral_ratio: u64,
+    // Share of collateral value (in basis points) that can be spent.
+    pub loan_to_value_bps: u64,
+    // Extra collateral (in basis points of the repaid debt) paid to the liquidator.
+    pub liquidation_bonus_bps: u64,
+    // Number of confidence intervals (in basis points, 10_000 = 1x) prices are
+    // widened by: collateral is valued at price - k*conf and debt at price + k*conf.
+    pub confidence_multiplier_bps: u64,
+    // Largest conf / price ratio (in basis points) the protocol will act on.
+    pub max_confidence_ratio_bps: u64,
+    // Share of each payment (in basis points) kept by the protocol, unless the
+    // merchant has its own rate.
+    pub merchant_fee_bps: u64,
+    // Annual interest (in basis points) charged on outstanding spend.
+    pub borrow_rate_bps: u64,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ConfigParams {
+    pub sol_usd_feed_id: [u8; 32],
+    pub maximum_age: u64,
+    pub liquidation_threshold: u64,
+    pub target_collateral_ratio: u64,
+    pub loan_to_value_bps: u64,
+    pub liquidation_bonus_bps: u64,
+    pub confidence_multiplier_bps: u64,
+    pub max_confidence_ratio_bps: u64,
+    pub merchant_fee_bps: u64,
+    pub borrow_rate_bps: u64,
+}
+
+impl ConfigParams {
+    pub fn validate(&self) -> Result<()> {
+        require!(self.maximum_age > 0, ProtocolError::InvalidConfig);
+        // A position must be over-collateralised before it is considered safe,
+        // and liquidation has to leave it healthier than the threshold.
+        require!(self.liquidation_threshold >= 100, ProtocolError::InvalidConfig);
+        require!(
+            self.target_collateral_ratio > self.liquidation_threshold,
+            ProtocolError::InvalidConfig
+        );
+        // Spending the full limit must leave the position above the liquidation threshold.
+        require!(self.loan_to_value_bps > 0, ProtocolError::InvalidConfig);
+        require!(
+            self.loan_to_value_bps
+                .checked_mul(self.liquidation_threshold)
+                .ok_or(ProtocolError::InvalidConfig)?
+                < 10_000 * 100,
+            ProtocolError::InvalidConfig
+        );
+        // The bonus must be smaller than the target over-collateralisation, otherwise
+        // seizing collateral could never bring the position back to the target ratio.
+        require!(
+            self.target_collateral_ratio.checked_mul(100).ok_or(ProtocolError::InvalidConfig)?
+                > self.liquidation_bonus_bps.checked_add(10_000).ok_or(ProtocolError::InvalidConfig)?,
+            ProtocolError::InvalidConfig
+        );
+        // The widest accepted interval must still leave a positive collateral price.
+        require!(
+            self.max_confidence_ratio_bps
+                .checked_mul(self.confidence_multiplier_bps)
+                .ok_or(ProtocolError::InvalidConfig)?
+                < 10_000 * 10_000,
+            ProtocolError::InvalidConfig
+        );
+        require!(self.merchant_fee_bps <= MAX_MERCHANT_FEE_BPS, ProtocolError::InvalidConfig);
+        require!(self.borrow_rate_bps <= MAX_BORROW_RATE_BPS, ProtocolError::InvalidConfig);
+        Ok(())
+    }
+}
+
+impl ProtocolConfig {
+    pub fn apply(&mut self, params: ConfigParams) {
+        self.sol_usd_feed_id = params.sol_usd_feed_id;
+        self.maximum_age = params.maximum_age;
+        self.liquidation_threshold = params.liquidation_threshold;
+        self.target_collateral_ratio = params.target_collateral_ratio;
+        self.loan_to_value_bps = params.loan_to_value_bps;
+        self.liquidation_bonus_bps = params.liquidation_bonus_bps;
+        self.confidence_multiplier_bps = params.confidence_multiplier_bps;
+        self.max_confidence_ratio_bps = params.max_confidence_ratio_bps;
+        self.merchant_fee_bps = params.merchant_fee_bps;
+        self.borrow_rate_bps = params.borrow_rate_bps;
+    }
+
+    // Shared fixture for the unit tests: 120% liquidation threshold, 150% target,
+    // 50% LTV, 5% bonus, 2x confidence widening and no fees or interest.
+    #[cfg(test)]
+    pub fn test_default() -> Self {
+        ProtocolConfig {
+            admin: Pubkey::default(),
+            sol_usd_feed_id: [0; 32],
+            maximum_age: 60,
+            liquidation_threshold: 120,
+            target_collateral_ratio: 150,
+            loan_to_value_bps: 5_000,
+            liquidation_bonus_bps: 500,
+            confidence_multiplier_bps: 20_000,
+            max_confidence_ratio_bps: 200,
+            merchant_fee_bps: 0,
+            borrow_rate_bps: 0,
+            bump: 0,
+        }
+    }
+}
@@ -1,10 +1,147 @@
 use anchor_lang::prelude::*;
 
+use crate::error::AmountError;
+
+// Unit that `spending_limit`, `amount_spent`, `window_limit` and `window_spent` are held in.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Debug)]
+pub enum DebtDenomination {
+    // Millionths of a US dollar (`USD_DECIMALS` = 6).
+    UsdMicro,
+}
+
 #[account]
 #[derive(InitSpace)]
 pub struct SpendingAccount {
     pub authority: Pubkey,
+    pub denomination: DebtDenomination,
     pub spending_limit: u64,
+    // Outstanding spend, treated as debt by `Liquidation`.
     pub amount_spent: u64,
+    // Length of a spend window, in seconds.
+    pub period_seconds: i64,
+    // Most that can be spent within one window, set by the owner. Independent of
+    // `spending_limit`, which caps the outstanding spend.
+    pub window_limit: u64,
+    // Unix timestamp the current spend window started at.
+    pub window_start: i64,
+    // Amount spent since `window_start`. Rolls back to zero every period.
+    pub window_spent: u64,
+    // The treasury's `borrow_index` when interest was last added to `amount_spent`.
+    pub borrow_index: u128,
     pub bump: u8,
 }
+
+impl SpendingAccount {
+    // Adds the interest owed since the last snapshot to `amount_spent`. Must be
+    // called with the treasury's up-to-date index before the debt is read or changed.
+    // The index only falls when the treasury is recreated; the debt is kept as is then.
+    pub fn accrue_interest(&mut self, borrow_index: u128) {
+        if self.amount_spent > 0 && self.borrow_index > 0 && borrow_index > self.borrow_index {
+            let debt = (self.amount_spent as u128)
+                .checked_mul(borrow_index).unwrap()
+                / self.borrow_index;
+            self.amount_spent = u64::try_from(debt).unwrap();
+        }
+        self.borrow_index = borrow_index;
+    }
+
+    // Starts a new window once the current one has elapsed, keeping windows
+    // aligned to multiples of `period_seconds` from the first one.
+    pub fn roll_window(&mut self, now: i64) {
+        let elapsed = now.saturating_sub(self.window_start);
+        if elapsed >= self.period_seconds {
+            self.window_start += elapsed - elapsed % self.period_seconds;
+            self.window_spent = 0;
+        }
+    }
+
+    // Checks `amount` against the outstanding spend under `spending_limit` and the
+    // current window's spend under `window_limit`, then records it.
+    pub fn record_spend(&mut self, amount: u64, now: i64) -> Result<()> {
+        self.roll_window(now);
+
+        let available_to_spend = self.spending_limit
+            .saturating_sub(self.amount_spent)
+            .min(self.window_limit.saturating_sub(self.window_spent));
+
+        require!(
+            amount <= available_to_spend,
+            AmountError::SpendingLimitExceeded
+        );
+
+        self.amount_spent = self.amount_spent.checked_add(amount).unwrap();
+        self.window_spent = self.window_spent.checked_add(amount).unwrap();
+
+        Ok(())
+    }
+
+    // Takes back `amount` of a spend made at `spent_at`. It only frees room in the
+    // current window if it was spent in it. Part of the spend may already have
+    // been repaid, so neither total goes below zero.
+    pub fn release_spend(&mut self, amount: u64, spent_at: i64, now: i64) {
+        self.roll_window(now);
+
+        self.amount_spent = self.amount_spent.saturating_sub(amount);
+        if spent_at >= self.window_start {
+            self.window_spent = self.window_spent.saturating_sub(amount);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spending_account(spending_limit: u64, window_limit: u64) -> SpendingAccount {
+        SpendingAccount {
+            authority: Pubkey::default(),
+            denomination: DebtDenomination::UsdMicro,
+            spending_limit,
+            amount_spent: 0,
+            period_seconds: 100,
+            window_limit,
+            window_start: 0,
+            window_spent: 0,
+            borrow_index: 0,
+            bump: 0,
+        }
+    }
+
+    #[test]
+    fn window_limit_caps_spend_within_a_window() {
+        let mut account = spending_account(1_000, 300);
+
+        account.record_spend(300, 10).unwrap();
+        assert!(account.record_spend(1, 20).is_err());
+
+        // The next window allows another 300, while the outstanding spend keeps growing.
+        account.record_spend(300, 100).unwrap();
+        assert_eq!(account.amount_spent, 600);
+        assert_eq!(account.window_spent, 300);
+    }
+
+    #[test]
+    fn releasing_spend_only_frees_the_window_it_was_made_in() {
+        let mut account = spending_account(1_000, 300);
+
+        account.record_spend(300, 10).unwrap();
+        account.release_spend(100, 10, 20);
+        assert_eq!(account.window_spent, 200);
+        account.record_spend(100, 30).unwrap();
+
+        // In the next window, releasing an older spend leaves the window alone.
+        account.record_spend(50, 100).unwrap();
+        account.release_spend(100, 10, 110);
+        assert_eq!(account.window_spent, 50);
+        assert_eq!(account.amount_spent, 250);
+    }
+
+    #[test]
+    fn spending_limit_caps_outstanding_spend_across_windows() {
+        let mut account = spending_account(500, u64::MAX);
+
+        account.record_spend(400, 10).unwrap();
+        assert!(account.record_spend(200, 150).is_err());
+        account.record_spend(100, 150).unwrap();
+    }
+}
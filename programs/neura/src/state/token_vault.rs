@@ -0,0 +1,12 @@
+use anchor_lang::prelude::*;
+
+// Tracks one user's balance of a single SPL mint. The tokens themselves sit in
+// the associated token account of `mint` owned by the user's `Vault` PDA.
+#[account]
+#[derive(InitSpace)]
+pub struct TokenVault {
+    pub authority: Pubkey,
+    pub mint: Pubkey,
+    pub balance: u64,
+    pub bump: u8,
+}
@@ -1,9 +1,68 @@
 use anchor_lang::prelude::*;
 
+use crate::error::{AmountError, ProtocolError};
+
+// `balance` always equals the vault's lamports above its rent-exempt minimum.
+// Every lamport moving in or out of a vault goes through `credit_vault` or
+// `debit_vault`, which keep the two in step.
 #[account]
 #[derive(InitSpace)]
 pub struct Vault{
     pub authority: Pubkey,
     pub balance: u64,
+    // Token vaults opened for this vault. Valuing the position's SPL collateral
+    // requires all of them, so none can be left out.
+    pub token_vault_count: u8,
+    // Set while the owner has a spending account. Collateral can then only leave
+    // the vault with that account passed in to check the debt against.
+    pub has_spending_account: bool,
     pub bump: u8,
-}
\ No newline at end of file
+}
+
+impl Vault {
+    // Lamports a vault keeps to stay rent exempt. They never count towards `balance`.
+    pub fn rent_exempt_minimum() -> Result<u64> {
+        Ok(Rent::get()?.minimum_balance(8 + Vault::INIT_SPACE))
+    }
+}
+
+// Lamports `vault` holds above its rent-exempt minimum, which `balance` should match.
+pub fn vault_lamports_available(vault: &Account<Vault>) -> Result<u64> {
+    Ok(vault.to_account_info().lamports().saturating_sub(Vault::rent_exempt_minimum()?))
+}
+
+// Records `amount` lamports that were just moved into the vault. Lamports sent to
+// the vault directly are folded in as well. Holding less than the new balance
+// means the books are wrong, and fails.
+pub fn credit_vault(vault: &mut Account<Vault>, amount: u64) -> Result<()> {
+    let available = vault_lamports_available(vault)?;
+    let expected = vault.balance.checked_add(amount).ok_or(ProtocolError::MathOverflow)?;
+    require!(available >= expected, ProtocolError::VaultOutOfSync);
+
+    vault.balance = available;
+    Ok(())
+}
+
+// Moves `amount` lamports out of the vault. The vault is owned by this program,
+// so its lamports are moved directly.
+pub fn debit_vault<'info>(vault: &mut Account<'info, Vault>, to: &AccountInfo<'info>, amount: u64) -> Result<()> {
+    credit_vault(vault, 0)?;
+    require!(
+        vault.balance >= amount,
+        AmountError::InsufficientFunds
+    );
+
+    let vault_info = vault.to_account_info();
+    **vault_info.try_borrow_mut_lamports()? = vault_info
+        .lamports()
+        .checked_sub(amount)
+        .ok_or(AmountError::InsufficientFunds)?;
+
+    **to.try_borrow_mut_lamports()? = to
+        .lamports()
+        .checked_add(amount)
+        .unwrap();
+
+    vault.balance = vault.balance.checked_sub(amount).unwrap();
+    Ok(())
+}
@@ -1,10 +1,30 @@
 use anchor_lang::prelude::*;
 
+use crate::REWARD_PRECISION;
+
 #[account]
 #[derive(InitSpace)]
 pub struct YieldAccount {
     
     pub authority: Pubkey,
     pub staked_amount: u64,
+    // The treasury `reward_per_share` at the last settlement.
+    pub last_index: u128,
+    // Rewards settled into this account but not yet claimed.
+    pub pending_rewards: u64,
     pub bump: u8,
 }
+
+impl YieldAccount {
+    // Accrues rewards earned since the last settlement. Must be called before
+    // `staked_amount` changes so the old stake is credited at the old size.
+    pub fn settle_rewards(&mut self, reward_per_share: u128) -> Result<()> {
+        let earned = (self.staked_amount as u128)
+            .checked_mul(reward_per_share.checked_sub(self.last_index).unwrap()).unwrap()
+            .checked_div(REWARD_PRECISION).unwrap();
+
+        self.pending_rewards = self.pending_rewards.checked_add(earned as u64).unwrap();
+        self.last_index = reward_per_share;
+        Ok(())
+    }
+}
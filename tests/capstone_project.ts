@@ -1,7 +1,22 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
-import { PublicKey, Keypair, LAMPORTS_PER_SOL, SystemProgram } from "@solana/web3.js";
+import { PublicKey, Keypair, LAMPORTS_PER_SOL, SystemProgram, Transaction } from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  TOKEN_2022_PROGRAM_ID,
+  createMint,
+  createAccount,
+  mintTo,
+  getAccount,
+  getAssociatedTokenAddressSync,
+  getOrCreateAssociatedTokenAccount,
+  ExtensionType,
+  getMintLen,
+  createInitializeTransferFeeConfigInstruction,
+  createInitializeMintInstruction,
+} from "@solana/spl-token";
 import { expect } from "chai";
+import { createHash } from "crypto";
 import { CapstoneProject } from "../target/types/capstone_project";
 
 describe("Capstone Project Program Tests", () => {
@@ -23,16 +38,247 @@ describe("Capstone Project Program Tests", () => {
   let userSpendingBump: number;
   let userYieldPda: PublicKey;
   let userYieldBump: number;
-  let treasuryPda: PublicKey;
-  let treasuryBump: number;
   let merchantPda: PublicKey;
   let merchantBump: number;
 
   // Test constants
   const DEPOSIT_AMOUNT = 2 * LAMPORTS_PER_SOL; // 2 SOL
-  const SPEND_AMOUNT = 0.5 * LAMPORTS_PER_SOL; // 0.5 SOL
+  const SPEND_USD = 10_000_000; // $10.00 of limit, in micro-USD
+  const PAYMENT_AMOUNT = 0.1 * LAMPORTS_PER_SOL; // 0.1 SOL
   const STAKE_AMOUNT = 1 * LAMPORTS_PER_SOL; // 1 SOL
 
+  // Protocol config (singleton, administered by the provider wallet)
+  const [configPda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("config")],
+    program.programId
+  );
+  // The program's upgrade authority, the provider wallet, is the only one who can create it.
+  const [programDataPda] = PublicKey.findProgramAddressSync(
+    [program.programId.toBuffer()],
+    new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+  );
+  // Pyth SOL/USD price feed
+  const SOL_USD_FEED_ID = Array.from(
+    Buffer.from("ef0d8b6fda2ceba41da15d4095d1da392a0d2f8ed0c6c7bc0f4cfac8c280b56d", "hex")
+  );
+  // Pyth SOL/USD PriceUpdateV2 fixture (see Anchor.toml), published when the
+  // fixture was generated. Prices are accepted for long enough to run the suite.
+  const SOL_USD_PRICE_UPDATE = new PublicKey("7UVimffxr9ow1uXYxsr4LHAcV58mLzhmwaeKvJ1pjLiE");
+  // The same feed at a lower price, to move the price with.
+  const SOL_USD_LOW_PRICE_UPDATE = new PublicKey("H3oobe2SfVcLjBSr1p8cNMZZCF2YSgoWkTSE7ZmxnPmE");
+  const TEST_MAX_PRICE_AGE = 60 * 60;
+
+  // Decodes price, conf and exponent from a fully verified PriceUpdateV2 account:
+  // discriminator (8) + write authority (32) + verification level (1) + feed id (32).
+  const readSolUsdPrice = async (priceUpdate: PublicKey = SOL_USD_PRICE_UPDATE) => {
+    const info = await connection.getAccountInfo(priceUpdate);
+    const data = info.data;
+    return {
+      price: new anchor.BN(data.readBigInt64LE(73).toString()),
+      conf: new anchor.BN(data.readBigUInt64LE(81).toString()),
+      exponent: data.readInt32LE(89),
+    };
+  };
+
+  // Mirrors the program: SOL is valued at price - k * conf when it counts as
+  // collateral and at price + k * conf when it counts as debt, in micro-USD.
+  const solPriceBounds = async (priceUpdate: PublicKey = SOL_USD_PRICE_UPDATE) => {
+    const { price, conf, exponent } = await readSolUsdPrice(priceUpdate);
+    const spread = conf.mul(defaultConfigParams().confidenceMultiplierBps).div(new anchor.BN(10_000));
+    return { collateralPrice: price.sub(spread), debtPrice: price.add(spread), exponent };
+  };
+  const solValueUsd = async (
+    lamports: number,
+    side: "collateral" | "debt",
+    priceUpdate: PublicKey = SOL_USD_PRICE_UPDATE
+  ) => {
+    const { collateralPrice, debtPrice, exponent } = await solPriceBounds(priceUpdate);
+    const bound = side === "collateral" ? collateralPrice : debtPrice;
+    const decimals = exponent + 6 - 9;
+    const scaled = new anchor.BN(lamports).mul(bound);
+    return decimals >= 0
+      ? scaled.mul(new anchor.BN(10).pow(new anchor.BN(decimals)))
+      : scaled.div(new anchor.BN(10).pow(new anchor.BN(-decimals)));
+  };
+  const collateralValueUsd = (lamports: number, priceUpdate?: PublicKey) =>
+    solValueUsd(lamports, "collateral", priceUpdate);
+  const debtValueUsd = (lamports: number, priceUpdate?: PublicKey) => solValueUsd(lamports, "debt", priceUpdate);
+
+  // The spending limit is the collateral value times the loan-to-value ratio.
+  const expectedSpendingLimit = async (collateralLamports: number, priceUpdate?: PublicKey) => {
+    const usd = await collateralValueUsd(collateralLamports, priceUpdate);
+    return usd.mul(defaultConfigParams().loanToValueBps).div(new anchor.BN(10_000)).toNumber();
+  };
+
+  // Mirrors `plan_liquidation` for a position backed by SOL alone that is not
+  // seized in full: the debt repaid to land back on the target ratio, and the
+  // lamports seized for it, bonus included.
+  const expectedLiquidation = async (debt: anchor.BN, lamports: number, priceUpdate: PublicKey) => {
+    const params = defaultConfigParams();
+    const { debtPrice, exponent } = await solPriceBounds(priceUpdate);
+    const collateral = await collateralValueUsd(lamports, priceUpdate);
+    const targetBps = params.targetCollateralRatio.muln(100);
+    const debtRepaid = anchor.BN.min(
+      targetBps.mul(debt).sub(collateral.muln(10_000)).div(targetBps.subn(10_000).sub(params.liquidationBonusBps)),
+      debt
+    );
+    const lamportsToRepay = debtRepaid.mul(new anchor.BN(10).pow(new anchor.BN(9 - 6 - exponent))).div(debtPrice);
+    const lamportsSeized = lamportsToRepay.mul(params.liquidationBonusBps.addn(10_000)).divn(10_000);
+    return { debtRepaid, lamportsSeized, liquidatorBonus: lamportsSeized.sub(lamportsToRepay) };
+  };
+
+  // New merchants cannot take payments until the admin verifies them.
+  const verifyMerchant = (merchantAccount: PublicKey) =>
+    program.methods
+      .setMerchantStatus(true, false)
+      .accounts({
+        admin: provider.wallet.publicKey,
+        config: configPda,
+        merchantAccount,
+      })
+      .rpc();
+
+  // A vault's balance is always exactly the lamports it holds above its rent-exempt minimum.
+  const expectVaultInSync = async (vault: PublicKey) => {
+    const info = await connection.getAccountInfo(vault);
+    const rentExemptMinimum = await connection.getMinimumBalanceForRentExemption(info.data.length);
+    const { balance } = await program.account.vault.fetch(vault);
+    expect(balance.toNumber()).to.equal(info.lamports - rentExemptMinimum);
+  };
+
+  // Spending accounts record the debt that withdrawals, payments and liquidation check.
+  const initializeSpendingAccount = () =>
+    program.methods
+      .initializeSpendingAccount()
+      .accounts({
+        authority: user.publicKey,
+        spendingAccount: userSpendingPda,
+        vault: userVaultPda,
+        treasury: treasuryPda,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user])
+      .rpc();
+
+  // Receipts without an order ID are keyed by the payer and a nonce it picks.
+  const receiptPda = (merchantAccount: PublicKey, payer: PublicKey, nonce: anchor.BN) =>
+    PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("receipt"),
+        merchantAccount.toBuffer(),
+        createHash("sha256")
+          .update("payer")
+          .update(payer.toBuffer())
+          .update(nonce.toArrayLike(Buffer, "le", 8))
+          .digest(),
+      ],
+      program.programId
+    )[0];
+  // Payments with an order ID are keyed by its hash instead.
+  const orderReceiptPda = (merchantAccount: PublicKey, orderId: string) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("receipt"), merchantAccount.toBuffer(), createHash("sha256").update("order").update(orderId).digest()],
+      program.programId
+    )[0];
+  let receiptNonce = 0;
+  const nextReceipt = (merchantAccount: PublicKey, payer: PublicKey = user.publicKey) => {
+    const nonce = new anchor.BN(++receiptNonce);
+    return { receipt: receiptPda(merchantAccount, payer, nonce), nonce };
+  };
+
+  // Decodes the Anchor events emitted by a confirmed transaction.
+  const eventParser = new anchor.EventParser(program.programId, program.coder);
+  const emittedEvents = async (signature: string) => {
+    const tx = await connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    return Array.from(eventParser.parseLogs(tx.meta.logMessages));
+  };
+
+  // Test collateral is priced off the SOL/USD feed.
+  const collateralParams = () => ({
+    priceFeedId: SOL_USD_FEED_ID,
+    collateralFactorBps: new anchor.BN(7_000),
+    liquidationThresholdBps: new anchor.BN(8_000),
+  });
+
+  const collateralAssetPda = (mint: PublicKey) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("collateral"), mint.toBuffer()],
+      program.programId
+    )[0];
+
+  // Token vaults can only be opened for registered collateral mints.
+  const addCollateralAsset = (mint: PublicKey) =>
+    program.methods
+      .addCollateralAsset(collateralParams())
+      .accounts({
+        admin: provider.wallet.publicKey,
+        config: configPda,
+        mint,
+        collateralAsset: collateralAssetPda(mint),
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+  // Each of a user's token vaults is passed as a (CollateralAsset, TokenVault, PriceUpdateV2) triple.
+  const collateralAccounts = (mint: PublicKey, tokenVault: PublicKey) => [
+    { pubkey: collateralAssetPda(mint), isWritable: false, isSigner: false },
+    { pubkey: tokenVault, isWritable: false, isSigner: false },
+    { pubkey: SOL_USD_PRICE_UPDATE, isWritable: false, isSigner: false },
+  ];
+
+  // Shared treasury (singleton, pooled across every user)
+  const [treasuryPda, treasuryBump] = PublicKey.findProgramAddressSync(
+    [Buffer.from("treasury")],
+    program.programId
+  );
+
+  const defaultConfigParams = () => ({
+    solUsdFeedId: SOL_USD_FEED_ID,
+    maximumAge: new anchor.BN(TEST_MAX_PRICE_AGE),
+    liquidationThreshold: new anchor.BN(120),
+    targetCollateralRatio: new anchor.BN(150),
+    loanToValueBps: new anchor.BN(5_000),
+    liquidationBonusBps: new anchor.BN(500),
+    confidenceMultiplierBps: new anchor.BN(20_000),
+    maxConfidenceRatioBps: new anchor.BN(200),
+    // Payments are fee-free unless a test sets a merchant fee.
+    merchantFeeBps: new anchor.BN(0),
+    // Spend accrues no interest unless a test sets a borrow rate.
+    borrowRateBps: new anchor.BN(0),
+  });
+
+  before(async () => {
+    // The config PDA is shared by every test, so only create it once per validator.
+    const existing = await connection.getAccountInfo(configPda);
+    if (existing === null) {
+      await program.methods
+        .initializeConfig(defaultConfigParams())
+        .accounts({
+          admin: provider.wallet.publicKey,
+          program: program.programId,
+          programData: programDataPda,
+          config: configPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+    }
+
+    if ((await connection.getAccountInfo(treasuryPda)) === null) {
+      await program.methods
+        .initializeTreasury()
+        .accounts({
+          authority: provider.wallet.publicKey,
+          config: configPda,
+          treasury: treasuryPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+    }
+  });
+
   beforeEach(async () => {
     // Generate fresh keypairs for each test
     user = Keypair.generate();
@@ -63,11 +309,6 @@ describe("Capstone Project Program Tests", () => {
       program.programId
     );
 
-    [treasuryPda, treasuryBump] = PublicKey.findProgramAddressSync(
-      [Buffer.from("treasury"), user.publicKey.toBuffer()],
-      program.programId
-    );
-
     [merchantPda, merchantBump] = PublicKey.findProgramAddressSync(
       [Buffer.from("merchant"), merchant.publicKey.toBuffer()],
       program.programId
@@ -109,7 +350,7 @@ describe("Capstone Project Program Tests", () => {
       const userBalanceBefore = await connection.getBalance(user.publicKey);
 
       // Deposit SOL
-      await program.methods
+      const signature = await program.methods
         .deposit(new anchor.BN(DEPOSIT_AMOUNT))
         .accounts({
           authority: user.publicKey,
@@ -117,12 +358,19 @@ describe("Capstone Project Program Tests", () => {
           systemProgram: SystemProgram.programId,
         })
         .signers([user])
-        .rpc();
+        .rpc({ commitment: "confirmed" });
 
       // Verify deposit
       const vaultAccount = await program.account.vault.fetch(userVaultPda);
       expect(vaultAccount.balance.toNumber()).to.equal(DEPOSIT_AMOUNT);
 
+      const [deposited] = await emittedEvents(signature);
+      expect(deposited.name).to.equal("deposited");
+      expect(deposited.data.user.toString()).to.equal(user.publicKey.toString());
+      expect(deposited.data.amount.toNumber()).to.equal(DEPOSIT_AMOUNT);
+      expect(deposited.data.vaultBalance.toNumber()).to.equal(DEPOSIT_AMOUNT);
+      await expectVaultInSync(userVaultPda);
+
       // Verify user balance decreased
       const userBalanceAfter = await connection.getBalance(user.publicKey);
       expect(userBalanceAfter).to.be.lessThan(userBalanceBefore);
@@ -153,13 +401,18 @@ describe("Capstone Project Program Tests", () => {
       // Get user balance before withdrawal
       const userBalanceBefore = await connection.getBalance(user.publicKey);
 
-      // Withdraw half
+      // Withdraw half. Without a spending account there is no debt to check.
       const withdrawAmount = DEPOSIT_AMOUNT / 2;
       await program.methods
         .withdraw(new anchor.BN(withdrawAmount))
         .accounts({
           vault: userVaultPda,
           authority: user.publicKey,
+          config: configPda,
+          yieldAccount: null,
+          spendingAccount: null,
+          treasury: null,
+          priceUpdate: null,
           systemProgram: SystemProgram.programId,
         })
         .signers([user])
@@ -168,6 +421,7 @@ describe("Capstone Project Program Tests", () => {
       // Verify withdrawal
       const vaultAccount = await program.account.vault.fetch(userVaultPda);
       expect(vaultAccount.balance.toNumber()).to.equal(DEPOSIT_AMOUNT - withdrawAmount);
+      await expectVaultInSync(userVaultPda);
 
       const userBalanceAfter = await connection.getBalance(user.publicKey);
       expect(userBalanceAfter).to.be.greaterThan(userBalanceBefore);
@@ -195,6 +449,8 @@ describe("Capstone Project Program Tests", () => {
         .signers([user])
         .rpc();
 
+      await initializeSpendingAccount();
+
       // Try to withdraw more than available
       try {
         await program.methods
@@ -202,6 +458,11 @@ describe("Capstone Project Program Tests", () => {
           .accounts({
             vault: userVaultPda,
             authority: user.publicKey,
+            config: configPda,
+            yieldAccount: null,
+            spendingAccount: userSpendingPda,
+            treasury: treasuryPda,
+            priceUpdate: SOL_USD_PRICE_UPDATE,
             systemProgram: SystemProgram.programId,
           })
           .signers([user])
@@ -213,6 +474,97 @@ describe("Capstone Project Program Tests", () => {
       }
     });
 
+    it("Should fold lamports sent straight to the vault into its balance", async () => {
+      await program.methods
+        .initialize()
+        .accounts({
+          user: user.publicKey,
+          vault: userVaultPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      // Anyone can transfer SOL to the vault PDA without going through `deposit`.
+      const stray = 1_000_000;
+      await provider.sendAndConfirm(
+        new Transaction().add(
+          SystemProgram.transfer({ fromPubkey: user.publicKey, toPubkey: userVaultPda, lamports: stray })
+        ),
+        [user]
+      );
+
+      await program.methods
+        .deposit(new anchor.BN(DEPOSIT_AMOUNT))
+        .accounts({
+          authority: user.publicKey,
+          vault: userVaultPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      const vaultAccount = await program.account.vault.fetch(userVaultPda);
+      expect(vaultAccount.balance.toNumber()).to.equal(DEPOSIT_AMOUNT + stray);
+      await expectVaultInSync(userVaultPda);
+    });
+
+    it("Should let only the admin sync a vault to its lamports", async () => {
+      await program.methods
+        .initialize()
+        .accounts({
+          user: user.publicKey,
+          vault: userVaultPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      const stray = 1_000_000;
+      await provider.sendAndConfirm(
+        new Transaction().add(
+          SystemProgram.transfer({ fromPubkey: user.publicKey, toPubkey: userVaultPda, lamports: stray })
+        ),
+        [user]
+      );
+
+      try {
+        await program.methods
+          .syncVault()
+          .accounts({
+            admin: user.publicKey,
+            config: configPda,
+            user: user.publicKey,
+            vault: userVaultPda,
+          })
+          .signers([user])
+          .rpc();
+
+        expect.fail("Should have thrown unauthorized error");
+      } catch (error) {
+        expect(error.toString()).to.include("Unauthorized");
+      }
+
+      const signature = await program.methods
+        .syncVault()
+        .accounts({
+          admin: provider.wallet.publicKey,
+          config: configPda,
+          user: user.publicKey,
+          vault: userVaultPda,
+        })
+        .rpc({ commitment: "confirmed" });
+
+      const vaultAccount = await program.account.vault.fetch(userVaultPda);
+      expect(vaultAccount.balance.toNumber()).to.equal(stray);
+      await expectVaultInSync(userVaultPda);
+
+      const [synced] = await emittedEvents(signature);
+      expect(synced.name).to.equal("vaultSynced");
+      expect(synced.data.previousBalance.toNumber()).to.equal(0);
+      expect(synced.data.balance.toNumber()).to.equal(stray);
+    });
+
     it("Should prevent unauthorized vault access", async () => {
       // Initialize vault with user
       await program.methods
@@ -283,6 +635,8 @@ describe("Capstone Project Program Tests", () => {
         .accounts({
           authority: user.publicKey,
           spendingAccount: userSpendingPda,
+          vault: userVaultPda,
+          treasury: treasuryPda,
           systemProgram: SystemProgram.programId,
         })
         .signers([user])
@@ -290,17 +644,23 @@ describe("Capstone Project Program Tests", () => {
 
       const spendingAccount = await program.account.spendingAccount.fetch(userSpendingPda);
       expect(spendingAccount.authority.toString()).to.equal(user.publicKey.toString());
+      expect(spendingAccount.denomination).to.deep.equal({ usdMicro: {} });
       expect(spendingAccount.spendingLimit.toNumber()).to.equal(0);
       expect(spendingAccount.amountSpent.toNumber()).to.equal(0);
+      expect(spendingAccount.periodSeconds.toNumber()).to.equal(30 * 24 * 60 * 60);
+      expect(spendingAccount.windowStart.toNumber()).to.be.greaterThan(0);
+      expect(spendingAccount.windowSpent.toNumber()).to.equal(0);
     });
 
-    it("Should update spending limit based on vault balance", async () => {
+    it("Should update spending limit based on collateral value", async () => {
       // Initialize spending account
       await program.methods
         .initializeSpendingAccount()
         .accounts({
           authority: user.publicKey,
           spendingAccount: userSpendingPda,
+          vault: userVaultPda,
+          treasury: treasuryPda,
           systemProgram: SystemProgram.programId,
         })
         .signers([user])
@@ -311,60 +671,54 @@ describe("Capstone Project Program Tests", () => {
         .updateSpendingLimit()
         .accounts({
           authority: user.publicKey,
+          config: configPda,
           vault: userVaultPda,
+          yieldAccount: null,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
           spendingAccount: userSpendingPda,
         })
         .signers([user])
         .rpc();
 
       const spendingAccount = await program.account.spendingAccount.fetch(userSpendingPda);
-      // Should be 50% of vault balance
-      expect(spendingAccount.spendingLimit.toNumber()).to.equal(DEPOSIT_AMOUNT * 0.5);
+      // Should be the loan-to-value share of the vault's USD value
+      expect(spendingAccount.spendingLimit.toNumber()).to.equal(
+        await expectedSpendingLimit(DEPOSIT_AMOUNT)
+      );
+      expect(spendingAccount.spendingLimit.toNumber()).to.be.greaterThan(SPEND_USD);
     });
 
-    it("Should authorize spend within limit", async () => {
-      // Setup spending account
+    it("Should count staked funds towards the spending limit", async () => {
       await program.methods
         .initializeSpendingAccount()
         .accounts({
           authority: user.publicKey,
           spendingAccount: userSpendingPda,
-          systemProgram: SystemProgram.programId,
-        })
-        .signers([user])
-        .rpc();
-
-      await program.methods
-        .updateSpendingLimit()
-        .accounts({
-          authority: user.publicKey,
           vault: userVaultPda,
-          spendingAccount: userSpendingPda,
+          treasury: treasuryPda,
+          systemProgram: SystemProgram.programId,
         })
         .signers([user])
         .rpc();
 
-      // Authorize spend
       await program.methods
-        .authorizeSpend(new anchor.BN(SPEND_AMOUNT))
+        .initializeYieldAccount()
         .accounts({
           authority: user.publicKey,
-          spendingAccount: userSpendingPda,
+          yieldAccount: userYieldPda,
+          treasury: treasuryPda,
+          systemProgram: SystemProgram.programId,
         })
         .signers([user])
         .rpc();
 
-      const spendingAccount = await program.account.spendingAccount.fetch(userSpendingPda);
-      expect(spendingAccount.amountSpent.toNumber()).to.equal(SPEND_AMOUNT);
-    });
-
-    it("Should fail to authorize spend beyond limit", async () => {
-      // Setup spending account with low limit
       await program.methods
-        .initializeSpendingAccount()
+        .stake(new anchor.BN(STAKE_AMOUNT))
         .accounts({
           authority: user.publicKey,
-          spendingAccount: userSpendingPda,
+          vault: userVaultPda,
+          yieldAccount: userYieldPda,
+          treasury: treasuryPda,
           systemProgram: SystemProgram.programId,
         })
         .signers([user])
@@ -374,36 +728,31 @@ describe("Capstone Project Program Tests", () => {
         .updateSpendingLimit()
         .accounts({
           authority: user.publicKey,
+          config: configPda,
           vault: userVaultPda,
+          yieldAccount: userYieldPda,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
           spendingAccount: userSpendingPda,
         })
         .signers([user])
         .rpc();
 
-      // Try to spend more than limit
-      try {
-        await program.methods
-          .authorizeSpend(new anchor.BN(DEPOSIT_AMOUNT)) // More than 50% limit
-          .accounts({
-            authority: user.publicKey,
-            spendingAccount: userSpendingPda,
-          })
-          .signers([user])
-          .rpc();
-        
-        expect.fail("Should have thrown spending limit exceeded error");
-      } catch (error) {
-        expect(error.toString()).to.include("SpendingLimitExceeded");
-      }
+      // Vault plus stake is still the full deposit.
+      const spendingAccount = await program.account.spendingAccount.fetch(userSpendingPda);
+      expect(spendingAccount.spendingLimit.toNumber()).to.equal(
+        await expectedSpendingLimit(DEPOSIT_AMOUNT)
+      );
     });
 
-    it("Should reset spend tracker", async () => {
-      // Setup and spend some amount
+    it("Should authorize spend within limit", async () => {
+      // Setup spending account
       await program.methods
         .initializeSpendingAccount()
         .accounts({
           authority: user.publicKey,
           spendingAccount: userSpendingPda,
+          vault: userVaultPda,
+          treasury: treasuryPda,
           systemProgram: SystemProgram.programId,
         })
         .signers([user])
@@ -413,163 +762,2940 @@ describe("Capstone Project Program Tests", () => {
         .updateSpendingLimit()
         .accounts({
           authority: user.publicKey,
+          config: configPda,
           vault: userVaultPda,
+          yieldAccount: null,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
           spendingAccount: userSpendingPda,
         })
         .signers([user])
         .rpc();
 
+      // Authorize spend
       await program.methods
-        .authorizeSpend(new anchor.BN(SPEND_AMOUNT))
+        .authorizeSpend(new anchor.BN(SPEND_USD))
         .accounts({
           authority: user.publicKey,
+          config: configPda,
+          vault: userVaultPda,
+          yieldAccount: null,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
           spendingAccount: userSpendingPda,
+          treasury: treasuryPda,
         })
         .signers([user])
         .rpc();
 
-      // Reset spend tracker
+      const spendingAccount = await program.account.spendingAccount.fetch(userSpendingPda);
+      expect(spendingAccount.amountSpent.toNumber()).to.equal(SPEND_USD);
+      expect(spendingAccount.windowSpent.toNumber()).to.equal(SPEND_USD);
+    });
+
+    it("Should re-price the collateral on every spend", async () => {
+      await initializeSpendingAccount();
+
       await program.methods
-        .resetSpendTracker()
+        .updateSpendingLimit()
         .accounts({
           authority: user.publicKey,
+          config: configPda,
+          vault: userVaultPda,
+          yieldAccount: null,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
           spendingAccount: userSpendingPda,
         })
         .signers([user])
         .rpc();
 
+      const authorize = (amount: number, priceUpdate: PublicKey) =>
+        program.methods
+          .authorizeSpend(new anchor.BN(amount))
+          .accounts({
+            authority: user.publicKey,
+            config: configPda,
+            vault: userVaultPda,
+            yieldAccount: null,
+            priceUpdate,
+            spendingAccount: userSpendingPda,
+            treasury: treasuryPda,
+          })
+          .signers([user])
+          .rpc();
+
+      // The stored limit was set at the higher price. Once the price falls, the
+      // collateral only backs what it is worth now.
+      const { spendingLimit } = await program.account.spendingAccount.fetch(userSpendingPda);
+      const lowLimit = await expectedSpendingLimit(DEPOSIT_AMOUNT, SOL_USD_LOW_PRICE_UPDATE);
+      expect(lowLimit).to.be.lessThan(spendingLimit.toNumber());
+
+      try {
+        await authorize(spendingLimit.toNumber(), SOL_USD_LOW_PRICE_UPDATE);
+        expect.fail("Should have thrown below initial margin error");
+      } catch (error) {
+        expect(error.toString()).to.include("BelowInitialMargin");
+      }
+
+      await authorize(lowLimit, SOL_USD_LOW_PRICE_UPDATE);
       const spendingAccount = await program.account.spendingAccount.fetch(userSpendingPda);
-      expect(spendingAccount.amountSpent.toNumber()).to.equal(0);
+      expect(spendingAccount.amountSpent.toNumber()).to.equal(lowLimit);
     });
-  });
 
-  describe("Treasury and Yield Tests", () => {
-    beforeEach(async () => {
-      // Setup vault for yield tests
+    it("Should accrue interest on outstanding spend", async () => {
       await program.methods
-        .initialize()
+        .initializeSpendingAccount()
         .accounts({
-          user: user.publicKey,
+          authority: user.publicKey,
+          spendingAccount: userSpendingPda,
           vault: userVaultPda,
+          treasury: treasuryPda,
           systemProgram: SystemProgram.programId,
         })
         .signers([user])
         .rpc();
 
       await program.methods
-        .deposit(new anchor.BN(DEPOSIT_AMOUNT))
+        .updateSpendingLimit()
         .accounts({
           authority: user.publicKey,
+          config: configPda,
           vault: userVaultPda,
-          systemProgram: SystemProgram.programId,
+          yieldAccount: null,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
+          spendingAccount: userSpendingPda,
         })
         .signers([user])
         .rpc();
-    });
 
-    it("Should initialize treasury", async () => {
+      // 100% a year, so a few seconds of interest shows up on a full-limit spend.
       await program.methods
-        .initializeTreasury()
+        .updateConfig({ ...defaultConfigParams(), borrowRateBps: new anchor.BN(10_000) })
         .accounts({
+          admin: provider.wallet.publicKey,
+          config: configPda,
           treasury: treasuryPda,
-          authority: user.publicKey,
-          systemProgram: SystemProgram.programId,
         })
-        .signers([user])
         .rpc();
 
-      // Verify treasury was created
-      const treasuryInfo = await connection.getAccountInfo(treasuryPda);
-      expect(treasuryInfo).to.not.be.null;
+      try {
+        const authorize = (amount: number) =>
+          program.methods
+            .authorizeSpend(new anchor.BN(amount))
+            .accounts({
+              authority: user.publicKey,
+              config: configPda,
+              vault: userVaultPda,
+              yieldAccount: null,
+              priceUpdate: SOL_USD_PRICE_UPDATE,
+              spendingAccount: userSpendingPda,
+              treasury: treasuryPda,
+            })
+            .signers([user])
+            .rpc();
+
+        const { spendingLimit } = await program.account.spendingAccount.fetch(userSpendingPda);
+        await authorize(spendingLimit.toNumber());
+
+        const before = await program.account.spendingAccount.fetch(userSpendingPda);
+        expect(before.amountSpent.toNumber()).to.equal(spendingLimit.toNumber());
+
+        await new Promise(resolve => setTimeout(resolve, 2000));
+
+        // A zero spend only brings the debt up to date.
+        await authorize(0);
+
+        const after = await program.account.spendingAccount.fetch(userSpendingPda);
+        const treasury = await program.account.treasury.fetch(treasuryPda);
+        expect(after.amountSpent.gt(before.amountSpent)).to.be.true;
+        expect(after.borrowIndex.toString()).to.equal(treasury.borrowIndex.toString());
+        expect(after.borrowIndex.gt(before.borrowIndex)).to.be.true;
+      } finally {
+        await program.methods
+          .updateConfig(defaultConfigParams())
+          .accounts({
+            admin: provider.wallet.publicKey,
+            config: configPda,
+            treasury: treasuryPda,
+          })
+          .rpc();
+      }
     });
 
-    it("Should initialize yield account", async () => {
+    it("Should report position health without writing to the accounts", async () => {
       await program.methods
-        .initializeYieldAccount()
+        .initializeSpendingAccount()
         .accounts({
           authority: user.publicKey,
-          yieldAccount: userYieldPda,
+          spendingAccount: userSpendingPda,
+          vault: userVaultPda,
+          treasury: treasuryPda,
           systemProgram: SystemProgram.programId,
         })
         .signers([user])
         .rpc();
 
-      const yieldAccount = await program.account.yieldAccount.fetch(userYieldPda);
-      expect(yieldAccount.authority.toString()).to.equal(user.publicKey.toString());
-      expect(yieldAccount.stakedAmount.toNumber()).to.equal(0);
-    });
-
-    it("Should stake funds to treasury", async () => {
-      // Initialize treasury and yield account
       await program.methods
-        .initializeTreasury()
+        .updateSpendingLimit()
         .accounts({
-          treasury: treasuryPda,
           authority: user.publicKey,
-          systemProgram: SystemProgram.programId,
+          config: configPda,
+          vault: userVaultPda,
+          yieldAccount: null,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
+          spendingAccount: userSpendingPda,
         })
         .signers([user])
         .rpc();
 
       await program.methods
-        .initializeYieldAccount()
+        .authorizeSpend(new anchor.BN(SPEND_USD))
         .accounts({
           authority: user.publicKey,
-          yieldAccount: userYieldPda,
-          systemProgram: SystemProgram.programId,
+          config: configPda,
+          vault: userVaultPda,
+          yieldAccount: null,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
+          spendingAccount: userSpendingPda,
+          treasury: treasuryPda,
         })
         .signers([user])
         .rpc();
 
-      // Get balances before staking
-      const vaultBefore = await program.account.vault.fetch(userVaultPda);
-      const treasuryBalanceBefore = await connection.getBalance(treasuryPda);
-
-      // Stake funds
-      await program.methods
-        .stake(new anchor.BN(STAKE_AMOUNT))
+      // Simulated, so any keeper can query any user's position.
+      const health = await program.methods
+        .getPositionHealth()
         .accounts({
-          authority: user.publicKey,
+          user: user.publicKey,
+          config: configPda,
           vault: userVaultPda,
-          yieldAccount: userYieldPda,
+          yieldAccount: null,
+          spendingAccount: userSpendingPda,
           treasury: treasuryPda,
-          systemProgram: SystemProgram.programId,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
         })
-        .signers([user])
-        .rpc();
+        .view();
+
+      expect(health.debt.toNumber()).to.equal(SPEND_USD);
+      expect(health.maxBorrowable.toNumber()).to.equal(
+        (await expectedSpendingLimit(DEPOSIT_AMOUNT)) - SPEND_USD
+      );
+      // The vault balance can be seized, so it counts towards liquidation too.
+      const collateralUsd = await collateralValueUsd(DEPOSIT_AMOUNT);
+      expect(health.collateralValue.toNumber()).to.equal(collateralUsd.toNumber());
+      expect(health.collateralRatio.toNumber()).to.equal(
+        collateralUsd.muln(100).divn(SPEND_USD).toNumber()
+      );
+      // The SOL price (micro-USD per SOL) at which the deposit covers the spend at
+      // the liquidation threshold.
+      expect(health.liquidationPrice.toNumber()).to.equal(
+        new anchor.BN(SPEND_USD)
+          .mul(defaultConfigParams().liquidationThreshold)
+          .mul(new anchor.BN(LAMPORTS_PER_SOL))
+          .divn(100)
+          .div(new anchor.BN(DEPOSIT_AMOUNT))
+          .toNumber()
+      );
+
+      const spendingAccount = await program.account.spendingAccount.fetch(userSpendingPda);
+      expect(spendingAccount.amountSpent.toNumber()).to.equal(SPEND_USD);
+    });
+
+    it("Should block withdrawals that leave spend without enough collateral", async () => {
+      await initializeSpendingAccount();
+
+      await program.methods
+        .updateSpendingLimit()
+        .accounts({
+          authority: user.publicKey,
+          config: configPda,
+          vault: userVaultPda,
+          yieldAccount: null,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
+          spendingAccount: userSpendingPda,
+        })
+        .signers([user])
+        .rpc();
+
+      // Spend half the limit, so half the deposit has to stay behind.
+      const { spendingLimit } = await program.account.spendingAccount.fetch(userSpendingPda);
+      await program.methods
+        .authorizeSpend(spendingLimit.divn(2))
+        .accounts({
+          authority: user.publicKey,
+          config: configPda,
+          vault: userVaultPda,
+          yieldAccount: null,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
+          spendingAccount: userSpendingPda,
+          treasury: treasuryPda,
+        })
+        .signers([user])
+        .rpc();
+
+      const withdraw = (amount: number) =>
+        program.methods
+          .withdraw(new anchor.BN(amount))
+          .accounts({
+            vault: userVaultPda,
+            authority: user.publicKey,
+            config: configPda,
+            yieldAccount: null,
+            spendingAccount: userSpendingPda,
+            treasury: treasuryPda,
+            priceUpdate: SOL_USD_PRICE_UPDATE,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user])
+          .rpc();
+
+      await withdraw(DEPOSIT_AMOUNT / 4);
+
+      try {
+        await withdraw(DEPOSIT_AMOUNT / 2);
+        expect.fail("Should have thrown below initial margin error");
+      } catch (error) {
+        expect(error.toString()).to.include("BelowInitialMargin");
+      }
+
+      // Leaving the spending account out doesn't skip the check.
+      try {
+        await program.methods
+          .withdraw(new anchor.BN(DEPOSIT_AMOUNT / 2))
+          .accounts({
+            vault: userVaultPda,
+            authority: user.publicKey,
+            config: configPda,
+            yieldAccount: null,
+            spendingAccount: null,
+            treasury: null,
+            priceUpdate: null,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user])
+          .rpc();
+        expect.fail("Should have thrown margin accounts required error");
+      } catch (error) {
+        expect(error.toString()).to.include("MarginAccountsRequired");
+      }
+
+      const vault = await program.account.vault.fetch(userVaultPda);
+      expect(vault.balance.toNumber()).to.equal(DEPOSIT_AMOUNT * 3 / 4);
+    });
+
+    it("Should fail to authorize spend beyond limit", async () => {
+      // Setup spending account with low limit
+      await program.methods
+        .initializeSpendingAccount()
+        .accounts({
+          authority: user.publicKey,
+          spendingAccount: userSpendingPda,
+          vault: userVaultPda,
+          treasury: treasuryPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      await program.methods
+        .updateSpendingLimit()
+        .accounts({
+          authority: user.publicKey,
+          config: configPda,
+          vault: userVaultPda,
+          yieldAccount: null,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
+          spendingAccount: userSpendingPda,
+        })
+        .signers([user])
+        .rpc();
+
+      // Try to spend more than limit
+      try {
+        await program.methods
+          .authorizeSpend(new anchor.BN(DEPOSIT_AMOUNT)) // More than the loan-to-value limit
+          .accounts({
+            authority: user.publicKey,
+            config: configPda,
+            vault: userVaultPda,
+            yieldAccount: null,
+            priceUpdate: SOL_USD_PRICE_UPDATE,
+            spendingAccount: userSpendingPda,
+            treasury: treasuryPda,
+          })
+          .signers([user])
+          .rpc();
+        
+        expect.fail("Should have thrown spending limit exceeded error");
+      } catch (error) {
+        expect(error.toString()).to.include("SpendingLimitExceeded");
+      }
+    });
+
+    it("Should partially repay outstanding spend", async () => {
+      await program.methods
+        .initializeSpendingAccount()
+        .accounts({
+          authority: user.publicKey,
+          spendingAccount: userSpendingPda,
+          vault: userVaultPda,
+          treasury: treasuryPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      await program.methods
+        .updateSpendingLimit()
+        .accounts({
+          authority: user.publicKey,
+          config: configPda,
+          vault: userVaultPda,
+          yieldAccount: null,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
+          spendingAccount: userSpendingPda,
+        })
+        .signers([user])
+        .rpc();
+
+      await program.methods
+        .authorizeSpend(new anchor.BN(SPEND_USD))
+        .accounts({
+          authority: user.publicKey,
+          config: configPda,
+          vault: userVaultPda,
+          yieldAccount: null,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
+          spendingAccount: userSpendingPda,
+          treasury: treasuryPda,
+        })
+        .signers([user])
+        .rpc();
+
+      // A tiny repayment is worth less than the outstanding spend.
+      const repayLamports = 10_000;
+      const repaidUsd = (await collateralValueUsd(repayLamports)).toNumber();
+      const treasuryBalanceBefore = await connection.getBalance(treasuryPda);
+
+      await program.methods
+        .repay(new anchor.BN(repayLamports))
+        .accounts({
+          authority: user.publicKey,
+          config: configPda,
+          spendingAccount: userSpendingPda,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
+          treasury: treasuryPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      const spendingAccount = await program.account.spendingAccount.fetch(userSpendingPda);
+      expect(spendingAccount.amountSpent.toNumber()).to.equal(SPEND_USD - repaidUsd);
 
-      // Verify staking
-      const vaultAfter = await program.account.vault.fetch(userVaultPda);
-      const yieldAccount = await program.account.yieldAccount.fetch(userYieldPda);
       const treasuryBalanceAfter = await connection.getBalance(treasuryPda);
+      expect(treasuryBalanceAfter).to.equal(treasuryBalanceBefore + repayLamports);
+    });
+
+    it("Should fully repay outstanding spend and keep the excess", async () => {
+      await program.methods
+        .initializeSpendingAccount()
+        .accounts({
+          authority: user.publicKey,
+          spendingAccount: userSpendingPda,
+          vault: userVaultPda,
+          treasury: treasuryPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      await program.methods
+        .updateSpendingLimit()
+        .accounts({
+          authority: user.publicKey,
+          config: configPda,
+          vault: userVaultPda,
+          yieldAccount: null,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
+          spendingAccount: userSpendingPda,
+        })
+        .signers([user])
+        .rpc();
+
+      await program.methods
+        .authorizeSpend(new anchor.BN(SPEND_USD))
+        .accounts({
+          authority: user.publicKey,
+          config: configPda,
+          vault: userVaultPda,
+          yieldAccount: null,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
+          spendingAccount: userSpendingPda,
+          treasury: treasuryPda,
+        })
+        .signers([user])
+        .rpc();
+
+      // Offer a whole SOL, far more than $10.00 of debt.
+      const treasuryBalanceBefore = await connection.getBalance(treasuryPda);
+
+      await program.methods
+        .repay(new anchor.BN(LAMPORTS_PER_SOL))
+        .accounts({
+          authority: user.publicKey,
+          config: configPda,
+          spendingAccount: userSpendingPda,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
+          treasury: treasuryPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      const spendingAccount = await program.account.spendingAccount.fetch(userSpendingPda);
+      expect(spendingAccount.amountSpent.toNumber()).to.equal(0);
+
+      const taken = (await connection.getBalance(treasuryPda)) - treasuryBalanceBefore;
+      expect(taken).to.be.greaterThan(0);
+      expect(taken).to.be.lessThan(LAMPORTS_PER_SOL);
+      expect((await collateralValueUsd(taken)).toNumber()).to.be.at.least(SPEND_USD);
+
+      // Nothing left to repay
+      try {
+        await program.methods
+          .repay(new anchor.BN(LAMPORTS_PER_SOL))
+          .accounts({
+            authority: user.publicKey,
+            config: configPda,
+            spendingAccount: userSpendingPda,
+            priceUpdate: SOL_USD_PRICE_UPDATE,
+            treasury: treasuryPda,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user])
+          .rpc();
+
+        expect.fail("Should have thrown nothing to repay error");
+      } catch (error) {
+        expect(error.toString()).to.include("NothingToRepay");
+      }
+    });
+
+    it("Should cap spend within a window at the owner's window limit", async () => {
+      await initializeSpendingAccount();
+
+      await program.methods
+        .updateSpendingLimit()
+        .accounts({
+          authority: user.publicKey,
+          config: configPda,
+          vault: userVaultPda,
+          yieldAccount: null,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
+          spendingAccount: userSpendingPda,
+        })
+        .signers([user])
+        .rpc();
+
+      await program.methods
+        .setWindowLimit(new anchor.BN(SPEND_USD))
+        .accounts({
+          authority: user.publicKey,
+          spendingAccount: userSpendingPda,
+        })
+        .signers([user])
+        .rpc();
+
+      await program.methods
+        .authorizeSpend(new anchor.BN(SPEND_USD))
+        .accounts({
+          authority: user.publicKey,
+          config: configPda,
+          vault: userVaultPda,
+          yieldAccount: null,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
+          spendingAccount: userSpendingPda,
+          treasury: treasuryPda,
+        })
+        .signers([user])
+        .rpc();
+
+      // The spending limit has room left, but this window is used up.
+      try {
+        await program.methods
+          .authorizeSpend(new anchor.BN(1))
+          .accounts({
+            authority: user.publicKey,
+            config: configPda,
+            vault: userVaultPda,
+            yieldAccount: null,
+            priceUpdate: SOL_USD_PRICE_UPDATE,
+            spendingAccount: userSpendingPda,
+            treasury: treasuryPda,
+          })
+          .signers([user])
+          .rpc();
+
+        expect.fail("Should have thrown spending limit exceeded error");
+      } catch (error) {
+        expect(error.toString()).to.include("SpendingLimitExceeded");
+      }
+
+      const spendingAccount = await program.account.spendingAccount.fetch(userSpendingPda);
+      expect(spendingAccount.windowLimit.toNumber()).to.equal(SPEND_USD);
+      expect(spendingAccount.spendingLimit.toNumber()).to.be.greaterThan(SPEND_USD);
+    });
+
+    it("Should only let the admin reset the spend window", async () => {
+      // Setup and spend some amount
+      await program.methods
+        .initializeSpendingAccount()
+        .accounts({
+          authority: user.publicKey,
+          spendingAccount: userSpendingPda,
+          vault: userVaultPda,
+          treasury: treasuryPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      await program.methods
+        .updateSpendingLimit()
+        .accounts({
+          authority: user.publicKey,
+          config: configPda,
+          vault: userVaultPda,
+          yieldAccount: null,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
+          spendingAccount: userSpendingPda,
+        })
+        .signers([user])
+        .rpc();
+
+      await program.methods
+        .authorizeSpend(new anchor.BN(SPEND_USD))
+        .accounts({
+          authority: user.publicKey,
+          config: configPda,
+          vault: userVaultPda,
+          yieldAccount: null,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
+          spendingAccount: userSpendingPda,
+          treasury: treasuryPda,
+        })
+        .signers([user])
+        .rpc();
+
+      // The owner can no longer clear their own tracker
+      try {
+        await program.methods
+          .resetSpendTracker()
+          .accounts({
+            admin: user.publicKey,
+            config: configPda,
+            user: user.publicKey,
+            spendingAccount: userSpendingPda,
+          })
+          .signers([user])
+          .rpc();
+
+        expect.fail("Should have thrown unauthorized error");
+      } catch (error) {
+        expect(error.toString()).to.include("Unauthorized");
+      }
+
+      // Admin resets the spend window
+      await program.methods
+        .resetSpendTracker()
+        .accounts({
+          admin: provider.wallet.publicKey,
+          config: configPda,
+          user: user.publicKey,
+          spendingAccount: userSpendingPda,
+        })
+        .rpc();
+
+      // Only the window is cleared; the outstanding spend is still owed.
+      const spendingAccount = await program.account.spendingAccount.fetch(userSpendingPda);
+      expect(spendingAccount.windowSpent.toNumber()).to.equal(0);
+      expect(spendingAccount.amountSpent.toNumber()).to.equal(SPEND_USD);
+    });
+  });
+
+  describe("Treasury and Yield Tests", () => {
+    beforeEach(async () => {
+      // Setup vault for yield tests
+      await program.methods
+        .initialize()
+        .accounts({
+          user: user.publicKey,
+          vault: userVaultPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      await program.methods
+        .deposit(new anchor.BN(DEPOSIT_AMOUNT))
+        .accounts({
+          authority: user.publicKey,
+          vault: userVaultPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+    });
+
+    it("Should share a single protocol treasury", async () => {
+      const treasury = await program.account.treasury.fetch(treasuryPda);
+      expect(treasury.authority.toString()).to.equal(provider.wallet.publicKey.toString());
+      expect(treasury.bump).to.equal(treasuryBump);
+    });
+
+    it("Should initialize yield account", async () => {
+      await program.methods
+        .initializeYieldAccount()
+        .accounts({
+          authority: user.publicKey,
+          yieldAccount: userYieldPda,
+          treasury: treasuryPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      const yieldAccount = await program.account.yieldAccount.fetch(userYieldPda);
+      expect(yieldAccount.authority.toString()).to.equal(user.publicKey.toString());
+      expect(yieldAccount.stakedAmount.toNumber()).to.equal(0);
+    });
+
+    it("Should stake funds to treasury", async () => {
+
+      await program.methods
+        .initializeYieldAccount()
+        .accounts({
+          authority: user.publicKey,
+          yieldAccount: userYieldPda,
+          treasury: treasuryPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      // Get balances before staking
+      const vaultBefore = await program.account.vault.fetch(userVaultPda);
+      const treasuryBalanceBefore = await connection.getBalance(treasuryPda);
+      const treasuryBefore = await program.account.treasury.fetch(treasuryPda);
+
+      // Stake funds
+      await program.methods
+        .stake(new anchor.BN(STAKE_AMOUNT))
+        .accounts({
+          authority: user.publicKey,
+          vault: userVaultPda,
+          yieldAccount: userYieldPda,
+          treasury: treasuryPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      // Verify staking
+      const vaultAfter = await program.account.vault.fetch(userVaultPda);
+      const yieldAccount = await program.account.yieldAccount.fetch(userYieldPda);
+      const treasuryBalanceAfter = await connection.getBalance(treasuryPda);
+
+      expect(vaultAfter.balance.toNumber()).to.equal(vaultBefore.balance.toNumber() - STAKE_AMOUNT);
+      await expectVaultInSync(userVaultPda);
+      expect(yieldAccount.stakedAmount.toNumber()).to.equal(STAKE_AMOUNT);
+      expect(treasuryBalanceAfter).to.equal(treasuryBalanceBefore + STAKE_AMOUNT);
+
+      const treasuryAfter = await program.account.treasury.fetch(treasuryPda);
+      expect(treasuryAfter.totalStaked.toNumber()).to.equal(
+        treasuryBefore.totalStaked.toNumber() + STAKE_AMOUNT
+      );
+    });
+
+    it("Should unstake funds from treasury", async () => {
+      // Setup: Initialize and stake first
+
+      await program.methods
+        .initializeYieldAccount()
+        .accounts({
+          authority: user.publicKey,
+          yieldAccount: userYieldPda,
+          treasury: treasuryPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      await program.methods
+        .stake(new anchor.BN(STAKE_AMOUNT))
+        .accounts({
+          authority: user.publicKey,
+          vault: userVaultPda,
+          yieldAccount: userYieldPda,
+          treasury: treasuryPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      await initializeSpendingAccount();
+
+      // Now unstake
+      const unstakeAmount = STAKE_AMOUNT / 2;
+      const vaultBefore = await program.account.vault.fetch(userVaultPda);
+      
+      await program.methods
+        .unstake(new anchor.BN(unstakeAmount))
+        .accounts({
+          authority: user.publicKey,
+          vault: userVaultPda,
+          yieldAccount: userYieldPda,
+          treasury: treasuryPda,
+        })
+        .signers([user])
+        .rpc();
+
+      // Verify unstaking
+      const vaultAfter = await program.account.vault.fetch(userVaultPda);
+      const yieldAccount = await program.account.yieldAccount.fetch(userYieldPda);
+
+      expect(vaultAfter.balance.toNumber()).to.equal(vaultBefore.balance.toNumber() + unstakeAmount);
+      await expectVaultInSync(userVaultPda);
+      expect(yieldAccount.stakedAmount.toNumber()).to.equal(STAKE_AMOUNT - unstakeAmount);
+    });
+
+    it("Should let a user with spend unstake, as the SOL stays collateral", async () => {
+      await program.methods
+        .initializeYieldAccount()
+        .accounts({
+          authority: user.publicKey,
+          yieldAccount: userYieldPda,
+          treasury: treasuryPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      await program.methods
+        .stake(new anchor.BN(STAKE_AMOUNT))
+        .accounts({
+          authority: user.publicKey,
+          vault: userVaultPda,
+          yieldAccount: userYieldPda,
+          treasury: treasuryPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      await initializeSpendingAccount();
+
+      await program.methods
+        .updateSpendingLimit()
+        .accounts({
+          authority: user.publicKey,
+          config: configPda,
+          vault: userVaultPda,
+          yieldAccount: userYieldPda,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
+          spendingAccount: userSpendingPda,
+        })
+        .signers([user])
+        .rpc();
+
+      const { spendingLimit } = await program.account.spendingAccount.fetch(userSpendingPda);
+      await program.methods
+        .authorizeSpend(spendingLimit.divn(2))
+        .accounts({
+          authority: user.publicKey,
+          config: configPda,
+          vault: userVaultPda,
+          yieldAccount: userYieldPda,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
+          spendingAccount: userSpendingPda,
+          treasury: treasuryPda,
+        })
+        .signers([user])
+        .rpc();
+
+      // Unstaked SOL lands in the vault, where it still backs the spend.
+      await program.methods
+        .unstake(new anchor.BN(STAKE_AMOUNT))
+        .accounts({
+          authority: user.publicKey,
+          vault: userVaultPda,
+          yieldAccount: userYieldPda,
+          treasury: treasuryPda,
+        })
+        .signers([user])
+        .rpc();
+
+      const yieldAccount = await program.account.yieldAccount.fetch(userYieldPda);
+      expect(yieldAccount.stakedAmount.toNumber()).to.equal(0);
+      const vault = await program.account.vault.fetch(userVaultPda);
+      expect(vault.balance.toNumber()).to.equal(DEPOSIT_AMOUNT);
+
+      // So taking it out of the vault is what the margin check stops.
+      try {
+        await program.methods
+          .withdraw(new anchor.BN(DEPOSIT_AMOUNT))
+          .accounts({
+            authority: user.publicKey,
+            config: configPda,
+            vault: userVaultPda,
+            yieldAccount: userYieldPda,
+            spendingAccount: userSpendingPda,
+            treasury: treasuryPda,
+            priceUpdate: SOL_USD_PRICE_UPDATE,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user])
+          .rpc();
+
+        expect.fail("Should have thrown below initial margin error");
+      } catch (error) {
+        expect(error.toString()).to.include("BelowInitialMargin");
+      }
+    });
+
+    it("Should pool stakes from multiple users in the shared treasury", async () => {
+      const [otherVaultPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), otherUser.publicKey.toBuffer()],
+        program.programId
+      );
+      const [otherYieldPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("yield"), otherUser.publicKey.toBuffer()],
+        program.programId
+      );
+
+      await program.methods
+        .initialize()
+        .accounts({
+          user: otherUser.publicKey,
+          vault: otherVaultPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([otherUser])
+        .rpc();
+
+      await program.methods
+        .deposit(new anchor.BN(DEPOSIT_AMOUNT))
+        .accounts({
+          authority: otherUser.publicKey,
+          vault: otherVaultPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([otherUser])
+        .rpc();
+
+      for (const [signer, yieldPda] of [
+        [user, userYieldPda],
+        [otherUser, otherYieldPda],
+      ] as [Keypair, PublicKey][]) {
+        await program.methods
+          .initializeYieldAccount()
+          .accounts({
+            authority: signer.publicKey,
+            yieldAccount: yieldPda,
+            treasury: treasuryPda,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([signer])
+          .rpc();
+      }
+
+      const treasuryBefore = await program.account.treasury.fetch(treasuryPda);
+
+      await program.methods
+        .stake(new anchor.BN(STAKE_AMOUNT))
+        .accounts({
+          authority: user.publicKey,
+          vault: userVaultPda,
+          yieldAccount: userYieldPda,
+          treasury: treasuryPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      await program.methods
+        .stake(new anchor.BN(STAKE_AMOUNT))
+        .accounts({
+          authority: otherUser.publicKey,
+          vault: otherVaultPda,
+          yieldAccount: otherYieldPda,
+          treasury: treasuryPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([otherUser])
+        .rpc();
+
+      const treasuryAfter = await program.account.treasury.fetch(treasuryPda);
+      expect(treasuryAfter.totalStaked.toNumber()).to.equal(
+        treasuryBefore.totalStaked.toNumber() + 2 * STAKE_AMOUNT
+      );
+
+      const userYield = await program.account.yieldAccount.fetch(userYieldPda);
+      const otherYield = await program.account.yieldAccount.fetch(otherYieldPda);
+      expect(userYield.stakedAmount.toNumber()).to.equal(STAKE_AMOUNT);
+      expect(otherYield.stakedAmount.toNumber()).to.equal(STAKE_AMOUNT);
+    });
+
+    it("Should accrue and claim staking rewards", async () => {
+      const REWARD_AMOUNT = 0.1 * LAMPORTS_PER_SOL;
+
+      await program.methods
+        .initializeYieldAccount()
+        .accounts({
+          authority: user.publicKey,
+          yieldAccount: userYieldPda,
+          treasury: treasuryPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      await program.methods
+        .stake(new anchor.BN(STAKE_AMOUNT))
+        .accounts({
+          authority: user.publicKey,
+          vault: userVaultPda,
+          yieldAccount: userYieldPda,
+          treasury: treasuryPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      const treasuryBefore = await program.account.treasury.fetch(treasuryPda);
+
+      await program.methods
+        .fundRewards(new anchor.BN(REWARD_AMOUNT))
+        .accounts({
+          admin: provider.wallet.publicKey,
+          config: configPda,
+          treasury: treasuryPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+      // The treasury is shared, so the user earns their pro-rata share of the funding.
+      const treasuryAfter = await program.account.treasury.fetch(treasuryPda);
+      const expectedReward = new anchor.BN(STAKE_AMOUNT)
+        .mul(treasuryAfter.rewardPerShare.sub(treasuryBefore.rewardPerShare))
+        .div(new anchor.BN("1000000000000"));
+      expect(expectedReward.toNumber()).to.be.greaterThan(0);
+
+      const userBalanceBefore = await connection.getBalance(user.publicKey);
+
+      const signature = await program.methods
+        .claimRewards()
+        .accounts({
+          authority: user.publicKey,
+          treasury: treasuryPda,
+          yieldAccount: userYieldPda,
+        })
+        .signers([user])
+        .rpc({ commitment: "confirmed" });
+
+      const userBalanceAfter = await connection.getBalance(user.publicKey);
+      expect(userBalanceAfter - userBalanceBefore).to.equal(expectedReward.toNumber());
+      const [claimed] = await emittedEvents(signature);
+      expect(claimed.name).to.equal("rewardsClaimed");
+      expect(claimed.data.amount.toNumber()).to.equal(expectedReward.toNumber());
+
+      const yieldAccount = await program.account.yieldAccount.fetch(userYieldPda);
+      expect(yieldAccount.pendingRewards.toNumber()).to.equal(0);
+      expect(yieldAccount.lastIndex.toString()).to.equal(treasuryAfter.rewardPerShare.toString());
+    });
+
+    it("Should reject reward funding from a non-admin", async () => {
+      try {
+        await program.methods
+          .fundRewards(new anchor.BN(LAMPORTS_PER_SOL))
+          .accounts({
+            admin: user.publicKey,
+            config: configPda,
+            treasury: treasuryPda,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user])
+          .rpc();
+
+        expect.fail("Should have thrown unauthorized error");
+      } catch (error) {
+        expect(error.toString()).to.include("Unauthorized");
+      }
+    });
+  });
+
+  describe("Token Vault Tests", () => {
+    const TOKEN_DECIMALS = 6;
+    const TOKEN_DEPOSIT = 1_000 * 10 ** TOKEN_DECIMALS;
+
+    beforeEach(async () => {
+      await program.methods
+        .initialize()
+        .accounts({
+          user: user.publicKey,
+          vault: userVaultPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+    });
+
+    // Creates a Token-2022 mint that withholds `feeBps` of every transfer.
+    const createTransferFeeMint = async (feeBps: number) => {
+      const mintKeypair = Keypair.generate();
+      const mintLen = getMintLen([ExtensionType.TransferFeeConfig]);
+      const lamports = await connection.getMinimumBalanceForRentExemption(mintLen);
+
+      const tx = new Transaction().add(
+        SystemProgram.createAccount({
+          fromPubkey: user.publicKey,
+          newAccountPubkey: mintKeypair.publicKey,
+          space: mintLen,
+          lamports,
+          programId: TOKEN_2022_PROGRAM_ID,
+        }),
+        createInitializeTransferFeeConfigInstruction(
+          mintKeypair.publicKey,
+          user.publicKey,
+          user.publicKey,
+          feeBps,
+          BigInt(TOKEN_DEPOSIT),
+          TOKEN_2022_PROGRAM_ID
+        ),
+        createInitializeMintInstruction(
+          mintKeypair.publicKey,
+          TOKEN_DECIMALS,
+          user.publicKey,
+          null,
+          TOKEN_2022_PROGRAM_ID
+        )
+      );
+      await provider.sendAndConfirm(tx, [user, mintKeypair]);
+
+      return mintKeypair.publicKey;
+    };
+
+    // Creates a mint under `tokenProgram` unless one is given, funds a user token
+    // account and opens the user's token vault for it.
+    const setupTokenVault = async (tokenProgram: PublicKey, existingMint?: PublicKey) => {
+      const mint = existingMint ?? await createMint(
+        connection,
+        user,
+        user.publicKey,
+        null,
+        TOKEN_DECIMALS,
+        undefined,
+        undefined,
+        tokenProgram
+      );
+      const userTokenAccount = await createAccount(
+        connection,
+        user,
+        mint,
+        user.publicKey,
+        undefined,
+        undefined,
+        tokenProgram
+      );
+      await mintTo(connection, user, mint, userTokenAccount, user, TOKEN_DEPOSIT, [], undefined, tokenProgram);
+      await addCollateralAsset(mint);
+
+      const [tokenVaultPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("token_vault"), user.publicKey.toBuffer(), mint.toBuffer()],
+        program.programId
+      );
+      const vaultTokenAccount = getAssociatedTokenAddressSync(mint, userVaultPda, true, tokenProgram);
+
+      await program.methods
+        .initializeTokenVault()
+        .accounts({
+          authority: user.publicKey,
+          vault: userVaultPda,
+          mint,
+          collateralAsset: collateralAssetPda(mint),
+          tokenVault: tokenVaultPda,
+          vaultTokenAccount,
+          tokenProgram,
+        })
+        .signers([user])
+        .rpc();
+
+      await initializeSpendingAccount();
+
+      return { mint, userTokenAccount, tokenVaultPda, vaultTokenAccount };
+    };
+
+    for (const [label, tokenProgram] of [
+      ["SPL Token", TOKEN_PROGRAM_ID],
+      ["Token-2022", TOKEN_2022_PROGRAM_ID],
+    ] as [string, PublicKey][]) {
+      it(`Should deposit and withdraw ${label} tokens`, async () => {
+        const { mint, userTokenAccount, tokenVaultPda, vaultTokenAccount } =
+          await setupTokenVault(tokenProgram);
+
+        const tokenVaultInit = await program.account.tokenVault.fetch(tokenVaultPda);
+        expect(tokenVaultInit.authority.toString()).to.equal(user.publicKey.toString());
+        expect(tokenVaultInit.mint.toString()).to.equal(mint.toString());
+        expect(tokenVaultInit.balance.toNumber()).to.equal(0);
+
+        const depositSignature = await program.methods
+          .depositToken(new anchor.BN(TOKEN_DEPOSIT))
+          .accounts({
+            authority: user.publicKey,
+            vault: userVaultPda,
+            mint,
+            tokenVault: tokenVaultPda,
+            vaultTokenAccount,
+            userTokenAccount,
+            tokenProgram,
+          })
+          .signers([user])
+          .rpc({ commitment: "confirmed" });
+
+        let tokenVault = await program.account.tokenVault.fetch(tokenVaultPda);
+        expect(tokenVault.balance.toNumber()).to.equal(TOKEN_DEPOSIT);
+        const [deposited] = await emittedEvents(depositSignature);
+        expect(deposited.name).to.equal("tokenDeposited");
+        expect(deposited.data.mint.toString()).to.equal(mint.toString());
+        expect(deposited.data.amount.toNumber()).to.equal(TOKEN_DEPOSIT);
+        expect(deposited.data.tokenVaultBalance.toNumber()).to.equal(TOKEN_DEPOSIT);
+        let vaultTokens = await getAccount(connection, vaultTokenAccount, undefined, tokenProgram);
+        expect(Number(vaultTokens.amount)).to.equal(TOKEN_DEPOSIT);
+
+        const withdrawAmount = TOKEN_DEPOSIT / 4;
+        const withdrawSignature = await program.methods
+          .withdrawToken(new anchor.BN(withdrawAmount))
+          .accounts({
+            authority: user.publicKey,
+            config: configPda,
+            vault: userVaultPda,
+            mint,
+            tokenVault: tokenVaultPda,
+            vaultTokenAccount,
+            userTokenAccount,
+            yieldAccount: null,
+            spendingAccount: userSpendingPda,
+            treasury: treasuryPda,
+            priceUpdate: SOL_USD_PRICE_UPDATE,
+            tokenProgram,
+          })
+          .signers([user])
+          .rpc({ commitment: "confirmed" });
+
+        tokenVault = await program.account.tokenVault.fetch(tokenVaultPda);
+        expect(tokenVault.balance.toNumber()).to.equal(TOKEN_DEPOSIT - withdrawAmount);
+        const [withdrawn] = await emittedEvents(withdrawSignature);
+        expect(withdrawn.name).to.equal("tokenWithdrawn");
+        expect(withdrawn.data.amount.toNumber()).to.equal(withdrawAmount);
+        expect(withdrawn.data.tokenVaultBalance.toNumber()).to.equal(TOKEN_DEPOSIT - withdrawAmount);
+        vaultTokens = await getAccount(connection, vaultTokenAccount, undefined, tokenProgram);
+        expect(Number(vaultTokens.amount)).to.equal(TOKEN_DEPOSIT - withdrawAmount);
+        const userTokens = await getAccount(connection, userTokenAccount, undefined, tokenProgram);
+        expect(Number(userTokens.amount)).to.equal(withdrawAmount);
+      });
+    }
+
+    it("Should close an empty token vault before the vault", async () => {
+      const { mint, userTokenAccount, tokenVaultPda, vaultTokenAccount } =
+        await setupTokenVault(TOKEN_PROGRAM_ID);
+      // Tokens sent straight to the vault's token account are never credited.
+      const stray = 1_000;
+      await mintTo(connection, user, mint, vaultTokenAccount, user, stray);
+
+      const closeVault = () =>
+        program.methods
+          .closeVault()
+          .accounts({
+            authority: user.publicKey,
+            vault: userVaultPda,
+          })
+          .signers([user])
+          .rpc();
+
+      try {
+        await closeVault();
+        expect.fail("Should have thrown account not empty error");
+      } catch (error) {
+        expect(error.toString()).to.include("AccountNotEmpty");
+      }
+
+      await program.methods
+        .closeTokenVault()
+        .accounts({
+          authority: user.publicKey,
+          vault: userVaultPda,
+          mint,
+          tokenVault: tokenVaultPda,
+          vaultTokenAccount,
+          userTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user])
+        .rpc();
+
+      expect(await connection.getAccountInfo(tokenVaultPda)).to.be.null;
+      expect(await connection.getAccountInfo(vaultTokenAccount)).to.be.null;
+      const userTokens = await getAccount(connection, userTokenAccount);
+      expect(Number(userTokens.amount)).to.equal(TOKEN_DEPOSIT + stray);
+      const vault = await program.account.vault.fetch(userVaultPda);
+      expect(vault.tokenVaultCount).to.equal(0);
+
+      await closeVault();
+      expect(await connection.getAccountInfo(userVaultPda)).to.be.null;
+    });
+
+    it("Should fail token withdrawal beyond the vault balance", async () => {
+      const { mint, userTokenAccount, tokenVaultPda, vaultTokenAccount } =
+        await setupTokenVault(TOKEN_PROGRAM_ID);
+
+      try {
+        await program.methods
+          .withdrawToken(new anchor.BN(1))
+          .accounts({
+            authority: user.publicKey,
+            config: configPda,
+            vault: userVaultPda,
+            mint,
+            tokenVault: tokenVaultPda,
+            vaultTokenAccount,
+            userTokenAccount,
+            yieldAccount: null,
+            spendingAccount: userSpendingPda,
+            treasury: treasuryPda,
+            priceUpdate: SOL_USD_PRICE_UPDATE,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([user])
+          .rpc();
+
+        expect.fail("Should have thrown insufficient funds error");
+      } catch (error) {
+        expect(error.toString()).to.include("InsufficientFunds");
+      }
+    });
+
+    it("Should credit only the tokens received when the mint charges a transfer fee", async () => {
+      const feeMint = await createTransferFeeMint(100);
+      const { mint, userTokenAccount, tokenVaultPda, vaultTokenAccount } =
+        await setupTokenVault(TOKEN_2022_PROGRAM_ID, feeMint);
+
+      await program.methods
+        .depositToken(new anchor.BN(TOKEN_DEPOSIT))
+        .accounts({
+          authority: user.publicKey,
+          vault: userVaultPda,
+          mint,
+          tokenVault: tokenVaultPda,
+          vaultTokenAccount,
+          userTokenAccount,
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+        })
+        .signers([user])
+        .rpc();
+
+      // 1% is withheld in the vault's token account and cannot be withdrawn.
+      const received = TOKEN_DEPOSIT - TOKEN_DEPOSIT / 100;
+      const tokenVault = await program.account.tokenVault.fetch(tokenVaultPda);
+      expect(tokenVault.balance.toNumber()).to.equal(received);
+      const vaultTokens = await getAccount(connection, vaultTokenAccount, undefined, TOKEN_2022_PROGRAM_ID);
+      expect(Number(vaultTokens.amount)).to.equal(received);
+    });
+  });
+
+  describe("Collateral Asset Tests", () => {
+    // The test token borrows the SOL/USD feed and SOL's decimals, so one token is
+    // valued exactly like one SOL.
+    const TOKEN_DECIMALS = 9;
+    const TOKEN_DEPOSIT = 1 * LAMPORTS_PER_SOL;
+
+    const createTestMint = () =>
+      createMint(connection, user, user.publicKey, null, TOKEN_DECIMALS);
+
+    it("Should let the admin register a collateral asset", async () => {
+      const mint = await createTestMint();
+      await addCollateralAsset(mint);
+
+      const asset = await program.account.collateralAsset.fetch(collateralAssetPda(mint));
+      expect(asset.mint.toString()).to.equal(mint.toString());
+      expect(asset.priceFeedId).to.deep.equal(SOL_USD_FEED_ID);
+      expect(asset.decimals).to.equal(TOKEN_DECIMALS);
+      expect(asset.collateralFactorBps.toNumber()).to.equal(7_000);
+      expect(asset.liquidationThresholdBps.toNumber()).to.equal(8_000);
+
+      await program.methods
+        .updateCollateralAsset({ ...collateralParams(), collateralFactorBps: new anchor.BN(6_000) })
+        .accounts({
+          admin: provider.wallet.publicKey,
+          config: configPda,
+          collateralAsset: collateralAssetPda(mint),
+        })
+        .rpc();
+
+      const updated = await program.account.collateralAsset.fetch(collateralAssetPda(mint));
+      expect(updated.collateralFactorBps.toNumber()).to.equal(6_000);
+    });
+
+    it("Should reject collateral assets registered by a non-admin", async () => {
+      const mint = await createTestMint();
+
+      try {
+        await program.methods
+          .addCollateralAsset(collateralParams())
+          .accounts({
+            admin: user.publicKey,
+            config: configPda,
+            mint,
+            collateralAsset: collateralAssetPda(mint),
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user])
+          .rpc();
+
+        expect.fail("Should have thrown unauthorized error");
+      } catch (error) {
+        expect(error.toString()).to.include("Unauthorized");
+      }
+    });
+
+    it("Should reject a collateral factor at or above the liquidation threshold", async () => {
+      const mint = await createTestMint();
+
+      try {
+        await program.methods
+          .addCollateralAsset({ ...collateralParams(), collateralFactorBps: new anchor.BN(8_000) })
+          .accounts({
+            admin: provider.wallet.publicKey,
+            config: configPda,
+            mint,
+            collateralAsset: collateralAssetPda(mint),
+            systemProgram: SystemProgram.programId,
+          })
+          .rpc();
+
+        expect.fail("Should have thrown invalid config error");
+      } catch (error) {
+        expect(error.toString()).to.include("InvalidConfig");
+      }
+    });
+
+    it("Should count token collateral towards the spending limit", async () => {
+      await program.methods
+        .initialize()
+        .accounts({
+          user: user.publicKey,
+          vault: userVaultPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      await program.methods
+        .deposit(new anchor.BN(DEPOSIT_AMOUNT))
+        .accounts({
+          authority: user.publicKey,
+          vault: userVaultPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      await program.methods
+        .initializeSpendingAccount()
+        .accounts({
+          authority: user.publicKey,
+          spendingAccount: userSpendingPda,
+          vault: userVaultPda,
+          treasury: treasuryPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      const mint = await createTestMint();
+      await addCollateralAsset(mint);
+
+      const userTokenAccount = await createAccount(connection, user, mint, user.publicKey);
+      await mintTo(connection, user, mint, userTokenAccount, user, TOKEN_DEPOSIT);
+
+      const [tokenVaultPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("token_vault"), user.publicKey.toBuffer(), mint.toBuffer()],
+        program.programId
+      );
+      const vaultTokenAccount = getAssociatedTokenAddressSync(mint, userVaultPda, true);
+
+      await program.methods
+        .initializeTokenVault()
+        .accounts({
+          authority: user.publicKey,
+          vault: userVaultPda,
+          mint,
+          collateralAsset: collateralAssetPda(mint),
+          tokenVault: tokenVaultPda,
+          vaultTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user])
+        .rpc();
+
+      await program.methods
+        .depositToken(new anchor.BN(TOKEN_DEPOSIT))
+        .accounts({
+          authority: user.publicKey,
+          vault: userVaultPda,
+          mint,
+          tokenVault: tokenVaultPda,
+          vaultTokenAccount,
+          userTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user])
+        .rpc();
+
+      const updateSpendingLimit = (remainingAccounts: anchor.web3.AccountMeta[]) =>
+        program.methods
+          .updateSpendingLimit()
+          .accounts({
+            authority: user.publicKey,
+            config: configPda,
+            vault: userVaultPda,
+            yieldAccount: null,
+            priceUpdate: SOL_USD_PRICE_UPDATE,
+            spendingAccount: userSpendingPda,
+          })
+          .remainingAccounts(remainingAccounts)
+          .signers([user])
+          .rpc();
+
+      // Every token vault the user opened has to be passed, so leaving one out to
+      // undervalue the position fails.
+      try {
+        await updateSpendingLimit([]);
+        expect.fail("Should have thrown invalid collateral accounts error");
+      } catch (error) {
+        expect(error.toString()).to.include("InvalidCollateralAccounts");
+      }
+
+      await updateSpendingLimit(collateralAccounts(mint, tokenVaultPda));
+
+      const tokenCapacity = (await collateralValueUsd(TOKEN_DEPOSIT))
+        .mul(collateralParams().collateralFactorBps)
+        .div(new anchor.BN(10_000))
+        .toNumber();
+
+      const spendingAccount = await program.account.spendingAccount.fetch(userSpendingPda);
+      expect(spendingAccount.spendingLimit.toNumber()).to.equal(
+        (await expectedSpendingLimit(DEPOSIT_AMOUNT)) + tokenCapacity
+      );
+    });
+  });
+
+  describe("Merchant and Payment Tests", () => {
+    beforeEach(async () => {
+      // Setup vault and spending account
+      await program.methods
+        .initialize()
+        .accounts({
+          user: user.publicKey,
+          vault: userVaultPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      await program.methods
+        .deposit(new anchor.BN(DEPOSIT_AMOUNT))
+        .accounts({
+          authority: user.publicKey,
+          vault: userVaultPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      await program.methods
+        .initializeSpendingAccount()
+        .accounts({
+          authority: user.publicKey,
+          spendingAccount: userSpendingPda,
+          vault: userVaultPda,
+          treasury: treasuryPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      await program.methods
+        .updateSpendingLimit()
+        .accounts({
+          authority: user.publicKey,
+          config: configPda,
+          vault: userVaultPda,
+          yieldAccount: null,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
+          spendingAccount: userSpendingPda,
+        })
+        .signers([user])
+        .rpc();
+    });
+
+    it("Should initialize merchant account", async () => {
+      const merchantName = "Test Merchant";
+
+      await program.methods
+        .initializeMerchantAccount(merchantName, null)
+        .accounts({
+          merchantAccount: merchantPda,
+          authority: merchant.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchant])
+        .rpc();
+
+      const merchantAccount = await program.account.merchantAccount.fetch(merchantPda);
+      expect(merchantAccount.authority.toString()).to.equal(merchant.publicKey.toString());
+      expect(merchantAccount.name).to.equal(merchantName);
+      expect(merchantAccount.payoutWallet.toString()).to.equal(merchant.publicKey.toString());
+      expect(merchantAccount.verified).to.be.false;
+      expect(merchantAccount.suspended).to.be.false;
+    });
+
+    it("Should let a merchant update its profile and close its account", async () => {
+      await program.methods
+        .initializeMerchantAccount("Test Merchant", null)
+        .accounts({
+          merchantAccount: merchantPda,
+          authority: merchant.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchant])
+        .rpc();
+
+      await program.methods
+        .updateMerchant({ name: "Renamed Merchant", payoutWallet: otherUser.publicKey, category: 5812 })
+        .accounts({
+          authority: merchant.publicKey,
+          merchantAccount: merchantPda,
+        })
+        .signers([merchant])
+        .rpc();
+
+      const updated = await program.account.merchantAccount.fetch(merchantPda);
+      expect(updated.name).to.equal("Renamed Merchant");
+      expect(updated.payoutWallet.toString()).to.equal(otherUser.publicKey.toString());
+      expect(updated.category).to.equal(5812);
+
+      // Unset fields are left alone.
+      await program.methods
+        .updateMerchant({ name: null, payoutWallet: null, category: 5814 })
+        .accounts({
+          authority: merchant.publicKey,
+          merchantAccount: merchantPda,
+        })
+        .signers([merchant])
+        .rpc();
+
+      const recategorised = await program.account.merchantAccount.fetch(merchantPda);
+      expect(recategorised.name).to.equal("Renamed Merchant");
+      expect(recategorised.category).to.equal(5814);
+
+      const rent = await connection.getBalance(merchantPda);
+      const merchantBalanceBefore = await connection.getBalance(merchant.publicKey);
+
+      await program.methods
+        .closeMerchant()
+        .accounts({
+          authority: merchant.publicKey,
+          merchantAccount: merchantPda,
+        })
+        .signers([merchant])
+        .rpc();
+
+      expect(await connection.getAccountInfo(merchantPda)).to.be.null;
+      expect(await connection.getBalance(merchant.publicKey)).to.equal(merchantBalanceBefore + rent);
+    });
+
+    it("Should only take payments for verified, unsuspended merchants", async () => {
+      await program.methods
+        .initializeMerchantAccount("Test Merchant", null)
+        .accounts({
+          merchantAccount: merchantPda,
+          authority: merchant.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchant])
+        .rpc();
+
+      const { receipt, nonce } = nextReceipt(merchantPda);
+      const pay = async () =>
+        program.methods
+          .processPayment(new anchor.BN(PAYMENT_AMOUNT), { wallet: {} }, null, nonce, null)
+          .accounts({
+            authority: user.publicKey,
+            config: configPda,
+            priceUpdate: SOL_USD_PRICE_UPDATE,
+            vault: userVaultPda,
+            yieldAccount: null,
+            spendingAccount: userSpendingPda,
+            merchantAccount: merchantPda,
+            payoutWallet: merchant.publicKey,
+            treasury: treasuryPda,
+            receipt,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user])
+          .rpc();
+
+      try {
+        await pay();
+        expect.fail("Should have thrown merchant not verified error");
+      } catch (error) {
+        expect(error.toString()).to.include("MerchantNotVerified");
+      }
+
+      // Only the admin controls the status flags.
+      try {
+        await program.methods
+          .setMerchantStatus(true, false)
+          .accounts({
+            admin: merchant.publicKey,
+            config: configPda,
+            merchantAccount: merchantPda,
+          })
+          .signers([merchant])
+          .rpc();
+        expect.fail("Should have thrown unauthorized error");
+      } catch (error) {
+        expect(error.toString()).to.include("Unauthorized");
+      }
+
+      await program.methods
+        .setMerchantStatus(true, true)
+        .accounts({
+          admin: provider.wallet.publicKey,
+          config: configPda,
+          merchantAccount: merchantPda,
+        })
+        .rpc();
+
+      try {
+        await pay();
+        expect.fail("Should have thrown merchant suspended error");
+      } catch (error) {
+        expect(error.toString()).to.include("MerchantSuspended");
+      }
+
+      await verifyMerchant(merchantPda);
+      await pay();
+
+      const receipt = await program.account.paymentReceipt.fetch(receiptPda(merchantPda, new anchor.BN(0)));
+      expect(receipt.amount.toNumber()).to.equal(PAYMENT_AMOUNT);
+    });
+
+    it("Should process payment successfully", async () => {
+      // Initialize merchant
+      await program.methods
+        .initializeMerchantAccount("Test Merchant", null)
+        .accounts({
+          merchantAccount: merchantPda,
+          authority: merchant.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchant])
+        .rpc();
+      await verifyMerchant(merchantPda);
+
+      // Get balances before payment
+      const userBalanceBefore = await connection.getBalance(user.publicKey);
+      const merchantBalanceBefore = await connection.getBalance(merchant.publicKey);
+
+      // Process payment
+      const { receipt, nonce } = nextReceipt(merchantPda);
+      const signature = await program.methods
+        .processPayment(new anchor.BN(PAYMENT_AMOUNT), { wallet: {} }, null, nonce, null)
+        .accounts({
+          authority: user.publicKey,
+          config: configPda,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
+          vault: userVaultPda,
+          yieldAccount: null,
+          spendingAccount: userSpendingPda,
+          merchantAccount: merchantPda,
+          payoutWallet: merchant.publicKey,
+          treasury: treasuryPda,
+          receipt,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc({ commitment: "confirmed" });
+
+      // Verify payment
+      const spendingAccount = await program.account.spendingAccount.fetch(userSpendingPda);
+      // Lamports paid are recorded as debt at the upper price bound.
+      expect(spendingAccount.amountSpent.toNumber()).to.equal(
+        (await debtValueUsd(PAYMENT_AMOUNT)).toNumber()
+      );
+
+      const userBalanceAfter = await connection.getBalance(user.publicKey);
+      const merchantBalanceAfter = await connection.getBalance(merchant.publicKey);
+
+      expect(userBalanceAfter).to.be.lessThan(userBalanceBefore);
+      expect(merchantBalanceAfter).to.equal(merchantBalanceBefore + PAYMENT_AMOUNT);
+
+      const [payment] = await emittedEvents(signature);
+      expect(payment.name).to.equal("paymentProcessed");
+      expect(payment.data.merchantAccount.toString()).to.equal(merchantPda.toString());
+      expect(payment.data.mint).to.be.null;
+      expect(payment.data.amount.toNumber()).to.equal(PAYMENT_AMOUNT);
+      expect(payment.data.amountUsd.toNumber()).to.equal(spendingAccount.amountSpent.toNumber());
+    });
+
+    it("Should split the merchant fee into the treasury for the admin to withdraw", async () => {
+      const FEE_BPS = 250;
+
+      await program.methods
+        .initializeMerchantAccount("Test Merchant", null)
+        .accounts({
+          merchantAccount: merchantPda,
+          authority: merchant.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchant])
+        .rpc();
+      await verifyMerchant(merchantPda);
+
+      await program.methods
+        .setMerchantFee(new anchor.BN(FEE_BPS))
+        .accounts({
+          admin: provider.wallet.publicKey,
+          config: configPda,
+          merchantAccount: merchantPda,
+        })
+        .rpc();
+
+      const treasuryBefore = await program.account.treasury.fetch(treasuryPda);
+      const merchantBalanceBefore = await connection.getBalance(merchant.publicKey);
+      const { receipt, nonce } = nextReceipt(merchantPda);
+
+      await program.methods
+        .processPayment(new anchor.BN(PAYMENT_AMOUNT), { wallet: {} }, null, nonce, null)
+        .accounts({
+          authority: user.publicKey,
+          config: configPda,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
+          vault: userVaultPda,
+          yieldAccount: null,
+          spendingAccount: userSpendingPda,
+          merchantAccount: merchantPda,
+          payoutWallet: merchant.publicKey,
+          treasury: treasuryPda,
+          receipt,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      const fee = (PAYMENT_AMOUNT * FEE_BPS) / 10_000;
+      expect(await connection.getBalance(merchant.publicKey)).to.equal(
+        merchantBalanceBefore + PAYMENT_AMOUNT - fee
+      );
+      expect((await program.account.paymentReceipt.fetch(receipt)).fee.toNumber()).to.equal(fee);
+
+      const treasuryAfter = await program.account.treasury.fetch(treasuryPda);
+      expect(treasuryAfter.accruedFees.toNumber()).to.equal(treasuryBefore.accruedFees.toNumber() + fee);
+      expect(treasuryAfter.totalFees.toNumber()).to.equal(treasuryBefore.totalFees.toNumber() + fee);
+
+      // The merchant can only refund what it received; the fee stays with the protocol.
+      const refund = (amount: number) =>
+        program.methods
+          .refund(new anchor.BN(amount))
+          .accounts({
+            merchant: merchant.publicKey,
+            config: configPda,
+            merchantAccount: merchantPda,
+            receipt,
+            payer: user.publicKey,
+            spendingAccount: userSpendingPda,
+            treasury: treasuryPda,
+            vault: null,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([merchant])
+          .rpc();
+
+      try {
+        await refund(PAYMENT_AMOUNT);
+        expect.fail("Should have thrown refund exceeds payment error");
+      } catch (error) {
+        expect(error.toString()).to.include("RefundExceedsPayment");
+      }
+
+      // Refunding everything the merchant kept releases the whole debt.
+      await refund(PAYMENT_AMOUNT - fee);
+      const spendingAccount = await program.account.spendingAccount.fetch(userSpendingPda);
+      expect(spendingAccount.amountSpent.toNumber()).to.equal(0);
+
+      // Only the admin can take the fees out.
+      try {
+        await program.methods
+          .withdrawFees(new anchor.BN(fee))
+          .accounts({
+            admin: user.publicKey,
+            config: configPda,
+            treasury: treasuryPda,
+          })
+          .signers([user])
+          .rpc();
+        expect.fail("Should have thrown unauthorized error");
+      } catch (error) {
+        expect(error.toString()).to.include("Unauthorized");
+      }
+
+      await program.methods
+        .withdrawFees(new anchor.BN(fee))
+        .accounts({
+          admin: provider.wallet.publicKey,
+          config: configPda,
+          treasury: treasuryPda,
+        })
+        .rpc();
+
+      const treasuryWithdrawn = await program.account.treasury.fetch(treasuryPda);
+      expect(treasuryWithdrawn.accruedFees.toNumber()).to.equal(treasuryBefore.accruedFees.toNumber());
+      expect(treasuryWithdrawn.totalFees.toNumber()).to.equal(treasuryAfter.totalFees.toNumber());
+
+      // Nothing beyond the collected fees can leave the treasury.
+      try {
+        await program.methods
+          .withdrawFees(treasuryWithdrawn.accruedFees.addn(1))
+          .accounts({
+            admin: provider.wallet.publicKey,
+            config: configPda,
+            treasury: treasuryPda,
+          })
+          .rpc();
+        expect.fail("Should have thrown insufficient funds error");
+      } catch (error) {
+        expect(error.toString()).to.include("InsufficientFunds");
+      }
+    });
+
+    it("Should pay a merchant from the vault", async () => {
+      await program.methods
+        .initializeMerchantAccount("Test Merchant", null)
+        .accounts({
+          merchantAccount: merchantPda,
+          authority: merchant.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchant])
+        .rpc();
+      await verifyMerchant(merchantPda);
+
+      const userBalanceBefore = await connection.getBalance(user.publicKey);
+      const merchantBalanceBefore = await connection.getBalance(merchant.publicKey);
+      const vaultLamportsBefore = await connection.getBalance(userVaultPda);
+
+      const { receipt, nonce } = nextReceipt(merchantPda);
+      await program.methods
+        .processPayment(new anchor.BN(PAYMENT_AMOUNT), { vault: {} }, null, nonce, null)
+        .accounts({
+          authority: user.publicKey,
+          config: configPda,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
+          vault: userVaultPda,
+          yieldAccount: null,
+          spendingAccount: userSpendingPda,
+          merchantAccount: merchantPda,
+          payoutWallet: merchant.publicKey,
+          treasury: treasuryPda,
+          receipt,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      // The spend is checked and recorded against the limit as for wallet payments...
+      const spendingAccount = await program.account.spendingAccount.fetch(userSpendingPda);
+      expect(spendingAccount.amountSpent.toNumber()).to.equal(
+        (await debtValueUsd(PAYMENT_AMOUNT)).toNumber()
+      );
+
+      // ...but the lamports come out of the vault, not the wallet.
+      const vault = await program.account.vault.fetch(userVaultPda);
+      expect(vault.balance.toNumber()).to.equal(DEPOSIT_AMOUNT - PAYMENT_AMOUNT);
+      await expectVaultInSync(userVaultPda);
+      expect(await connection.getBalance(userVaultPda)).to.equal(vaultLamportsBefore - PAYMENT_AMOUNT);
+      expect(await connection.getBalance(merchant.publicKey)).to.equal(merchantBalanceBefore + PAYMENT_AMOUNT);
+      expect(await connection.getBalance(user.publicKey)).to.equal(userBalanceBefore);
+    });
+
+    it("Should refuse a vault payment that leaves the vault below the initial margin", async () => {
+      await program.methods
+        .initializeMerchantAccount("Test Merchant", null)
+        .accounts({
+          merchantAccount: merchantPda,
+          authority: merchant.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchant])
+        .rpc();
+      await verifyMerchant(merchantPda);
+
+      // 0.8 SOL fits the limit set against the full 2 SOL, but once paid out of the
+      // vault the 1.2 SOL left only backs 0.6 SOL of debt.
+      const { receipt, nonce } = nextReceipt(merchantPda);
+      try {
+        await program.methods
+          .processPayment(new anchor.BN(0.8 * LAMPORTS_PER_SOL), { vault: {} }, null, nonce, null)
+          .accounts({
+            authority: user.publicKey,
+            config: configPda,
+            priceUpdate: SOL_USD_PRICE_UPDATE,
+            vault: userVaultPda,
+            yieldAccount: null,
+            spendingAccount: userSpendingPda,
+            merchantAccount: merchantPda,
+            payoutWallet: merchant.publicKey,
+            treasury: treasuryPda,
+            receipt,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user])
+          .rpc();
+
+        expect.fail("Should have thrown below initial margin error");
+      } catch (error) {
+        expect(error.toString()).to.include("BelowInitialMargin");
+      }
+
+      const vault = await program.account.vault.fetch(userVaultPda);
+      expect(vault.balance.toNumber()).to.equal(DEPOSIT_AMOUNT);
+    });
+
+    describe("Refunds", () => {
+      beforeEach(async () => {
+        await program.methods
+          .initializeMerchantAccount("Test Merchant", null)
+          .accounts({
+            merchantAccount: merchantPda,
+            authority: merchant.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([merchant])
+          .rpc();
+        await verifyMerchant(merchantPda);
+      });
+
+      const pay = async (source: object) => {
+        const { receipt, nonce } = nextReceipt(merchantPda);
+        await program.methods
+          .processPayment(new anchor.BN(PAYMENT_AMOUNT), source as any, null, nonce, null)
+          .accounts({
+            authority: user.publicKey,
+            config: configPda,
+            priceUpdate: SOL_USD_PRICE_UPDATE,
+            vault: userVaultPda,
+            yieldAccount: null,
+            spendingAccount: userSpendingPda,
+            merchantAccount: merchantPda,
+            payoutWallet: merchant.publicKey,
+            treasury: treasuryPda,
+            receipt,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user])
+          .rpc();
+        return receipt;
+      };
+
+      const refund = (receipt: PublicKey, amount: number, vault: PublicKey | null = userVaultPda) =>
+        program.methods
+          .refund(new anchor.BN(amount))
+          .accounts({
+            merchant: merchant.publicKey,
+            config: configPda,
+            merchantAccount: merchantPda,
+            receipt,
+            payer: user.publicKey,
+            spendingAccount: userSpendingPda,
+            treasury: treasuryPda,
+            vault,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([merchant])
+          .rpc();
+
+      it("Should issue a receipt and refund it in parts", async () => {
+        const receipt = await pay({ wallet: {} });
+
+        const issued = await program.account.paymentReceipt.fetch(receipt);
+        expect(issued.payer.toString()).to.equal(user.publicKey.toString());
+        expect(issued.merchantAccount.toString()).to.equal(merchantPda.toString());
+        expect(issued.amount.toNumber()).to.equal(PAYMENT_AMOUNT);
+        const amountUsd = issued.amountUsd.toNumber();
+        expect(amountUsd).to.equal((await debtValueUsd(PAYMENT_AMOUNT)).toNumber());
+
+        const userBalanceBefore = await connection.getBalance(user.publicKey);
+        await refund(receipt, PAYMENT_AMOUNT / 2);
+
+        let spendingAccount = await program.account.spendingAccount.fetch(userSpendingPda);
+        expect(spendingAccount.amountSpent.toNumber()).to.equal(amountUsd - Math.floor(amountUsd / 2));
+        expect(spendingAccount.windowSpent.toNumber()).to.equal(amountUsd - Math.floor(amountUsd / 2));
+        expect(await connection.getBalance(user.publicKey)).to.equal(userBalanceBefore + PAYMENT_AMOUNT / 2);
+
+        await refund(receipt, PAYMENT_AMOUNT / 2);
+
+        spendingAccount = await program.account.spendingAccount.fetch(userSpendingPda);
+        expect(spendingAccount.amountSpent.toNumber()).to.equal(0);
+        expect(spendingAccount.windowSpent.toNumber()).to.equal(0);
+        const refunded = await program.account.paymentReceipt.fetch(receipt);
+        expect(refunded.refunded.toNumber()).to.equal(PAYMENT_AMOUNT);
+        expect(refunded.refundedUsd.toNumber()).to.equal(amountUsd);
+
+        try {
+          await refund(receipt, 1);
+          expect.fail("Should have thrown refund exceeds payment error");
+        } catch (error) {
+          expect(error.toString()).to.include("RefundExceedsPayment");
+        }
+      });
+
+      it("Should record the order ID and memo and reject paying an order twice", async () => {
+        const orderId = "order-1001";
+        const receipt = orderReceiptPda(merchantPda, orderId);
+        const payOrder = (amount: number) =>
+          program.methods
+            .processPayment(new anchor.BN(amount), { wallet: {} }, orderId, new anchor.BN(0), "Two coffees")
+            .accounts({
+              authority: user.publicKey,
+              config: configPda,
+              priceUpdate: SOL_USD_PRICE_UPDATE,
+              vault: userVaultPda,
+              yieldAccount: null,
+              spendingAccount: userSpendingPda,
+              merchantAccount: merchantPda,
+              payoutWallet: merchant.publicKey,
+              treasury: treasuryPda,
+              receipt,
+              systemProgram: SystemProgram.programId,
+            })
+            .signers([user])
+            .rpc();
+
+        await payOrder(PAYMENT_AMOUNT);
+
+        const paid = await program.account.paymentReceipt.fetch(receipt);
+        expect(paid.orderId).to.equal(orderId);
+        expect(paid.memo).to.equal("Two coffees");
+        expect(paid.amount.toNumber()).to.equal(PAYMENT_AMOUNT);
+
+        try {
+          // A different amount so the retry is not deduplicated as the same transaction.
+          await payOrder(PAYMENT_AMOUNT / 2);
+          expect.fail("Should have rejected a second payment for the same order");
+        } catch (error) {
+          expect(error.toString()).to.include("already in use");
+        }
+
+        const spendingAccount = await program.account.spendingAccount.fetch(userSpendingPda);
+        expect(spendingAccount.amountSpent.toNumber()).to.equal(paid.amountUsd.toNumber());
+      });
+
+      it("Should refund vault payments back into the vault", async () => {
+        const receipt = await pay({ vault: {} });
+        await refund(receipt, PAYMENT_AMOUNT);
+
+        const vault = await program.account.vault.fetch(userVaultPda);
+        expect(vault.balance.toNumber()).to.equal(DEPOSIT_AMOUNT);
+        await expectVaultInSync(userVaultPda);
+        const spendingAccount = await program.account.spendingAccount.fetch(userSpendingPda);
+        expect(spendingAccount.amountSpent.toNumber()).to.equal(0);
+      });
+
+      it("Should refund a vault payment to the payer once the vault is gone", async () => {
+        const receipt = await pay({ vault: {} });
+        const userBalanceBefore = await connection.getBalance(user.publicKey);
+
+        await refund(receipt, PAYMENT_AMOUNT, null);
+
+        expect(await connection.getBalance(user.publicKey)).to.equal(userBalanceBefore + PAYMENT_AMOUNT);
+        const vault = await program.account.vault.fetch(userVaultPda);
+        expect(vault.balance.toNumber()).to.equal(DEPOSIT_AMOUNT - PAYMENT_AMOUNT);
+        await expectVaultInSync(userVaultPda);
+      });
+
+      it("Should only let the paid merchant refund", async () => {
+        const receipt = await pay({ wallet: {} });
+
+        const [otherMerchantPda] = PublicKey.findProgramAddressSync(
+          [Buffer.from("merchant"), otherUser.publicKey.toBuffer()],
+          program.programId
+        );
+        await program.methods
+          .initializeMerchantAccount("Other Merchant", null)
+          .accounts({
+            merchantAccount: otherMerchantPda,
+            authority: otherUser.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([otherUser])
+          .rpc();
+
+        try {
+          await program.methods
+            .refund(new anchor.BN(PAYMENT_AMOUNT))
+            .accounts({
+              merchant: otherUser.publicKey,
+              config: configPda,
+              merchantAccount: otherMerchantPda,
+              receipt,
+              payer: user.publicKey,
+              spendingAccount: userSpendingPda,
+              treasury: treasuryPda,
+              vault: null,
+              systemProgram: SystemProgram.programId,
+            })
+            .signers([otherUser])
+            .rpc();
+
+          expect.fail("Should have thrown has one constraint error");
+        } catch (error) {
+          expect(error.toString()).to.include("ConstraintHasOne");
+        }
+      });
+
+      it("Should keep the merchant open until its receipts are closed", async () => {
+        const receipt = await pay({ wallet: {} });
+        const closeMerchant = () =>
+          program.methods
+            .closeMerchant()
+            .accounts({
+              authority: merchant.publicKey,
+              merchantAccount: merchantPda,
+            })
+            .signers([merchant])
+            .rpc();
+
+        try {
+          await closeMerchant();
+          expect.fail("Should have thrown merchant has open accounts error");
+        } catch (error) {
+          expect(error.toString()).to.include("MerchantHasOpenAccounts");
+        }
+
+        const closeReceipt = (authority: Keypair) =>
+          program.methods
+            .closeReceipt()
+            .accounts({
+              authority: authority.publicKey,
+              merchantAccount: merchantPda,
+              receipt,
+              rentPayer: user.publicKey,
+            })
+            .signers([authority])
+            .rpc();
+
+        // Closing frees the order to be paid again, so the payer cannot close it and
+        // the merchant only once nothing is left to refund.
+        try {
+          await closeReceipt(user);
+          expect.fail("Should have thrown seeds constraint error");
+        } catch (error) {
+          expect(error.toString()).to.include("ConstraintSeeds");
+        }
+
+        try {
+          await closeReceipt(merchant);
+          expect.fail("Should have thrown receipt still retained error");
+        } catch (error) {
+          expect(error.toString()).to.include("ReceiptStillRetained");
+        }
+
+        const { amount, fee } = await program.account.paymentReceipt.fetch(receipt);
+        await refund(receipt, amount.sub(fee).toNumber());
+
+        // The merchant closes the receipt, but its rent goes back to the payer.
+        const rent = await connection.getBalance(receipt);
+        const userBalanceBefore = await connection.getBalance(user.publicKey);
+        await closeReceipt(merchant);
+
+        expect(await connection.getAccountInfo(receipt)).to.be.null;
+        expect(await connection.getBalance(user.publicKey)).to.equal(userBalanceBefore + rent);
+        expect((await program.account.merchantAccount.fetch(merchantPda)).openReceipts.toNumber()).to.equal(0);
+
+        await closeMerchant();
+        expect(await connection.getAccountInfo(merchantPda)).to.be.null;
+      });
+    });
+
+    describe("Payment Mandates", () => {
+      let mandatePda: PublicKey;
+
+      beforeEach(async () => {
+        await program.methods
+          .initializeMerchantAccount("Subscription Merchant", null)
+          .accounts({
+            merchantAccount: merchantPda,
+            authority: merchant.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([merchant])
+          .rpc();
+        await verifyMerchant(merchantPda);
+
+        [mandatePda] = PublicKey.findProgramAddressSync(
+          [Buffer.from("mandate"), user.publicKey.toBuffer(), merchantPda.toBuffer()],
+          program.programId
+        );
+      });
+
+      const createMandate = async (maxPerCharge: number, maxPerPeriod: number) =>
+        program.methods
+          .createMandate({
+            maxPerCharge: new anchor.BN(maxPerCharge),
+            maxPerPeriod: new anchor.BN(maxPerPeriod),
+            periodSeconds: new anchor.BN(30 * 24 * 60 * 60),
+            expiresAt: new anchor.BN(Math.floor(Date.now() / 1000) + 365 * 24 * 60 * 60),
+          })
+          .accounts({
+            authority: user.publicKey,
+            merchantAccount: merchantPda,
+            mandate: mandatePda,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user])
+          .rpc();
+
+      // Only the merchant signs a charge, and it pays for the receipt.
+      const charge = (amount: number, { receipt, nonce } = nextReceipt(merchantPda)) =>
+        program.methods
+          .charge(new anchor.BN(amount), nonce)
+          .accounts({
+            merchant: merchant.publicKey,
+            merchantAccount: merchantPda,
+            payoutWallet: merchant.publicKey,
+            treasury: treasuryPda,
+            user: user.publicKey,
+            config: configPda,
+            priceUpdate: SOL_USD_PRICE_UPDATE,
+            mandate: mandatePda,
+            vault: userVaultPda,
+            yieldAccount: null,
+            spendingAccount: userSpendingPda,
+            receipt,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([merchant])
+          .rpc();
+
+      it("Should let a merchant charge the vault within the mandate", async () => {
+        const chargeUsd = (await debtValueUsd(PAYMENT_AMOUNT)).toNumber();
+        // Room for one charge per period, with some slack for price movement.
+        await createMandate(chargeUsd * 2, Math.floor(chargeUsd * 3 / 2));
+
+        const merchantBalanceBefore = await connection.getBalance(merchant.publicKey);
+        const { receipt, nonce } = nextReceipt(merchantPda);
+        await charge(PAYMENT_AMOUNT, { receipt, nonce });
+
+        const mandate = await program.account.paymentMandate.fetch(mandatePda);
+        expect(mandate.periodSpent.toNumber()).to.equal(chargeUsd);
+        const spendingAccount = await program.account.spendingAccount.fetch(userSpendingPda);
+        expect(spendingAccount.amountSpent.toNumber()).to.equal(chargeUsd);
+        const vault = await program.account.vault.fetch(userVaultPda);
+        expect(vault.balance.toNumber()).to.equal(DEPOSIT_AMOUNT - PAYMENT_AMOUNT);
+        await expectVaultInSync(userVaultPda);
+        // The charge gets a receipt like a vault payment, funded by the merchant.
+        const issued = await program.account.paymentReceipt.fetch(receipt);
+        expect(issued.payer.toString()).to.equal(user.publicKey.toString());
+        expect(issued.rentPayer.toString()).to.equal(merchant.publicKey.toString());
+        expect(issued.source).to.deep.equal({ vault: {} });
+        expect(issued.amount.toNumber()).to.equal(PAYMENT_AMOUNT);
+        expect(issued.amountUsd.toNumber()).to.equal(chargeUsd);
+        expect(issued.nonce.toNumber()).to.equal(nonce.toNumber());
+        expect((await program.account.merchantAccount.fetch(merchantPda)).openReceipts.toNumber()).to.equal(1);
+
+        // The merchant signs (but does not pay the fee), so it receives the charge
+        // less the receipt's rent.
+        const receiptRent = await connection.getBalance(receipt);
+        expect(await connection.getBalance(merchant.publicKey)).to.equal(
+          merchantBalanceBefore + PAYMENT_AMOUNT - receiptRent
+        );
+
+        try {
+          await charge(PAYMENT_AMOUNT);
+          expect.fail("Should have thrown mandate limit exceeded error");
+        } catch (error) {
+          expect(error.toString()).to.include("MandateLimitExceeded");
+        }
+      });
+
+      it("Should stop charges once the user revokes the mandate", async () => {
+        const chargeUsd = (await debtValueUsd(PAYMENT_AMOUNT)).toNumber();
+        await createMandate(chargeUsd * 2, chargeUsd * 4);
+
+        await program.methods
+          .revokeMandate()
+          .accounts({
+            authority: user.publicKey,
+            merchantAccount: merchantPda,
+            mandate: mandatePda,
+          })
+          .signers([user])
+          .rpc();
+
+        expect(await connection.getAccountInfo(mandatePda)).to.be.null;
+
+        try {
+          await charge(PAYMENT_AMOUNT);
+          expect.fail("Should have thrown account not initialized error");
+        } catch (error) {
+          expect(error.toString()).to.include("AccountNotInitialized");
+        }
+      });
+    });
+
+    it("Should fail payment with insufficient spending limit", async () => {
+      // Initialize merchant
+      await program.methods
+        .initializeMerchantAccount("Test Merchant", null)
+        .accounts({
+          merchantAccount: merchantPda,
+          authority: merchant.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchant])
+        .rpc();
+      await verifyMerchant(merchantPda);
+
+      // Try to process payment exceeding limit
+      const { receipt, nonce } = nextReceipt(merchantPda);
+      try {
+        await program.methods
+          .processPayment(new anchor.BN(DEPOSIT_AMOUNT), { wallet: {} }, null, nonce, null) // More than the loan-to-value limit
+          .accounts({
+            authority: user.publicKey,
+            config: configPda,
+            priceUpdate: SOL_USD_PRICE_UPDATE,
+            vault: userVaultPda,
+            yieldAccount: null,
+            spendingAccount: userSpendingPda,
+            merchantAccount: merchantPda,
+            payoutWallet: merchant.publicKey,
+            treasury: treasuryPda,
+            receipt,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user])
+          .rpc();
+        
+        expect.fail("Should have thrown spending limit exceeded error");
+      } catch (error) {
+        expect(error.toString()).to.include("SpendingLimitExceeded");
+      }
+    });
+
+    it("Should pay a merchant in its accepted SPL mint", async () => {
+      // The token borrows the SOL/USD feed and SOL's decimals, so it is priced like SOL.
+      const mint = await createMint(connection, user, user.publicKey, null, 9);
+      await addCollateralAsset(mint);
+
+      const [tokenVaultPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("token_vault"), user.publicKey.toBuffer(), mint.toBuffer()],
+        program.programId
+      );
+      const vaultTokenAccount = getAssociatedTokenAddressSync(mint, userVaultPda, true);
+      const userTokenAccount = await createAccount(connection, user, mint, user.publicKey);
+      await mintTo(connection, user, mint, userTokenAccount, user, PAYMENT_AMOUNT);
+
+      await program.methods
+        .initializeTokenVault()
+        .accounts({
+          authority: user.publicKey,
+          vault: userVaultPda,
+          mint,
+          collateralAsset: collateralAssetPda(mint),
+          tokenVault: tokenVaultPda,
+          vaultTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user])
+        .rpc();
+
+      await program.methods
+        .depositToken(new anchor.BN(PAYMENT_AMOUNT))
+        .accounts({
+          authority: user.publicKey,
+          vault: userVaultPda,
+          mint,
+          tokenVault: tokenVaultPda,
+          vaultTokenAccount,
+          userTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user])
+        .rpc();
+
+      await program.methods
+        .initializeMerchantAccount("Token Merchant", mint)
+        .accounts({
+          merchantAccount: merchantPda,
+          authority: merchant.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchant])
+        .rpc();
+      await verifyMerchant(merchantPda);
+      const merchantTokenAccount = await createAccount(connection, merchant, mint, merchant.publicKey);
+
+      // Token payments pay the merchant fee in the token, into the treasury's token account.
+      const FEE_BPS = 250;
+      await program.methods
+        .setMerchantFee(new anchor.BN(FEE_BPS))
+        .accounts({
+          admin: provider.wallet.publicKey,
+          config: configPda,
+          merchantAccount: merchantPda,
+        })
+        .rpc();
+      const treasuryTokenAccount = (
+        await getOrCreateAssociatedTokenAccount(connection, user, mint, treasuryPda, true)
+      ).address;
+
+      const orderId = "token-order-1";
+      const receipt = orderReceiptPda(merchantPda, orderId);
+      const payOrder = (amount: number) =>
+        program.methods
+          .processTokenPayment(new anchor.BN(amount), orderId, new anchor.BN(0), null)
+          .accounts({
+            authority: user.publicKey,
+            config: configPda,
+            vault: userVaultPda,
+            yieldAccount: null,
+            mint,
+            collateralAsset: collateralAssetPda(mint),
+            priceUpdate: SOL_USD_PRICE_UPDATE,
+            solPriceUpdate: SOL_USD_PRICE_UPDATE,
+            tokenVault: tokenVaultPda,
+            vaultTokenAccount,
+            spendingAccount: userSpendingPda,
+            treasury: treasuryPda,
+            merchantAccount: merchantPda,
+            merchantTokenAccount,
+            treasuryTokenAccount,
+            receipt,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .remainingAccounts(collateralAccounts(mint, tokenVaultPda))
+          .signers([user])
+          .rpc();
+
+      await payOrder(PAYMENT_AMOUNT);
+
+      // Token payments get a receipt keyed by order ID like lamport payments.
+      const issued = await program.account.paymentReceipt.fetch(receipt);
+      expect(issued.mint.toString()).to.equal(mint.toString());
+      expect(issued.amount.toNumber()).to.equal(PAYMENT_AMOUNT);
+      expect(issued.orderId).to.equal(orderId);
+
+      try {
+        await payOrder(1);
+        expect.fail("Should have thrown account already in use error");
+      } catch (error) {
+        expect(error.toString()).to.include("already in use");
+      }
+
+      // Tokens paid are recorded as micro-USD debt at the upper price bound.
+      const spendingAccount = await program.account.spendingAccount.fetch(userSpendingPda);
+      expect(spendingAccount.amountSpent.toNumber()).to.equal(
+        (await debtValueUsd(PAYMENT_AMOUNT)).toNumber()
+      );
+
+      const tokenVault = await program.account.tokenVault.fetch(tokenVaultPda);
+      expect(tokenVault.balance.toNumber()).to.equal(0);
+      const fee = (PAYMENT_AMOUNT * FEE_BPS) / 10_000;
+      const merchantTokens = await getAccount(connection, merchantTokenAccount);
+      expect(Number(merchantTokens.amount)).to.equal(PAYMENT_AMOUNT - fee);
+      expect(Number((await getAccount(connection, treasuryTokenAccount)).amount)).to.equal(fee);
+
+      // Token receipts are refunded in the token, back into the payer's token vault.
+      try {
+        await program.methods
+          .refund(new anchor.BN(PAYMENT_AMOUNT - fee))
+          .accounts({
+            merchant: merchant.publicKey,
+            config: configPda,
+            merchantAccount: merchantPda,
+            receipt,
+            payer: user.publicKey,
+            spendingAccount: userSpendingPda,
+            treasury: treasuryPda,
+            vault: userVaultPda,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([merchant])
+          .rpc();
+        expect.fail("Should have thrown token refund unsupported error");
+      } catch (error) {
+        expect(error.toString()).to.include("TokenRefundUnsupported");
+      }
+
+      await program.methods
+        .refundToken(new anchor.BN(PAYMENT_AMOUNT - fee))
+        .accounts({
+          merchant: merchant.publicKey,
+          config: configPda,
+          merchantAccount: merchantPda,
+          receipt,
+          payer: user.publicKey,
+          spendingAccount: userSpendingPda,
+          treasury: treasuryPda,
+          mint,
+          tokenVault: tokenVaultPda,
+          vault: userVaultPda,
+          vaultTokenAccount,
+          merchantTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([merchant])
+        .rpc();
+
+      expect((await program.account.tokenVault.fetch(tokenVaultPda)).balance.toNumber()).to.equal(PAYMENT_AMOUNT - fee);
+      expect(Number((await getAccount(connection, vaultTokenAccount)).amount)).to.equal(PAYMENT_AMOUNT - fee);
+      // A full refund releases all of the debt.
+      expect((await program.account.spendingAccount.fetch(userSpendingPda)).amountSpent.toNumber()).to.equal(0);
+
+      // Only the admin can take the token fees out.
+      const adminTokenAccount = await createAccount(connection, user, mint, provider.wallet.publicKey);
+      const withdrawTokenFees = (admin: PublicKey) =>
+        program.methods
+          .withdrawTokenFees(new anchor.BN(fee))
+          .accounts({
+            admin,
+            config: configPda,
+            treasury: treasuryPda,
+            mint,
+            treasuryTokenAccount,
+            destination: adminTokenAccount,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          });
+
+      try {
+        await withdrawTokenFees(user.publicKey).signers([user]).rpc();
+        expect.fail("Should have thrown unauthorized error");
+      } catch (error) {
+        expect(error.toString()).to.include("Unauthorized");
+      }
+
+      await withdrawTokenFees(provider.wallet.publicKey).rpc();
+      expect(Number((await getAccount(connection, adminTokenAccount)).amount)).to.equal(fee);
+      expect(Number((await getAccount(connection, treasuryTokenAccount)).amount)).to.equal(0);
+
+      // The merchant only accepts the token, not SOL.
+      const { receipt, nonce } = nextReceipt(merchantPda);
+      try {
+        await program.methods
+          .processPayment(new anchor.BN(PAYMENT_AMOUNT), { wallet: {} }, null, nonce, null)
+          .accounts({
+            authority: user.publicKey,
+            config: configPda,
+            priceUpdate: SOL_USD_PRICE_UPDATE,
+            vault: userVaultPda,
+            yieldAccount: null,
+            spendingAccount: userSpendingPda,
+            merchantAccount: merchantPda,
+            payoutWallet: merchant.publicKey,
+            treasury: treasuryPda,
+            receipt,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user])
+          .rpc();
+
+        expect.fail("Should have thrown asset not accepted error");
+      } catch (error) {
+        expect(error.toString()).to.include("AssetNotAccepted");
+      }
+    });
+  });
+
+  describe("Liquidation Tests", () => {
+    // Fits the limit the 2 SOL deposit gets at the normal price, but leaves the
+    // position under the liquidation threshold at the low one.
+    const LIQUIDATION_SPEND_USD = 140_000_000; // $140.00
+
+    beforeEach(async () => {
+      await program.methods
+        .initialize()
+        .accounts({
+          user: user.publicKey,
+          vault: userVaultPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      await program.methods
+        .deposit(new anchor.BN(DEPOSIT_AMOUNT))
+        .accounts({
+          authority: user.publicKey,
+          vault: userVaultPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      await initializeSpendingAccount();
+
+      await program.methods
+        .updateSpendingLimit()
+        .accounts({
+          authority: user.publicKey,
+          config: configPda,
+          vault: userVaultPda,
+          yieldAccount: null,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
+          spendingAccount: userSpendingPda,
+        })
+        .signers([user])
+        .rpc();
+    });
+
+    const spend = (amount: number) =>
+      program.methods
+        .authorizeSpend(new anchor.BN(amount))
+        .accounts({
+          authority: user.publicKey,
+          config: configPda,
+          vault: userVaultPda,
+          yieldAccount: null,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
+          spendingAccount: userSpendingPda,
+          treasury: treasuryPda,
+        })
+        .signers([user])
+        .rpc();
+
+    // Any keeper can liquidate, and signs alone: the position's accounts are all
+    // derived from `user`.
+    const liquidate = (priceUpdate: PublicKey, keeper: Keypair = otherUser) =>
+      program.methods
+        .liquidate()
+        .accounts({
+          liquidator: keeper.publicKey,
+          user: user.publicKey,
+          config: configPda,
+          vault: userVaultPda,
+          spendingAccount: userSpendingPda,
+          yieldAccount: null,
+          priceUpdate,
+          treasury: treasuryPda,
+          tokenVault: null,
+          mint: null,
+          vaultTokenAccount: null,
+          liquidatorTokenAccount: null,
+          tokenProgram: null,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([keeper])
+        .rpc();
+
+    it("Should refuse to liquidate a healthy position", async () => {
+      await spend(LIQUIDATION_SPEND_USD);
+
+      try {
+        await liquidate(SOL_USD_PRICE_UPDATE);
+        expect.fail("Should have thrown position healthy error");
+      } catch (error) {
+        expect(error.toString()).to.include("PositionHealthy");
+      }
+    });
+
+    it("Should let a keeper liquidate a position after a price drop", async () => {
+      await spend(LIQUIDATION_SPEND_USD);
+
+      const expected = await expectedLiquidation(
+        new anchor.BN(LIQUIDATION_SPEND_USD),
+        DEPOSIT_AMOUNT,
+        SOL_USD_LOW_PRICE_UPDATE
+      );
+      const keeperBalanceBefore = await connection.getBalance(otherUser.publicKey);
+      const treasuryBalanceBefore = await connection.getBalance(treasuryPda);
+
+      const signature = await liquidate(SOL_USD_LOW_PRICE_UPDATE);
+
+      const spendingAccount = await program.account.spendingAccount.fetch(userSpendingPda);
+      expect(spendingAccount.amountSpent.toNumber()).to.equal(
+        LIQUIDATION_SPEND_USD - expected.debtRepaid.toNumber()
+      );
+
+      // The seized SOL comes out of the vault into the treasury, which pays the
+      // bonus on to the keeper and keeps the rest against the debt repaid.
+      const vault = await program.account.vault.fetch(userVaultPda);
+      expect(vault.balance.toNumber()).to.equal(DEPOSIT_AMOUNT - expected.lamportsSeized.toNumber());
+      await expectVaultInSync(userVaultPda);
+      expect(await connection.getBalance(otherUser.publicKey)).to.equal(
+        keeperBalanceBefore + expected.liquidatorBonus.toNumber()
+      );
+      expect(await connection.getBalance(treasuryPda)).to.equal(
+        treasuryBalanceBefore + expected.lamportsSeized.sub(expected.liquidatorBonus).toNumber()
+      );
+
+      const [liquidated] = await emittedEvents(signature);
+      expect(liquidated.name).to.equal("liquidated");
+      expect(liquidated.data.user.toString()).to.equal(user.publicKey.toString());
+      expect(liquidated.data.liquidator.toString()).to.equal(otherUser.publicKey.toString());
+      expect(liquidated.data.debtRepaid.toNumber()).to.equal(expected.debtRepaid.toNumber());
+      expect(liquidated.data.lamportsSeized.toNumber()).to.equal(expected.lamportsSeized.toNumber());
+      expect(liquidated.data.liquidatorBonus.toNumber()).to.equal(expected.liquidatorBonus.toNumber());
+      expect(liquidated.data.mint).to.be.null;
+      expect(liquidated.data.tokensSeized.toNumber()).to.equal(0);
+
+      // Back at the target ratio, the position is healthy again.
+      try {
+        await liquidate(SOL_USD_LOW_PRICE_UPDATE);
+        expect.fail("Should have thrown position healthy error");
+      } catch (error) {
+        expect(error.toString()).to.include("PositionHealthy");
+      }
+    });
+
+    it("Should liquidate the right amount after a payment and a price move", async () => {
+      await program.methods
+        .initializeMerchantAccount("Test Merchant", null)
+        .accounts({
+          merchantAccount: merchantPda,
+          authority: merchant.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchant])
+        .rpc();
+      await verifyMerchant(merchantPda);
+
+      // Paid at the normal price, the debt is the payment at its upper price bound.
+      const payment = 0.93 * LAMPORTS_PER_SOL;
+      const { receipt, nonce } = nextReceipt(merchantPda);
+      await program.methods
+        .processPayment(new anchor.BN(payment), { wallet: {} }, null, nonce, null)
+        .accounts({
+          authority: user.publicKey,
+          config: configPda,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
+          vault: userVaultPda,
+          yieldAccount: null,
+          spendingAccount: userSpendingPda,
+          merchantAccount: merchantPda,
+          payoutWallet: merchant.publicKey,
+          treasury: treasuryPda,
+          receipt,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      const debt = await debtValueUsd(payment);
+      expect((await program.account.spendingAccount.fetch(userSpendingPda)).amountSpent.toNumber()).to.equal(
+        debt.toNumber()
+      );
+
+      // The wallet payment left the vault alone, so after the price move it is the
+      // whole 2 SOL that is weighed against the debt and seized from.
+      const expected = await expectedLiquidation(debt, DEPOSIT_AMOUNT, SOL_USD_LOW_PRICE_UPDATE);
+      expect(expected.debtRepaid.gtn(0)).to.be.true;
+
+      await liquidate(SOL_USD_LOW_PRICE_UPDATE);
+
+      const spendingAccount = await program.account.spendingAccount.fetch(userSpendingPda);
+      expect(spendingAccount.amountSpent.toNumber()).to.equal(debt.sub(expected.debtRepaid).toNumber());
+      const vault = await program.account.vault.fetch(userVaultPda);
+      expect(vault.balance.toNumber()).to.equal(DEPOSIT_AMOUNT - expected.lamportsSeized.toNumber());
+      await expectVaultInSync(userVaultPda);
+    });
+  });
+
+  describe("Integration Tests", () => {
+    it("Should handle complete user flow", async () => {
+      // 1. Initialize all accounts
+      await program.methods
+        .initialize()
+        .accounts({
+          user: user.publicKey,
+          vault: userVaultPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      await program.methods
+        .initializeSpendingAccount()
+        .accounts({
+          authority: user.publicKey,
+          spendingAccount: userSpendingPda,
+          vault: userVaultPda,
+          treasury: treasuryPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+
+      await program.methods
+        .initializeYieldAccount()
+        .accounts({
+          authority: user.publicKey,
+          yieldAccount: userYieldPda,
+          treasury: treasuryPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
 
-      expect(vaultAfter.balance.toNumber()).to.equal(vaultBefore.balance.toNumber() - STAKE_AMOUNT);
-      expect(yieldAccount.stakedAmount.toNumber()).to.equal(STAKE_AMOUNT);
-      expect(treasuryBalanceAfter).to.equal(treasuryBalanceBefore + STAKE_AMOUNT);
-    });
+      await program.methods
+        .initializeMerchantAccount("Integration Test Merchant", null)
+        .accounts({
+          merchantAccount: merchantPda,
+          authority: merchant.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchant])
+        .rpc();
+      await verifyMerchant(merchantPda);
 
-    it("Should unstake funds from treasury", async () => {
-      // Setup: Initialize and stake first
+      // 2. Deposit funds
       await program.methods
-        .initializeTreasury()
+        .deposit(new anchor.BN(DEPOSIT_AMOUNT))
         .accounts({
-          treasury: treasuryPda,
           authority: user.publicKey,
+          vault: userVaultPda,
           systemProgram: SystemProgram.programId,
         })
         .signers([user])
         .rpc();
 
+      // 3. Set up spending limit
       await program.methods
-        .initializeYieldAccount()
+        .updateSpendingLimit()
         .accounts({
           authority: user.publicKey,
-          yieldAccount: userYieldPda,
-          systemProgram: SystemProgram.programId,
+          config: configPda,
+          vault: userVaultPda,
+          yieldAccount: null,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
+          spendingAccount: userSpendingPda,
         })
         .signers([user])
         .rpc();
 
+      // 4. Stake funds for yield
       await program.methods
         .stake(new anchor.BN(STAKE_AMOUNT))
         .accounts({
@@ -582,33 +3708,325 @@ describe("Capstone Project Program Tests", () => {
         .signers([user])
         .rpc();
 
-      // Now unstake
-      const unstakeAmount = STAKE_AMOUNT / 2;
-      const vaultBefore = await program.account.vault.fetch(userVaultPda);
-      
+      // 5. Make a payment
+      const merchantBalanceBefore = await connection.getBalance(merchant.publicKey);
+
+      const { receipt, nonce } = nextReceipt(merchantPda);
       await program.methods
-        .unstake(new anchor.BN(unstakeAmount))
+        .processPayment(new anchor.BN(PAYMENT_AMOUNT), { wallet: {} }, null, nonce, null)
         .accounts({
           authority: user.publicKey,
+          config: configPda,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
           vault: userVaultPda,
-          yieldAccount: userYieldPda,
+          yieldAccount: null,
+          spendingAccount: userSpendingPda,
+          merchantAccount: merchantPda,
+          payoutWallet: merchant.publicKey,
           treasury: treasuryPda,
+          receipt,
+          systemProgram: SystemProgram.programId,
         })
         .signers([user])
         .rpc();
 
-      // Verify unstaking
-      const vaultAfter = await program.account.vault.fetch(userVaultPda);
+      // 6. Verify final state
+      const vaultAccount = await program.account.vault.fetch(userVaultPda);
+      const spendingAccount = await program.account.spendingAccount.fetch(userSpendingPda);
       const yieldAccount = await program.account.yieldAccount.fetch(userYieldPda);
+      const merchantAccount = await program.account.merchantAccount.fetch(merchantPda);
+      const merchantBalanceAfter = await connection.getBalance(merchant.publicKey);
 
-      expect(vaultAfter.balance.toNumber()).to.equal(vaultBefore.balance.toNumber() + unstakeAmount);
-      expect(yieldAccount.stakedAmount.toNumber()).to.equal(STAKE_AMOUNT - unstakeAmount);
+      // Vault should have remaining balance after staking
+      expect(vaultAccount.balance.toNumber()).to.equal(DEPOSIT_AMOUNT - STAKE_AMOUNT);
+      
+      // Spending account should show the purchase, in micro-USD
+      expect(spendingAccount.amountSpent.toNumber()).to.equal(
+        (await debtValueUsd(PAYMENT_AMOUNT)).toNumber()
+      );
+      
+      // Yield account should show staked amount
+      expect(yieldAccount.stakedAmount.toNumber()).to.equal(STAKE_AMOUNT);
+      
+      // Merchant should have received payment
+      expect(merchantBalanceAfter).to.equal(merchantBalanceBefore + PAYMENT_AMOUNT);
+      
+      console.log("Integration test completed successfully!");
+    });
+
+    it("Should handle multiple users independently", async () => {
+      // Derive PDAs for otherUser
+      const [otherUserVaultPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), otherUser.publicKey.toBuffer()],
+        program.programId
+      );
+
+      // Initialize accounts for both users
+      await program.methods
+        .initialize()
+        .accounts({
+          user: user.publicKey,
+          vault: userVaultPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      await program.methods
+        .initialize()
+        .accounts({
+          user: otherUser.publicKey,
+          vault: otherUserVaultPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([otherUser])
+        .rpc();
+
+      // Both users deposit different amounts
+      await program.methods
+        .deposit(new anchor.BN(DEPOSIT_AMOUNT))
+        .accounts({
+          authority: user.publicKey,
+          vault: userVaultPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      await program.methods
+        .deposit(new anchor.BN(DEPOSIT_AMOUNT * 2))
+        .accounts({
+          authority: otherUser.publicKey,
+          vault: otherUserVaultPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([otherUser])
+        .rpc();
+
+      // Verify independent balances
+      const userVault = await program.account.vault.fetch(userVaultPda);
+      const otherUserVault = await program.account.vault.fetch(otherUserVaultPda);
+
+      expect(userVault.balance.toNumber()).to.equal(DEPOSIT_AMOUNT);
+      expect(otherUserVault.balance.toNumber()).to.equal(DEPOSIT_AMOUNT * 2);
+      expect(userVault.authority.toString()).to.equal(user.publicKey.toString());
+      expect(otherUserVault.authority.toString()).to.equal(otherUser.publicKey.toString());
     });
   });
 
-  describe("Merchant and Payment Tests", () => {
+  describe("Protocol Config Tests", () => {
+    afterEach(async () => {
+      // Restore the defaults so later tests see the same risk parameters.
+      await program.methods
+        .updateConfig(defaultConfigParams())
+        .accounts({
+          admin: provider.wallet.publicKey,
+          config: configPda,
+          treasury: treasuryPda,
+        })
+        .rpc();
+    });
+
+    it("Should store the admin and risk parameters", async () => {
+      const config = await program.account.protocolConfig.fetch(configPda);
+      expect(config.admin.toString()).to.equal(provider.wallet.publicKey.toString());
+      expect(config.solUsdFeedId).to.deep.equal(SOL_USD_FEED_ID);
+      expect(config.maximumAge.toNumber()).to.equal(TEST_MAX_PRICE_AGE);
+      expect(config.liquidationThreshold.toNumber()).to.equal(120);
+      expect(config.targetCollateralRatio.toNumber()).to.equal(150);
+      expect(config.loanToValueBps.toNumber()).to.equal(5_000);
+      expect(config.liquidationBonusBps.toNumber()).to.equal(500);
+      expect(config.confidenceMultiplierBps.toNumber()).to.equal(20_000);
+      expect(config.maxConfidenceRatioBps.toNumber()).to.equal(200);
+      expect(config.merchantFeeBps.toNumber()).to.equal(0);
+      expect(config.borrowRateBps.toNumber()).to.equal(0);
+    });
+
+    it("Should only let the upgrade authority initialize the config", async () => {
+      try {
+        await program.methods
+          .initializeConfig(defaultConfigParams())
+          .accounts({
+            admin: otherUser.publicKey,
+            program: program.programId,
+            programData: programDataPda,
+            config: configPda,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([otherUser])
+          .rpc();
+        expect.fail("Should have thrown unauthorized error");
+      } catch (error) {
+        expect(error.toString()).to.include("Unauthorized");
+      }
+    });
+
+    it("Should let the admin update the config", async () => {
+      await program.methods
+        .updateConfig({
+          ...defaultConfigParams(),
+          maximumAge: new anchor.BN(30),
+          liquidationThreshold: new anchor.BN(125),
+          targetCollateralRatio: new anchor.BN(160),
+        })
+        .accounts({
+          admin: provider.wallet.publicKey,
+          config: configPda,
+          treasury: treasuryPda,
+        })
+        .rpc();
+
+      const config = await program.account.protocolConfig.fetch(configPda);
+      expect(config.maximumAge.toNumber()).to.equal(30);
+      expect(config.liquidationThreshold.toNumber()).to.equal(125);
+      expect(config.targetCollateralRatio.toNumber()).to.equal(160);
+    });
+
+    it("Should reject config updates from a non-admin", async () => {
+      try {
+        await program.methods
+          .updateConfig(defaultConfigParams())
+          .accounts({
+            admin: user.publicKey,
+            config: configPda,
+            treasury: treasuryPda,
+          })
+          .signers([user])
+          .rpc();
+
+        expect.fail("Should have thrown has_one error");
+      } catch (error) {
+        expect(error.toString()).to.include("ConstraintHasOne");
+      }
+    });
+
+    it("Should reject a target ratio below the liquidation threshold", async () => {
+      try {
+        await program.methods
+          .updateConfig({
+            ...defaultConfigParams(),
+            liquidationThreshold: new anchor.BN(150),
+            targetCollateralRatio: new anchor.BN(120),
+          })
+          .accounts({
+            admin: provider.wallet.publicKey,
+            config: configPda,
+            treasury: treasuryPda,
+          })
+          .rpc();
+
+        expect.fail("Should have thrown invalid config error");
+      } catch (error) {
+        expect(error.toString()).to.include("InvalidConfig");
+      }
+    });
+
+    it("Should reject a loan-to-value ratio that is immediately liquidatable", async () => {
+      try {
+        await program.methods
+          .updateConfig({
+            ...defaultConfigParams(),
+            loanToValueBps: new anchor.BN(9_000),
+          })
+          .accounts({
+            admin: provider.wallet.publicKey,
+            config: configPda,
+            treasury: treasuryPda,
+          })
+          .rpc();
+
+        expect.fail("Should have thrown invalid config error");
+      } catch (error) {
+        expect(error.toString()).to.include("InvalidConfig");
+      }
+    });
+
+    it("Should reject a confidence band that can push the collateral price to zero", async () => {
+      try {
+        await program.methods
+          .updateConfig({
+            ...defaultConfigParams(),
+            confidenceMultiplierBps: new anchor.BN(30_000),
+            maxConfidenceRatioBps: new anchor.BN(4_000),
+          })
+          .accounts({
+            admin: provider.wallet.publicKey,
+            config: configPda,
+            treasury: treasuryPda,
+          })
+          .rpc();
+
+        expect.fail("Should have thrown invalid config error");
+      } catch (error) {
+        expect(error.toString()).to.include("InvalidConfig");
+      }
+    });
+
+    it("Should reject a merchant fee above the maximum", async () => {
+      try {
+        await program.methods
+          .updateConfig({
+            ...defaultConfigParams(),
+            merchantFeeBps: new anchor.BN(1_001),
+          })
+          .accounts({
+            admin: provider.wallet.publicKey,
+            config: configPda,
+            treasury: treasuryPda,
+          })
+          .rpc();
+
+        expect.fail("Should have thrown invalid config error");
+      } catch (error) {
+        expect(error.toString()).to.include("InvalidConfig");
+      }
+    });
+
+    it("Should reject a borrow rate above the maximum", async () => {
+      try {
+        await program.methods
+          .updateConfig({
+            ...defaultConfigParams(),
+            borrowRateBps: new anchor.BN(10_001),
+          })
+          .accounts({
+            admin: provider.wallet.publicKey,
+            config: configPda,
+            treasury: treasuryPda,
+          })
+          .rpc();
+
+        expect.fail("Should have thrown invalid config error");
+      } catch (error) {
+        expect(error.toString()).to.include("InvalidConfig");
+      }
+    });
+
+    it("Should reject a liquidation bonus that exceeds the target over-collateralisation", async () => {
+      try {
+        await program.methods
+          .updateConfig({
+            ...defaultConfigParams(),
+            liquidationBonusBps: new anchor.BN(5_000),
+          })
+          .accounts({
+            admin: provider.wallet.publicKey,
+            config: configPda,
+            treasury: treasuryPda,
+          })
+          .rpc();
+
+        expect.fail("Should have thrown invalid config error");
+      } catch (error) {
+        expect(error.toString()).to.include("InvalidConfig");
+      }
+    });
+  });
+
+  describe("Account Closing Tests", () => {
+    const accountExists = async (pda: PublicKey) => (await connection.getAccountInfo(pda)) !== null;
+
     beforeEach(async () => {
-      // Setup vault and spending account
       await program.methods
         .initialize()
         .accounts({
@@ -619,6 +4037,55 @@ describe("Capstone Project Program Tests", () => {
         .signers([user])
         .rpc();
 
+      await initializeSpendingAccount();
+    });
+
+    it("Should close an empty vault and return its rent", async () => {
+      const rent = await connection.getBalance(userVaultPda);
+      const userBalanceBefore = await connection.getBalance(user.publicKey);
+
+      await program.methods
+        .closeVault()
+        .accounts({
+          authority: user.publicKey,
+          vault: userVaultPda,
+        })
+        .signers([user])
+        .rpc();
+
+      expect(await accountExists(userVaultPda)).to.be.false;
+      // The user pays the transaction fee out of the returned rent.
+      expect(await connection.getBalance(user.publicKey)).to.be.greaterThan(userBalanceBefore + rent - 10_000);
+    });
+
+    it("Should refuse to close a vault that still holds a balance", async () => {
+      await program.methods
+        .deposit(new anchor.BN(DEPOSIT_AMOUNT))
+        .accounts({
+          authority: user.publicKey,
+          vault: userVaultPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      try {
+        await program.methods
+          .closeVault()
+          .accounts({
+            authority: user.publicKey,
+            vault: userVaultPda,
+          })
+          .signers([user])
+          .rpc();
+
+        expect.fail("Should have thrown account not empty error");
+      } catch (error) {
+        expect(error.toString()).to.include("AccountNotEmpty");
+      }
+    });
+
+    it("Should only close a spending account without outstanding spend", async () => {
       await program.methods
         .deposit(new anchor.BN(DEPOSIT_AMOUNT))
         .accounts({
@@ -630,124 +4097,164 @@ describe("Capstone Project Program Tests", () => {
         .rpc();
 
       await program.methods
-        .initializeSpendingAccount()
+        .updateSpendingLimit()
         .accounts({
           authority: user.publicKey,
+          config: configPda,
+          vault: userVaultPda,
+          yieldAccount: null,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
           spendingAccount: userSpendingPda,
-          systemProgram: SystemProgram.programId,
         })
         .signers([user])
         .rpc();
 
       await program.methods
-        .updateSpendingLimit()
+        .authorizeSpend(new anchor.BN(SPEND_USD))
         .accounts({
           authority: user.publicKey,
+          config: configPda,
           vault: userVaultPda,
+          yieldAccount: null,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
           spendingAccount: userSpendingPda,
+          treasury: treasuryPda,
         })
         .signers([user])
         .rpc();
-    });
 
-    it("Should initialize merchant account", async () => {
-      const merchantName = "Test Merchant";
+      const closeSpendingAccount = () =>
+        program.methods
+          .closeSpendingAccount()
+          .accounts({
+            authority: user.publicKey,
+            spendingAccount: userSpendingPda,
+            vault: userVaultPda,
+            treasury: treasuryPda,
+          })
+          .signers([user])
+          .rpc();
+
+      try {
+        await closeSpendingAccount();
+        expect.fail("Should have thrown account not empty error");
+      } catch (error) {
+        expect(error.toString()).to.include("AccountNotEmpty");
+      }
 
+      // Offer a whole SOL, far more than $10.00 of debt.
       await program.methods
-        .initializeMerchantAccount(merchantName)
+        .repay(new anchor.BN(LAMPORTS_PER_SOL))
         .accounts({
-          merchantAccount: merchantPda,
-          authority: merchant.publicKey,
+          authority: user.publicKey,
+          config: configPda,
+          spendingAccount: userSpendingPda,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
+          treasury: treasuryPda,
           systemProgram: SystemProgram.programId,
         })
-        .signers([merchant])
+        .signers([user])
         .rpc();
 
-      const merchantAccount = await program.account.merchantAccount.fetch(merchantPda);
-      expect(merchantAccount.authority.toString()).to.equal(merchant.publicKey.toString());
-      expect(merchantAccount.name).to.equal(merchantName);
+      await closeSpendingAccount();
+      expect(await accountExists(userSpendingPda)).to.be.false;
     });
 
-    it("Should process payment successfully", async () => {
-      // Initialize merchant
+    it("Should refuse to close a yield account that still holds stake", async () => {
       await program.methods
-        .initializeMerchantAccount("Test Merchant")
+        .deposit(new anchor.BN(DEPOSIT_AMOUNT))
         .accounts({
-          merchantAccount: merchantPda,
-          authority: merchant.publicKey,
+          authority: user.publicKey,
+          vault: userVaultPda,
           systemProgram: SystemProgram.programId,
         })
-        .signers([merchant])
+        .signers([user])
         .rpc();
 
-      // Get balances before payment
-      const userBalanceBefore = await connection.getBalance(user.publicKey);
-      const merchantBalanceBefore = await connection.getBalance(merchant.publicKey);
-
-      // Process payment
       await program.methods
-        .processPayment(new anchor.BN(SPEND_AMOUNT))
+        .initializeYieldAccount()
         .accounts({
           authority: user.publicKey,
-          spendingAccount: userSpendingPda,
-          merchantAccount: merchantPda,
-          merchantAuthority: merchant.publicKey,
+          yieldAccount: userYieldPda,
+          treasury: treasuryPda,
           systemProgram: SystemProgram.programId,
         })
         .signers([user])
         .rpc();
 
-      // Verify payment
-      const spendingAccount = await program.account.spendingAccount.fetch(userSpendingPda);
-      expect(spendingAccount.amountSpent.toNumber()).to.equal(SPEND_AMOUNT);
-
-      const userBalanceAfter = await connection.getBalance(user.publicKey);
-      const merchantBalanceAfter = await connection.getBalance(merchant.publicKey);
-
-      expect(userBalanceAfter).to.be.lessThan(userBalanceBefore);
-      expect(merchantBalanceAfter).to.equal(merchantBalanceBefore + SPEND_AMOUNT);
-    });
-
-    it("Should fail payment with insufficient spending limit", async () => {
-      // Initialize merchant
       await program.methods
-        .initializeMerchantAccount("Test Merchant")
+        .stake(new anchor.BN(STAKE_AMOUNT))
         .accounts({
-          merchantAccount: merchantPda,
-          authority: merchant.publicKey,
+          authority: user.publicKey,
+          vault: userVaultPda,
+          yieldAccount: userYieldPda,
+          treasury: treasuryPda,
           systemProgram: SystemProgram.programId,
         })
-        .signers([merchant])
+        .signers([user])
         .rpc();
 
-      // Try to process payment exceeding limit
       try {
         await program.methods
-          .processPayment(new anchor.BN(DEPOSIT_AMOUNT)) // More than 50% limit
+          .closeYieldAccount()
           .accounts({
             authority: user.publicKey,
-            spendingAccount: userSpendingPda,
-            merchantAccount: merchantPda,
-            merchantAuthority: merchant.publicKey,
-            systemProgram: SystemProgram.programId,
+            yieldAccount: userYieldPda,
+            treasury: treasuryPda,
           })
           .signers([user])
           .rpc();
-        
-        expect.fail("Should have thrown spending limit exceeded error");
+
+        expect.fail("Should have thrown account not empty error");
       } catch (error) {
-        expect(error.toString()).to.include("SpendingLimitExceeded");
+        expect(error.toString()).to.include("AccountNotEmpty");
       }
     });
-  });
 
-  describe("Integration Tests", () => {
-    it("Should handle complete user flow", async () => {
-      // 1. Initialize all accounts
+    it("Should only let the admin close the treasury", async () => {
+      try {
+        await program.methods
+          .closeTreasury()
+          .accounts({
+            admin: user.publicKey,
+            config: configPda,
+            treasury: treasuryPda,
+          })
+          .signers([user])
+          .rpc();
+
+        expect.fail("Should have thrown unauthorized error");
+      } catch (error) {
+        expect(error.toString()).to.include("Unauthorized");
+      }
+    });
+
+    it("Should keep the treasury open while spending or yield accounts exist", async () => {
+      // The user's spending account from `beforeEach` is still open.
+      try {
+        await program.methods
+          .closeTreasury()
+          .accounts({
+            admin: provider.wallet.publicKey,
+            config: configPda,
+            treasury: treasuryPda,
+          })
+          .rpc();
+
+        expect.fail("Should have thrown account not empty error");
+      } catch (error) {
+        expect(error.toString()).to.include("AccountNotEmpty");
+      }
+
+      const treasury = await program.account.treasury.fetch(treasuryPda);
+      expect(treasury.openSpendingAccounts.toNumber()).to.be.greaterThan(0);
+    });
+
+    it("Should unwind every position in one instruction", async () => {
       await program.methods
-        .initialize()
+        .deposit(new anchor.BN(DEPOSIT_AMOUNT))
         .accounts({
-          user: user.publicKey,
+          authority: user.publicKey,
           vault: userVaultPda,
           systemProgram: SystemProgram.programId,
         })
@@ -755,46 +4262,63 @@ describe("Capstone Project Program Tests", () => {
         .rpc();
 
       await program.methods
-        .initializeSpendingAccount()
+        .initializeYieldAccount()
         .accounts({
           authority: user.publicKey,
-          spendingAccount: userSpendingPda,
+          yieldAccount: userYieldPda,
+          treasury: treasuryPda,
           systemProgram: SystemProgram.programId,
         })
         .signers([user])
         .rpc();
 
       await program.methods
-        .initializeTreasury()
+        .stake(new anchor.BN(STAKE_AMOUNT))
         .accounts({
-          treasury: treasuryPda,
           authority: user.publicKey,
+          vault: userVaultPda,
+          yieldAccount: userYieldPda,
+          treasury: treasuryPda,
           systemProgram: SystemProgram.programId,
         })
         .signers([user])
         .rpc();
 
-      await program.methods
-        .initializeYieldAccount()
+      const treasuryBefore = await program.account.treasury.fetch(treasuryPda);
+      const userBalanceBefore = await connection.getBalance(user.publicKey);
+
+      const signature = await program.methods
+        .closeAllPositions()
         .accounts({
           authority: user.publicKey,
+          config: configPda,
+          vault: userVaultPda,
+          spendingAccount: userSpendingPda,
           yieldAccount: userYieldPda,
-          systemProgram: SystemProgram.programId,
+          merchantAccount: null,
+          treasury: treasuryPda,
         })
         .signers([user])
-        .rpc();
+        .rpc({ commitment: "confirmed" });
 
-      await program.methods
-        .initializeMerchantAccount("Integration Test Merchant")
-        .accounts({
-          merchantAccount: merchantPda,
-          authority: merchant.publicKey,
-          systemProgram: SystemProgram.programId,
-        })
-        .signers([merchant])
-        .rpc();
+      expect(await accountExists(userVaultPda)).to.be.false;
+      expect(await accountExists(userSpendingPda)).to.be.false;
+      expect(await accountExists(userYieldPda)).to.be.false;
 
-      // 2. Deposit funds
+      const treasuryAfter = await program.account.treasury.fetch(treasuryPda);
+      expect(treasuryAfter.totalStaked.toNumber()).to.equal(
+        treasuryBefore.totalStaked.toNumber() - STAKE_AMOUNT
+      );
+      // The full deposit comes back: what stayed in the vault plus the stake.
+      expect(await connection.getBalance(user.publicKey)).to.be.greaterThan(userBalanceBefore + DEPOSIT_AMOUNT);
+
+      const [closed] = await emittedEvents(signature);
+      expect(closed.name).to.equal("positionsClosed");
+      expect(closed.data.vaultBalance.toNumber()).to.equal(DEPOSIT_AMOUNT - STAKE_AMOUNT);
+      expect(closed.data.stakedAmount.toNumber()).to.equal(STAKE_AMOUNT);
+    });
+
+    it("Should refuse to unwind positions with outstanding spend", async () => {
       await program.methods
         .deposit(new anchor.BN(DEPOSIT_AMOUNT))
         .accounts({
@@ -805,124 +4329,111 @@ describe("Capstone Project Program Tests", () => {
         .signers([user])
         .rpc();
 
-      // 3. Set up spending limit
       await program.methods
         .updateSpendingLimit()
         .accounts({
           authority: user.publicKey,
+          config: configPda,
           vault: userVaultPda,
+          yieldAccount: null,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
           spendingAccount: userSpendingPda,
         })
         .signers([user])
         .rpc();
 
-      // 4. Stake funds for yield
       await program.methods
-        .stake(new anchor.BN(STAKE_AMOUNT))
+        .authorizeSpend(new anchor.BN(SPEND_USD))
         .accounts({
           authority: user.publicKey,
+          config: configPda,
           vault: userVaultPda,
-          yieldAccount: userYieldPda,
+          yieldAccount: null,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
+          spendingAccount: userSpendingPda,
           treasury: treasuryPda,
-          systemProgram: SystemProgram.programId,
         })
         .signers([user])
         .rpc();
 
-      // 5. Make a payment
-      const merchantBalanceBefore = await connection.getBalance(merchant.publicKey);
+      try {
+        await program.methods
+          .closeAllPositions()
+          .accounts({
+            authority: user.publicKey,
+            config: configPda,
+            vault: userVaultPda,
+            spendingAccount: userSpendingPda,
+            yieldAccount: null,
+            merchantAccount: null,
+            treasury: treasuryPda,
+          })
+          .signers([user])
+          .rpc();
 
-      await program.methods
-        .processPayment(new anchor.BN(SPEND_AMOUNT))
-        .accounts({
-          authority: user.publicKey,
-          spendingAccount: userSpendingPda,
-          merchantAccount: merchantPda,
-          merchantAuthority: merchant.publicKey,
-          systemProgram: SystemProgram.programId,
-        })
-        .signers([user])
-        .rpc();
+        expect.fail("Should have thrown account not empty error");
+      } catch (error) {
+        expect(error.toString()).to.include("AccountNotEmpty");
+      }
 
-      // 6. Verify final state
-      const vaultAccount = await program.account.vault.fetch(userVaultPda);
-      const spendingAccount = await program.account.spendingAccount.fetch(userSpendingPda);
-      const yieldAccount = await program.account.yieldAccount.fetch(userYieldPda);
-      const merchantAccount = await program.account.merchantAccount.fetch(merchantPda);
-      const merchantBalanceAfter = await connection.getBalance(merchant.publicKey);
+      // Leaving the spending account out doesn't skip the debt check.
+      try {
+        await program.methods
+          .closeAllPositions()
+          .accounts({
+            authority: user.publicKey,
+            config: configPda,
+            vault: userVaultPda,
+            spendingAccount: null,
+            yieldAccount: null,
+            merchantAccount: null,
+            treasury: treasuryPda,
+          })
+          .signers([user])
+          .rpc();
 
-      // Vault should have remaining balance after staking
-      expect(vaultAccount.balance.toNumber()).to.equal(DEPOSIT_AMOUNT - STAKE_AMOUNT);
-      
-      // Spending account should show the purchase
-      expect(spendingAccount.amountSpent.toNumber()).to.equal(SPEND_AMOUNT);
-      
-      // Yield account should show staked amount
-      expect(yieldAccount.stakedAmount.toNumber()).to.equal(STAKE_AMOUNT);
-      
-      // Merchant should have received payment
-      expect(merchantBalanceAfter).to.equal(merchantBalanceBefore + SPEND_AMOUNT);
-      
-      console.log("Integration test completed successfully!");
-    });
+        expect.fail("Should have thrown margin accounts required error");
+      } catch (error) {
+        expect(error.toString()).to.include("MarginAccountsRequired");
+      }
 
-    it("Should handle multiple users independently", async () => {
-      // Derive PDAs for otherUser
-      const [otherUserVaultPda] = PublicKey.findProgramAddressSync(
-        [Buffer.from("vault"), otherUser.publicKey.toBuffer()],
-        program.programId
-      );
+      expect(await accountExists(userVaultPda)).to.be.true;
+    });
 
-      // Initialize accounts for both users
+    it("Should unwind positions without a spending account once it is closed", async () => {
       await program.methods
-        .initialize()
+        .closeSpendingAccount()
         .accounts({
-          user: user.publicKey,
+          authority: user.publicKey,
+          spendingAccount: userSpendingPda,
           vault: userVaultPda,
-          systemProgram: SystemProgram.programId,
+          treasury: treasuryPda,
         })
         .signers([user])
         .rpc();
 
-      await program.methods
-        .initialize()
-        .accounts({
-          user: otherUser.publicKey,
-          vault: otherUserVaultPda,
-          systemProgram: SystemProgram.programId,
-        })
-        .signers([otherUser])
-        .rpc();
+      const treasuryBefore = await program.account.treasury.fetch(treasuryPda);
 
-      // Both users deposit different amounts
       await program.methods
-        .deposit(new anchor.BN(DEPOSIT_AMOUNT))
+        .closeAllPositions()
         .accounts({
           authority: user.publicKey,
+          config: configPda,
           vault: userVaultPda,
-          systemProgram: SystemProgram.programId,
+          spendingAccount: null,
+          yieldAccount: null,
+          merchantAccount: null,
+          treasury: treasuryPda,
         })
         .signers([user])
         .rpc();
 
-      await program.methods
-        .deposit(new anchor.BN(DEPOSIT_AMOUNT * 2))
-        .accounts({
-          authority: otherUser.publicKey,
-          vault: otherUserVaultPda,
-          systemProgram: SystemProgram.programId,
-        })
-        .signers([otherUser])
-        .rpc();
-
-      // Verify independent balances
-      const userVault = await program.account.vault.fetch(userVaultPda);
-      const otherUserVault = await program.account.vault.fetch(otherUserVaultPda);
+      expect(await accountExists(userVaultPda)).to.be.false;
 
-      expect(userVault.balance.toNumber()).to.equal(DEPOSIT_AMOUNT);
-      expect(otherUserVault.balance.toNumber()).to.equal(DEPOSIT_AMOUNT * 2);
-      expect(userVault.authority.toString()).to.equal(user.publicKey.toString());
-      expect(otherUserVault.authority.toString()).to.equal(otherUser.publicKey.toString());
+      const treasuryAfter = await program.account.treasury.fetch(treasuryPda);
+      expect(treasuryAfter.openSpendingAccounts.toNumber()).to.equal(
+        treasuryBefore.openSpendingAccounts.toNumber()
+      );
     });
   });
 
@@ -1004,6 +4515,8 @@ describe("Capstone Project Program Tests", () => {
         .signers([user])
         .rpc();
 
+      await initializeSpendingAccount();
+
       // Test withdrawal from empty vault (should fail)
       try {
         await program.methods
@@ -1011,6 +4524,11 @@ describe("Capstone Project Program Tests", () => {
           .accounts({
             vault: userVaultPda,
             authority: user.publicKey,
+            config: configPda,
+            yieldAccount: null,
+            spendingAccount: userSpendingPda,
+            treasury: treasuryPda,
+            priceUpdate: SOL_USD_PRICE_UPDATE,
             systemProgram: SystemProgram.programId,
           })
           .signers([user])
@@ -1040,6 +4558,8 @@ describe("Capstone Project Program Tests", () => {
         .accounts({
           authority: user.publicKey,
           spendingAccount: userSpendingPda,
+          vault: userVaultPda,
+          treasury: treasuryPda,
           systemProgram: SystemProgram.programId,
         })
         .signers([user])
@@ -1049,7 +4569,10 @@ describe("Capstone Project Program Tests", () => {
         .updateSpendingLimit()
         .accounts({
           authority: user.publicKey,
+          config: configPda,
           vault: userVaultPda,
+          yieldAccount: null,
+          priceUpdate: SOL_USD_PRICE_UPDATE,
           spendingAccount: userSpendingPda,
         })
         .signers([user])
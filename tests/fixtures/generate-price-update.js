@@ -0,0 +1,86 @@
+// Writes the Pyth SOL/USD PriceUpdateV2 accounts the test validator loads at
+// genesis (see Anchor.toml) into `generated/`, which is not committed:
+// `sol-usd-price-update.json` at the sponsored SOL/USD address, and
+// `sol-usd-price-update-low.json`, the same feed at a lower price under a second
+// address, which tests use to move the price. The publish time defaults to now,
+// so run this before `anchor test`; `yarn test` does both.
+//
+//   node tests/fixtures/generate-price-update.js [publish_time] [price] [low_price]
+//
+// Prices are in units of 10^-8 USD, e.g. 15000000000 for $150.00.
+const crypto = require("crypto");
+const fs = require("fs");
+const path = require("path");
+
+const ADDRESS = "7UVimffxr9ow1uXYxsr4LHAcV58mLzhmwaeKvJ1pjLiE";
+// An arbitrary address, only used by the tests.
+const LOW_ADDRESS = "H3oobe2SfVcLjBSr1p8cNMZZCF2YSgoWkTSE7ZmxnPmE";
+const PYTH_RECEIVER = "rec5EKMGg6MxZYaMdyBfgwp4d5rB9T1VQH5pJv5LtFJ";
+const SOL_USD_FEED_ID = "ef0d8b6fda2ceba41da15d4095d1da392a0d2f8ed0c6c7bc0f4cfac8c280b56d";
+const EXPONENT = -8;
+// PriceUpdateV2::LEN. A fully verified update leaves the last byte unused.
+const SPACE = 134;
+
+const publishTime = BigInt(process.argv[2] ?? Math.floor(Date.now() / 1000));
+const price = BigInt(process.argv[3] ?? 15_000_000_000);
+const lowPrice = BigInt(process.argv[4] ?? 8_000_000_000);
+
+const int64 = (value) => {
+  const bytes = Buffer.alloc(8);
+  bytes.writeBigInt64LE(value);
+  return bytes;
+};
+
+const priceUpdate = (address, price) => {
+  // A confidence of 0.05% of the price, well inside `max_confidence_ratio_bps`.
+  const conf = price / 2_000n;
+
+  const data = Buffer.alloc(SPACE);
+  let offset = 0;
+  const write = (bytes) => {
+    bytes.copy(data, offset);
+    offset += bytes.length;
+  };
+
+  write(crypto.createHash("sha256").update("account:PriceUpdateV2").digest().subarray(0, 8));
+  // Write authority: unused by the program.
+  write(Buffer.alloc(32));
+  // VerificationLevel::Full
+  write(Buffer.from([1]));
+  // PriceFeedMessage
+  write(Buffer.from(SOL_USD_FEED_ID, "hex"));
+  write(int64(price));
+  write(int64(conf));
+  const exponent = Buffer.alloc(4);
+  exponent.writeInt32LE(EXPONENT);
+  write(exponent);
+  write(int64(publishTime));
+  write(int64(publishTime - 1n));
+  // EMA price and confidence.
+  write(int64(price));
+  write(int64(conf));
+  // Posted slot.
+  write(int64(0n));
+
+  return {
+    pubkey: address,
+    account: {
+      // Rent-exempt minimum for SPACE bytes.
+      lamports: (SPACE + 128) * 6960,
+      data: [data.toString("base64"), "base64"],
+      owner: PYTH_RECEIVER,
+      executable: false,
+      rentEpoch: 0,
+      space: SPACE,
+    },
+  };
+};
+
+const dir = path.join(__dirname, "generated");
+fs.mkdirSync(dir, { recursive: true });
+for (const [file, address, value] of [
+  ["sol-usd-price-update.json", ADDRESS, price],
+  ["sol-usd-price-update-low.json", LOW_ADDRESS, lowPrice],
+]) {
+  fs.writeFileSync(path.join(dir, file), JSON.stringify(priceUpdate(address, value), null, 2) + "\n");
+}